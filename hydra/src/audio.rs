@@ -1,9 +1,9 @@
 use std::{collections::VecDeque, io::Read, sync::{Arc, Mutex}, time::Duration};
 
 use cpal::{Device, Host, OutputCallbackInfo, SampleRate, SizedSample, Stream, StreamConfig, StreamError, traits::{DeviceTrait, HostTrait, StreamTrait}};
-use ringbuf::{HeapProd, HeapRb, traits::{Consumer, Observer, Split}, wrap::Wrap};
+use ringbuf::{HeapProd, HeapRb, traits::{Consumer, Observer, Producer, Split}, wrap::Wrap};
 
-use crate::common::audio::sine_callback;
+use crate::common::{audio::sine_callback, output::AudioOutput};
 
 pub struct Audio {
     host: Host,
@@ -29,6 +29,10 @@ impl Audio {
         self.config.channels
     }
 
+    pub fn open_stream(&mut self) -> AudioStream {
+        AudioStream { sample_rate: self.get_sample_rate(), producer: self.get_producer() }
+    }
+
     pub fn get_producer(&mut self) -> HeapProd<f32> {
         let (producer, mut consumer) = HeapRb::<f32>::new(self.config.sample_rate as usize / 10).split();
         let stream = self.output.build_output_stream(&self.config, move |samples: &mut [f32], _| {consumer.pop_slice(samples);}, Self::error_callback, None).unwrap();
@@ -40,4 +44,20 @@ impl Audio {
     fn error_callback(err: StreamError) {
         panic!("Audio streaming error: {}", err)
     }
+}
+
+/// The producing end of the playback stream, for use as an emulator's audio output.
+pub struct AudioStream {
+    sample_rate: u32,
+    producer: HeapProd<f32>,
+}
+
+impl AudioOutput for AudioStream {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, samples: &[f32]) {
+        self.producer.push_slice(samples);
+    }
 }
\ No newline at end of file
@@ -1,6 +1,10 @@
+#[cfg(feature = "frontend")]
 pub mod audio;
 pub mod bit;
 pub mod emulator;
 pub mod errors;
+pub mod output;
+pub mod rewind;
+pub mod savestate;
 pub mod timing;
 pub mod util;
\ No newline at end of file
@@ -2,6 +2,8 @@ use std::ops::{Deref, DerefMut};
 
 use funty::{Integral, Unsigned};
 
+use crate::common::{errors::HydraIOError, savestate::{SaveState, StateReader, StateWriter}};
+
 #[macro_export]
 macro_rules! bitmask_with_width {
     ($hi:literal..=$lo:literal) => {
@@ -214,4 +216,15 @@ impl<T: BitVec, const MASK_VALUE: bool> DerefMut for MaskedBitVec<T, MASK_VALUE>
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.inner
     }
-}
\ No newline at end of file
+}
+
+/// Only the value is saved, since the masks are fixed by the register's hardware.
+impl<T: BitVec + SaveState, const MASK_VALUE: bool> SaveState for MaskedBitVec<T, MASK_VALUE> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.inner.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.inner.load_state(state)
+    }
+}
@@ -1,18 +1,43 @@
-use std::{
-    ffi::OsStr,
-    path::Path,
-    sync::{Arc, RwLock, mpsc::Sender},
-};
+use std::{path::{Path, PathBuf}, sync::mpsc::Sender, thread::JoinHandle};
+#[cfg(feature = "frontend")]
+use std::ffi::OsStr;
 
-use winit::{event::KeyEvent, window::Window};
+#[cfg(feature = "frontend")]
+use winit::event::KeyEvent;
 
-use crate::{common::errors::HydraIOError, config::Config, gameboy, graphics::Graphics, window::HydraApp};
+use crate::gameboy;
+#[cfg(feature = "frontend")]
+use crate::{common::errors::HydraIOError, window::HydraApp};
 
 pub trait Emulator {
     fn main_thread(self);
 }
 
-pub fn init_from_file(path: &Path, app: &HydraApp) -> Result<Sender<EmuMessage>, HydraIOError> {
+/// A handle to an emulator running on its own thread.
+pub struct EmuHandle {
+    channel: Sender<EmuMessage>,
+    thread: JoinHandle<()>,
+}
+
+impl EmuHandle {
+    pub fn new(channel: Sender<EmuMessage>, thread: JoinHandle<()>) -> Self {
+        EmuHandle { channel, thread }
+    }
+
+    pub fn send(&self, message: EmuMessage) {
+        // The emulator may have already exited, in which case there's nothing left to notify
+        let _ = self.channel.send(message);
+    }
+
+    /// Stops the emulator, blocking until it has finished shutting down (e.g. writing save files).
+    pub fn stop(self) {
+        self.send(EmuMessage::Stop);
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(feature = "frontend")]
+pub fn init_from_file(path: &Path, app: &HydraApp) -> Result<EmuHandle, HydraIOError> {
     match path.extension().and_then(OsStr::to_str) {
         Some("gb") => gameboy::GameBoy::new(path, gameboy::Model::GameBoy(app.get_config().gb.default_models.dmg), app),
         Some("gbc") => gameboy::GameBoy::new(path, gameboy::Model::GameBoyColor(app.get_config().gb.default_models.cgb), app),
@@ -23,6 +48,22 @@ pub fn init_from_file(path: &Path, app: &HydraApp) -> Result<Sender<EmuMessage>,
 pub enum EmuMessage {
     Start,
     Stop,
+    #[cfg(feature = "frontend")]
     KeyboardInput(KeyEvent),
     HotSwap(&'static Path),
+    SaveState(u8),
+    LoadState(u8),
+    RecordMovie { from_power_on: bool },
+    PlayMovie(PathBuf),
+    StopMovie,
+    /// A line typed into the debug console.
+    DebugCommand(String),
+    /// Starts redrawing a debug view after every frame.
+    OpenViewer(gameboy::Viewer),
+    CloseViewer(gameboy::Viewer),
+    SetTilePalette(gameboy::TilePalette),
+    /// The colors DMG shades are displayed with were changed from the menu.
+    SetShadePalette(gameboy::ShadePalette),
+    /// The mouse wheel was scrolled over the given pixel of a debug view.
+    ScrollViewer { viewer: gameboy::Viewer, x: u32, y: u32, delta: i32 },
 }
@@ -5,13 +5,22 @@ pub enum HydraIOError {
     InvalidEmulator(&'static str, Option<String>),
     InvalidInstruction(u64, usize),
     MalformedROM(&'static str),
+    InvalidHeader(Vec<&'static str>),
+    MalformedBootROM(&'static str),
+    MalformedSaveState(&'static str),
+    IncompatibleSaveState(&'static str),
+    MalformedMovie(&'static str),
+    IncompatibleMovie(&'static str),
+    InvalidDebugCommand(&'static str),
     OpenBusAccess,
 
     NoCamera,
+    NoGraphicsAdapter,
 
     IOError(std::io::Error),
     DeserializationError(toml::de::Error),
     SerializationError(toml::ser::Error),
+    #[cfg(feature = "frontend")]
     CameraError(nokhwa::NokhwaError),
 }
 
@@ -22,13 +31,22 @@ impl fmt::Display for HydraIOError {
             HydraIOError::InvalidEmulator(emulator, None) => write!(f, "{} does not support extensionless ROM files", emulator),
             HydraIOError::InvalidInstruction(value, address) => write!(f, "Attempted to execute invalid instruction {} at address {}", value, address),
             HydraIOError::MalformedROM(details) => write!(f, "Malformed ROM file: {}", details),
+            HydraIOError::InvalidHeader(problems) => write!(f, "Cartridge header is invalid, so the ROM is likely a corrupt dump: {}", problems.join("; ")),
+            HydraIOError::MalformedBootROM(details) => write!(f, "Malformed boot ROM: {}", details),
+            HydraIOError::MalformedSaveState(details) => write!(f, "Malformed save state: {}", details),
+            HydraIOError::IncompatibleSaveState(details) => write!(f, "Save state is incompatible: {}", details),
+            HydraIOError::MalformedMovie(details) => write!(f, "Malformed movie file: {}", details),
+            HydraIOError::IncompatibleMovie(details) => write!(f, "Movie is incompatible: {}", details),
+            HydraIOError::InvalidDebugCommand(details) => write!(f, "Invalid debugger command: {}", details),
             HydraIOError::OpenBusAccess => write!(f, "Attempted to access an unmapped memory block"),
 
             HydraIOError::NoCamera => write!(f, "Camera doesn't exist, or access to the camera was denied"),
+            HydraIOError::NoGraphicsAdapter => write!(f, "No compatible graphics adapter is available"),
 
             HydraIOError::IOError(error) => write!(f, "{}", error),
             HydraIOError::DeserializationError(error) => write!(f, "{}", error),
             HydraIOError::SerializationError(error) => write!(f, "{}", error),
+            #[cfg(feature = "frontend")]
             HydraIOError::CameraError(error) => write!(f, "{}", error),
         }
     }
@@ -54,6 +72,7 @@ impl From<toml::ser::Error> for HydraIOError {
     }
 }
 
+#[cfg(feature = "frontend")]
 impl From<nokhwa::NokhwaError> for HydraIOError {
     fn from(err: nokhwa::NokhwaError) -> Self {
         HydraIOError::CameraError(err)
@@ -0,0 +1,14 @@
+/// A destination for the frames produced by an emulator, such as a window's viewport.
+pub trait VideoOutput {
+    /// Presents a completed frame, given as tightly packed RGBA8 pixels.
+    fn present(&mut self, frame: &[u8]);
+}
+
+/// A destination for the audio produced by an emulator, such as a playback stream.
+pub trait AudioOutput {
+    /// Returns the number of stereo frames per second this output expects.
+    fn sample_rate(&self) -> u32;
+
+    /// Queues interleaved left/right samples for playback.
+    fn push_samples(&mut self, samples: &[f32]);
+}
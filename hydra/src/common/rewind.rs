@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+
+/// A rolling history of save states, used to step emulation backwards.
+///
+/// Only the newest state is kept whole. Older states are stored as compressed deltas against the state after them,
+/// so the oldest ones can be dropped whenever the buffer grows past its memory cap.
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    memory_used: usize,
+    memory_cap: usize,
+
+    frames_per_snapshot: u32,
+    frames_until_snapshot: u32,
+}
+
+impl RewindBuffer {
+    /// Creates a buffer taking a snapshot every `frames_per_snapshot` frames, and holding at most `memory_cap` bytes.
+    /// A cap of 0 disables rewinding entirely.
+    pub fn new(frames_per_snapshot: u32, memory_cap: usize) -> Self {
+        let frames_per_snapshot = frames_per_snapshot.max(1);
+        RewindBuffer {
+            latest: None,
+            deltas: VecDeque::new(),
+            memory_used: 0,
+            memory_cap,
+
+            frames_per_snapshot,
+            frames_until_snapshot: frames_per_snapshot,
+        }
+    }
+
+    /// Creates a buffer which never takes snapshots, until it's replaced by one with a memory cap.
+    pub fn disabled() -> Self {
+        Self::new(1, 0)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.memory_cap > 0
+    }
+
+    /// Counts down to the next snapshot. Should be called once per emulated frame.
+    pub fn frame(&mut self) {
+        self.frames_until_snapshot = self.frames_until_snapshot.saturating_sub(1);
+    }
+
+    /// Returns whether enough frames have passed since the last snapshot for another to be taken.
+    pub fn is_snapshot_due(&self) -> bool {
+        self.is_enabled() && self.frames_until_snapshot == 0
+    }
+
+    /// Records a new snapshot as the newest state, evicting the oldest ones if the memory cap is exceeded.
+    pub fn push(&mut self, state: Vec<u8>) {
+        self.frames_until_snapshot = self.frames_per_snapshot;
+        if let Some(previous) = self.latest.take() {
+            let delta = encode_delta(&state, &previous);
+            self.memory_used += delta.len();
+            self.memory_used -= previous.len();
+            self.deltas.push_back(delta);
+        }
+        self.memory_used += state.len();
+        self.latest = Some(state);
+
+        while self.memory_used > self.memory_cap && let Some(oldest) = self.deltas.pop_front() {
+            self.memory_used -= oldest.len();
+        }
+        if self.memory_used > self.memory_cap {
+            // Not even a single state fits
+            self.clear();
+        }
+    }
+
+    /// Removes and returns the newest snapshot, making the one before it the newest.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.latest.take()?;
+        self.memory_used -= state.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            let previous = decode_delta(&state, &delta);
+            self.memory_used -= delta.len();
+            self.memory_used += previous.len();
+            self.latest = Some(previous);
+        }
+        self.frames_until_snapshot = self.frames_per_snapshot;
+        Some(state)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.memory_used = 0;
+        self.frames_until_snapshot = self.frames_per_snapshot;
+    }
+}
+
+// Deltas are the XOR of two states, with runs of zeroes (i.e. unchanged bytes) collapsed.
+// They're laid out as the length of the target state, followed by pairs of
+// (unchanged byte count, changed byte count, changed bytes), with every count stored as a LEB128 varint.
+
+/// Runs of unchanged bytes shorter than this are kept inline, since they'd cost about as much as a new pair.
+const MIN_ZERO_RUN: usize = 4;
+
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let len = base.len().max(target.len());
+    let xor: Vec<u8> = (0..len).map(|i| base.get(i).unwrap_or(&0) ^ target.get(i).unwrap_or(&0)).collect();
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, target.len());
+    let mut i = 0;
+    while i < len {
+        let zeros = xor[i..].iter().take_while(|&&byte| byte == 0).count();
+        i += zeros;
+        if i == len {
+            break;
+        }
+
+        // Extend the changed bytes until the next run of zeroes worth collapsing
+        let start = i;
+        while i < len && !xor[i..].iter().take(MIN_ZERO_RUN).all(|&byte| byte == 0) {
+            i += 1;
+        }
+        write_varint(&mut delta, zeros);
+        write_varint(&mut delta, i - start);
+        delta.extend_from_slice(&xor[start..i]);
+    }
+    delta
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut delta = delta;
+    let target_len = read_varint(&mut delta);
+    let mut target = base.to_vec();
+    target.resize(base.len().max(target_len), 0);
+
+    let mut i = 0;
+    while !delta.is_empty() {
+        i += read_varint(&mut delta);
+        let changed = read_varint(&mut delta);
+        let (bytes, rest) = delta.split_at(changed);
+        target[i..i + changed].iter_mut().zip(bytes).for_each(|(byte, xor)| *byte ^= xor);
+        i += changed;
+        delta = rest;
+    }
+    target.truncate(target_len);
+    target
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[0];
+        *data = &data[1..];
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = encode_delta(base, target);
+        assert_eq!(decode_delta(base, &delta), target);
+        delta
+    }
+
+    /// A pseudo-random state, so neighbouring bytes rarely match.
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut x = seed.wrapping_mul(2654435761) | 1;
+        (0..len).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        }).collect()
+    }
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, 0x7F, 0x80, 300, 0x3FFF, 0x4000, 1 << 40, usize::MAX] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            assert_eq!(out.len(), (usize::BITS - value.leading_zeros()).max(1).div_ceil(7) as usize, "{} took the wrong number of bytes", value);
+            let mut data = out.as_slice();
+            assert_eq!(read_varint(&mut data), value);
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn identical_states() {
+        let state = noise(1000, 1);
+        // Only the length is stored
+        assert_eq!(round_trip(&state, &state), [0xE8, 0x07]);
+        assert_eq!(round_trip(&[], &[]), [0x00]);
+    }
+
+    #[test]
+    fn entirely_different_states() {
+        let base = noise(1000, 1);
+        let target: Vec<u8> = base.iter().map(|byte| !byte).collect();
+        let delta = round_trip(&base, &target);
+        // Length, no unchanged bytes, then all 1000 changed bytes in a single run
+        assert_eq!(&delta[..5], [0xE8, 0x07, 0x00, 0xE8, 0x07]);
+        assert_eq!(delta.len(), 5 + 1000);
+    }
+
+    #[test]
+    fn long_runs() {
+        let base = noise(100_000, 2);
+        let mut target = base.clone();
+        target[50_000..50_300].iter_mut().for_each(|byte| *byte = !*byte);
+        target[99_999] ^= 1;
+        let delta = round_trip(&base, &target);
+        assert!(delta.len() < 320, "delta took {} bytes", delta.len());
+    }
+
+    #[test]
+    fn short_unchanged_runs_stay_inline() {
+        let base = vec![0; 16];
+        let mut target = base.clone();
+        target[2] = 1;
+        target[5] = 1; // 2 unchanged bytes between, kept inline
+        target[12] = 1; // 6 unchanged bytes between, collapsed
+        let delta = round_trip(&base, &target);
+        assert_eq!(delta, [16, 2, 4, 1, 0, 0, 1, 6, 1, 1]);
+    }
+
+    #[test]
+    fn states_changing_size() {
+        let short = noise(300, 3);
+        let long = noise(5000, 4);
+        round_trip(&short, &long);
+        round_trip(&long, &short);
+        round_trip(&[], &long);
+        round_trip(&long, &[]);
+    }
+
+    #[test]
+    fn pops_in_reverse_order() {
+        let states: Vec<_> = (0..20).map(|seed| {
+            let mut state = noise(4096, 5);
+            state[seed as usize * 100..seed as usize * 100 + 50].copy_from_slice(&noise(50, seed));
+            state
+        }).collect();
+        let mut buffer = RewindBuffer::new(1, usize::MAX);
+        states.iter().for_each(|state| buffer.push(state.clone()));
+        for state in states.iter().rev() {
+            assert_eq!(buffer.pop().as_ref(), Some(state));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.memory_used, 0);
+    }
+
+    #[test]
+    fn evicts_oldest_states_past_cap() {
+        let states: Vec<_> = (0..50).map(|seed| noise(1000, seed)).collect();
+        let mut buffer = RewindBuffer::new(1, 10_000);
+        for state in &states {
+            buffer.push(state.clone());
+            assert!(buffer.memory_used <= 10_000);
+        }
+        // Every state is entirely different from the last, so each delta costs about as much as a whole state
+        let mut popped = Vec::new();
+        while let Some(state) = buffer.pop() {
+            popped.push(state);
+        }
+        assert!((5..10).contains(&popped.len()), "kept {} states", popped.len());
+        assert!(popped.iter().eq(states.iter().rev().take(popped.len())));
+    }
+
+    #[test]
+    fn state_larger_than_cap() {
+        let mut buffer = RewindBuffer::new(1, 100);
+        buffer.push(vec![0; 50]);
+        buffer.push(vec![0; 200]);
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.memory_used, 0);
+    }
+
+    #[test]
+    fn snapshot_timing() {
+        let mut buffer = RewindBuffer::new(3, 1000);
+        for _ in 0..2 {
+            buffer.frame();
+            assert!(!buffer.is_snapshot_due());
+        }
+        buffer.frame();
+        assert!(buffer.is_snapshot_due());
+        buffer.push(vec![1]);
+        assert!(!buffer.is_snapshot_due());
+
+        let mut disabled = RewindBuffer::disabled();
+        (0..10).for_each(|_| disabled.frame());
+        assert!(!disabled.is_snapshot_due());
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::VecDeque;
+
+use crate::common::errors::HydraIOError;
+
+/// A component whose state can be captured in, and restored from, a save state.
+///
+/// Fields are written as raw little-endian values with no tags, so `load_state` must read them back in exactly the order `save_state` wrote them.
+/// Anything that changes this order should also bump the owning emulator's save state version.
+pub trait SaveState {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError>;
+}
+
+/// Accumulates the bytes of a save state.
+pub struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter(Vec::new())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Consumes the bytes of a save state, in the order they were written.
+pub struct StateReader<'a>(&'a [u8]);
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader(data)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], HydraIOError> {
+        if len > self.0.len() {
+            return Err(HydraIOError::MalformedSaveState("Unexpected end of file"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], HydraIOError> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+
+    /// Returns whether every byte of the save state has been read.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+macro_rules! impl_save_state_le {
+    ($($ty:ty),*) => {$(
+        impl SaveState for $ty {
+            fn save_state(&self, state: &mut StateWriter) {
+                state.write_bytes(&self.to_le_bytes());
+            }
+
+            fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+                *self = <$ty>::from_le_bytes(state.read_array()?);
+                Ok(())
+            }
+        }
+    )*};
+}
+
+impl_save_state_le!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl SaveState for usize {
+    fn save_state(&self, state: &mut StateWriter) {
+        (*self as u64).save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let mut value = 0u64;
+        value.load_state(state)?;
+        *self = usize::try_from(value).map_err(|_| HydraIOError::MalformedSaveState("Value out of range"))?;
+        Ok(())
+    }
+}
+
+impl SaveState for bool {
+    fn save_state(&self, state: &mut StateWriter) {
+        (*self as u8).save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        *self = match state.read_array::<1>()? {
+            [0] => false,
+            [1] => true,
+            _ => return Err(HydraIOError::MalformedSaveState("Invalid boolean")),
+        };
+        Ok(())
+    }
+}
+
+impl<T: SaveState, const N: usize> SaveState for [T; N] {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.iter().for_each(|elem| elem.save_state(state));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.iter_mut().try_for_each(|elem| elem.load_state(state))
+    }
+}
+
+/// Slices are prefixed with their length, which must match when loading, since their size is fixed by the hardware being emulated.
+impl<T: SaveState> SaveState for [T] {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.len().save_state(state);
+        self.iter().for_each(|elem| elem.save_state(state));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let mut len = 0usize;
+        len.load_state(state)?;
+        if len != self.len() {
+            return Err(HydraIOError::MalformedSaveState("Memory size doesn't match the running system"));
+        }
+        self.iter_mut().try_for_each(|elem| elem.load_state(state))
+    }
+}
+
+impl<T: SaveState + ?Sized> SaveState for Box<T> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.as_ref().save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.as_mut().load_state(state)
+    }
+}
+
+impl<T: SaveState + Default> SaveState for Vec<T> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.len().save_state(state);
+        self.iter().for_each(|elem| elem.save_state(state));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let mut len = 0usize;
+        len.load_state(state)?;
+        if len > state.0.len() {
+            return Err(HydraIOError::MalformedSaveState("Unexpected end of file"));
+        }
+        self.clear();
+        self.resize_with(len, T::default);
+        self.iter_mut().try_for_each(|elem| elem.load_state(state))
+    }
+}
+
+impl<T: SaveState + Default> SaveState for VecDeque<T> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.len().save_state(state);
+        self.iter().for_each(|elem| elem.save_state(state));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let mut len = 0usize;
+        len.load_state(state)?;
+        if len > state.0.len() {
+            return Err(HydraIOError::MalformedSaveState("Unexpected end of file"));
+        }
+        self.clear();
+        self.resize_with(len, T::default);
+        self.iter_mut().try_for_each(|elem| elem.load_state(state))
+    }
+}
+
+impl<T: SaveState + Default> SaveState for Option<T> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.is_some().save_state(state);
+        if let Some(inner) = self {
+            inner.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let mut is_some = false;
+        is_some.load_state(state)?;
+        match is_some {
+            true => self.get_or_insert_with(T::default).load_state(state),
+            false => {
+                *self = None;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Implements `SaveState` for a struct by saving the listed fields, in order.
+/// Fields that are fixed for the lifetime of the emulator (e.g. ROM, model or host devices) should be left out.
+#[macro_export]
+macro_rules! save_state_fields {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::common::savestate::SaveState for $type {
+            fn save_state(&self, state: &mut $crate::common::savestate::StateWriter) {
+                $($crate::common::savestate::SaveState::save_state(&self.$field, state);)*
+            }
+
+            fn load_state(&mut self, state: &mut $crate::common::savestate::StateReader) -> Result<(), $crate::common::errors::HydraIOError> {
+                $($crate::common::savestate::SaveState::load_state(&mut self.$field, state)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Implements `SaveState` for a fieldless enum, storing the variant as its index in the given list.
+#[macro_export]
+macro_rules! save_state_enum {
+    ($type:ty { $($variant:ident),* $(,)? }) => {
+        impl $crate::common::savestate::SaveState for $type {
+            fn save_state(&self, state: &mut $crate::common::savestate::StateWriter) {
+                let variants = [$(matches!(self, Self::$variant)),*];
+                $crate::common::savestate::SaveState::save_state(&(variants.iter().position(|&is_variant| is_variant).unwrap() as u8), state);
+            }
+
+            fn load_state(&mut self, state: &mut $crate::common::savestate::StateReader) -> Result<(), $crate::common::errors::HydraIOError> {
+                let mut index = 0u8;
+                $crate::common::savestate::SaveState::load_state(&mut index, state)?;
+                let variants = [$(Self::$variant),*];
+                *self = variants.into_iter().nth(index as usize).ok_or($crate::common::errors::HydraIOError::MalformedSaveState(concat!("Invalid ", stringify!($type))))?;
+                Ok(())
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{CGBRevision, GBRevision, GameBoy, Model};
+
+    const DMG: Model = Model::GameBoy(GBRevision::DMG);
+    const CGB: Model = Model::GameBoyColor(CGBRevision::CGB);
+
+    /// A console that's been running an MBC1 cartridge with battery-backed RAM for a few frames, which just loops at $0100.
+    fn running(model: Model) -> GameBoy {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+        rom[0x0147] = 0x03;
+        rom[0x0149] = 0x02;
+        let mut gb = GameBoy::headless(rom, model).unwrap();
+        gb.run_cycles(10 * 70224);
+        gb
+    }
+
+    #[test]
+    fn primitives_round_trip() {
+        let mut state = StateWriter::new();
+        0x1234u16.save_state(&mut state);
+        true.save_state(&mut state);
+        [1u8, 2, 3].save_state(&mut state);
+        vec![4u32, 5].save_state(&mut state);
+        Some(6u8).save_state(&mut state);
+        let data = state.into_inner();
+
+        let mut reader = StateReader::new(&data);
+        let (mut short, mut flag, mut array, mut vec, mut option) = (0u16, false, [0u8; 3], Vec::<u32>::new(), None::<u8>);
+        short.load_state(&mut reader).unwrap();
+        flag.load_state(&mut reader).unwrap();
+        array.load_state(&mut reader).unwrap();
+        vec.load_state(&mut reader).unwrap();
+        option.load_state(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!((short, flag, array, vec, option), (0x1234, true, [1, 2, 3], vec![4, 5], Some(6)));
+    }
+
+    #[test]
+    fn primitives_reject_bad_data() {
+        assert!(matches!(true.load_state(&mut StateReader::new(&[2])), Err(HydraIOError::MalformedSaveState(_))));
+        assert!(matches!(0u32.load_state(&mut StateReader::new(&[0; 3])), Err(HydraIOError::MalformedSaveState(_))));
+        // Slices are sized by the hardware, so a different length can't be loaded
+        let mut state = StateWriter::new();
+        [0u8; 4][..].save_state(&mut state);
+        let data = state.into_inner();
+        assert!(matches!([0u8; 5][..].load_state(&mut StateReader::new(&data)), Err(HydraIOError::MalformedSaveState(_))));
+        // A length longer than what's left can't be allocated up front
+        let mut state = StateWriter::new();
+        usize::MAX.save_state(&mut state);
+        let data = state.into_inner();
+        assert!(matches!(Vec::<u8>::new().load_state(&mut StateReader::new(&data)), Err(HydraIOError::MalformedSaveState(_))));
+    }
+
+    #[test]
+    fn save_load_save_is_identical() {
+        for model in [DMG, CGB] {
+            let mut gb = running(model);
+            let saved = gb.save_state();
+            gb.run_cycles(5 * 70224);
+            assert_ne!(gb.save_state(), saved);
+            gb.load_state(&saved).unwrap();
+            assert_eq!(gb.save_state(), saved, "{:?} didn't restore exactly", model);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_version() {
+        let mut gb = running(DMG);
+        let saved = gb.save_state();
+
+        let mut bad_magic = saved.clone();
+        bad_magic[0] ^= 0xFF;
+        assert!(matches!(gb.load_state(&bad_magic), Err(HydraIOError::MalformedSaveState(_))));
+
+        let mut other_version = saved.clone();
+        other_version[8] = other_version[8].wrapping_add(1);
+        assert!(matches!(gb.load_state(&other_version), Err(HydraIOError::IncompatibleSaveState(_))));
+
+        let other_model = running(CGB).save_state();
+        assert!(matches!(gb.load_state(&other_model), Err(HydraIOError::IncompatibleSaveState(_))));
+        assert_eq!(gb.save_state(), saved);
+    }
+
+    #[test]
+    fn failed_load_leaves_machine_untouched() {
+        let mut gb = running(DMG);
+        let earlier = gb.save_state();
+        gb.run_cycles(5 * 70224);
+        let current = gb.save_state();
+
+        // Cut off partway through, after the CPU and some of memory have already been overwritten
+        let truncated = &earlier[..earlier.len() / 2];
+        assert!(matches!(gb.load_state(truncated), Err(HydraIOError::MalformedSaveState(_))));
+        assert_eq!(gb.save_state(), current);
+
+        let mut trailing = earlier.clone();
+        trailing.push(0);
+        assert!(matches!(gb.load_state(&trailing), Err(HydraIOError::MalformedSaveState(_))));
+        assert_eq!(gb.save_state(), current);
+    }
+}
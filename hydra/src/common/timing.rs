@@ -2,6 +2,8 @@ use std::{borrow::Borrow, ops::{Deref, DerefMut, Rem}};
 
 use funty::Unsigned;
 
+use crate::common::{errors::HydraIOError, savestate::{SaveState, StateReader, StateWriter}};
+
 pub struct Resettable<T> {
     pub current: T,
     pub reset_value: T
@@ -138,4 +140,54 @@ impl<T, R> DynamicOverflowCounter<T, R> where T: Unsigned, R: Borrow<T> + From<T
     pub fn has_completed_cycle(&self) -> bool {
         self.value == *self.reset_value.borrow()
     }
-}
\ No newline at end of file
+}
+
+impl<T: SaveState> SaveState for Resettable<T> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.current.save_state(state);
+        self.reset_value.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.current.load_state(state)?;
+        self.reset_value.load_state(state)
+    }
+}
+
+impl<T: SaveState + Default> SaveState for DelayedTickCounter<T> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.value.save_state(state);
+        self.target.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.value.load_state(state)?;
+        self.target.load_state(state)
+    }
+}
+
+impl<T: SaveState, M: SaveState, R: SaveState> SaveState for DynamicModuloCounter<T, M, R> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.value.save_state(state);
+        self.modulus.save_state(state);
+        self.reset_value.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.value.load_state(state)?;
+        self.modulus.load_state(state)?;
+        self.reset_value.load_state(state)
+    }
+}
+
+impl<T: SaveState, R: SaveState> SaveState for DynamicOverflowCounter<T, R> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.value.save_state(state);
+        self.reset_value.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.value.load_state(state)?;
+        self.reset_value.load_state(state)
+    }
+}
@@ -1,8 +1,8 @@
-use std::fs;
+use std::{fs, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{common::errors::HydraIOError, propagate, propagate_or};
+use crate::{common::errors::HydraIOError, gameboy::{AGBRevision, CGBRevision, GBRevision, Model, SGBRevision, ShadePalette}, propagate, propagate_or};
 
 const CONFIG_PATH: &str = "config.toml";
 
@@ -16,6 +16,15 @@ pub struct Config {
 pub struct GBConfig {
     pub default_models: GBDefaultModelsConfig,
     pub show_all_revisions: bool,
+    #[serde(default)]
+    pub rewind: GBRewindConfig,
+    /// The localhost port a GDB stub listens on for debuggers to attach to. Set to 0 to disable it.
+    #[serde(default)]
+    pub gdb_port: u16,
+    #[serde(default)]
+    pub boot_roms: GBBootRomConfig,
+    #[serde(default)]
+    pub palettes: GBPaletteConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +35,106 @@ pub struct GBDefaultModelsConfig {
     pub agb: crate::gameboy::AGBRevision,
 }
 
+/// Paths to boot ROM dumps for each model. Models without one skip straight to the cartridge, as if their boot ROM had already run.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GBBootRomConfig {
+    pub dmg0: Option<PathBuf>,
+    pub dmg: Option<PathBuf>,
+    pub mgb: Option<PathBuf>,
+    pub sgb: Option<PathBuf>,
+    pub sgb2: Option<PathBuf>,
+    pub cgb0: Option<PathBuf>,
+    pub cgb: Option<PathBuf>,
+    pub agb0: Option<PathBuf>,
+    pub agb: Option<PathBuf>,
+}
+
+impl GBBootRomConfig {
+    pub fn get(&self, model: &Model) -> Option<&PathBuf> {
+        match model {
+            Model::GameBoy(GBRevision::DMG0) => self.dmg0.as_ref(),
+            Model::GameBoy(GBRevision::DMG) => self.dmg.as_ref(),
+            Model::GameBoy(GBRevision::MGB) => self.mgb.as_ref(),
+            Model::SuperGameBoy(SGBRevision::SGB) => self.sgb.as_ref(),
+            Model::SuperGameBoy(SGBRevision::SGB2) => self.sgb2.as_ref(),
+            Model::GameBoyColor(CGBRevision::CGB0) => self.cgb0.as_ref(),
+            Model::GameBoyColor(CGBRevision::CGB) => self.cgb.as_ref(),
+            Model::GameBoyAdvance(AGBRevision::AGB0) => self.agb0.as_ref(),
+            Model::GameBoyAdvance(AGBRevision::AGB) => self.agb.as_ref(),
+        }
+    }
+}
+
+/// The colors DMG games are displayed with, picked from the built-in presets or from any custom palettes defined here.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GBPaletteConfig {
+    /// The name of a preset (`grayscale`, `dmg`, `pocket`, `light` or `high_contrast`) or of a custom palette.
+    pub selected: String,
+    #[serde(default)]
+    pub custom: Vec<GBCustomPaletteConfig>,
+}
+
+/// A palette given as RGB colors for each shade, from lightest to darkest.
+/// OBJ0 and OBJ1 fall back to the background's colors if left out.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GBCustomPaletteConfig {
+    pub name: String,
+    pub bg: [[u8; 3]; 4],
+    pub obj0: Option<[[u8; 3]; 4]>,
+    pub obj1: Option<[[u8; 3]; 4]>,
+}
+
+impl GBPaletteConfig {
+    /// Lists every palette that can be selected, as its name along with the name it's shown with.
+    pub fn list(&self) -> Vec<(String, String)> {
+        ShadePalette::PRESETS.iter().map(|(name, label, _)| (name.to_string(), label.to_string()))
+            .chain(self.custom.iter().map(|custom| (custom.name.clone(), custom.name.clone())))
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<ShadePalette> {
+        match ShadePalette::PRESETS.iter().find(|(preset, _, _)| *preset == name) {
+            Some((_, _, shades)) => Some(*shades),
+            None => self.custom.iter().find(|custom| custom.name == name)
+                .map(|custom| ShadePalette::layered(custom.bg, custom.obj0.unwrap_or(custom.bg), custom.obj1.unwrap_or(custom.bg))),
+        }
+    }
+
+    /// Returns the selected palette, or grayscale if there isn't one by that name.
+    pub fn get_selected(&self) -> ShadePalette {
+        self.get(&self.selected).unwrap_or_else(|| {
+            println!("No palette named \"{}\" exists, so grayscale will be used instead.", self.selected);
+            ShadePalette::GRAYSCALE
+        })
+    }
+}
+
+impl Default for GBPaletteConfig {
+    fn default() -> Self {
+        GBPaletteConfig {
+            selected: String::from("grayscale"),
+            custom: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GBRewindConfig {
+    /// How many frames pass between each snapshot. Lower values make rewinding smoother, but fill the buffer faster.
+    pub frames_per_snapshot: u32,
+    /// The most memory the rewind buffer may use, in MiB. Set to 0 to disable rewinding.
+    pub memory_cap_mib: u32,
+}
+
+impl Default for GBRewindConfig {
+    fn default() -> Self {
+        GBRewindConfig {
+            frames_per_snapshot: 2,
+            memory_cap_mib: 64,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -38,6 +147,10 @@ impl Default for Config {
                     agb: crate::gameboy::AGBRevision::AGB,
                 },
                 show_all_revisions: false,
+                rewind: GBRewindConfig::default(),
+                gdb_port: 0,
+                boot_roms: GBBootRomConfig::default(),
+                palettes: GBPaletteConfig::default(),
             },
         }
     }
@@ -1,22 +1,35 @@
 mod apu;
 mod cpu;
+mod debugger;
+mod gdb;
 mod interrupt;
 mod joypad;
 mod memory;
+mod movie;
 mod ppu;
 mod serial;
+mod sgb;
+mod symbols;
 mod timer;
+mod trace;
+#[cfg(test)]
+mod tests;
 
-use wgpu::{Device, Queue};
+#[cfg(feature = "frontend")]
 use winit::{event::KeyEvent, keyboard::{KeyCode, PhysicalKey}};
 
 use crate::{
     common::{
-        bit::{BitVec, MaskedBitVec}, emulator::{EmuMessage, Emulator}, errors::HydraIOError
-    }, gameboy::{apu::Apu, cpu::Cpu, interrupt::{InterruptEnable, InterruptFlags}, joypad::{JoypButton, JoypDpad, Joypad}, memory::{MemoryMap, MemoryMapped, oam::Oam, rom::{Rom, RomHeader}, vram::Vram, wram::Wram}, ppu::{Ppu, PpuMode, colormap::{self, CgbColorMap, ColorMap, DmgColorMap}, state::PpuState}, timer::MasterTimer}, graphics::Graphics, window::HydraApp
+        emulator::{EmuMessage, Emulator}, errors::HydraIOError, rewind::RewindBuffer, savestate::{SaveState, StateReader, StateWriter}
+    }, config::GBRewindConfig, gameboy::{apu::Apu, cpu::{Cpu, Register16}, debugger::Debugger, gdb::GdbStub, joypad::{ControllerPort, NoControllers}, memory::{MemoryMap, boot::BootRom, rom::RomHeader}, movie::MovieSession, ppu::{Ppu, viewer::Viewers}}, save_state_enum
 };
+#[cfg(feature = "frontend")]
+use crate::{common::emulator::EmuHandle, graphics::{Graphics, ViewerProxy, Viewport}, window::HydraApp};
+pub use crate::gameboy::{debugger::{Breakpoint, WatchKind, Watchpoint}, joypad::{JoypButton, JoypDpad}, memory::header::CartridgeHeader, movie::{Movie, MovieStart}, ppu::{colormap::ShadePalette, viewer::{TilePalette, Viewer, ViewerImage, ViewerOutput}}, symbols::SymbolTable, trace::TraceLogger};
+#[cfg(feature = "frontend")]
+use std::{ffi::OsStr, sync::{RwLock, mpsc::channel}};
 use std::{
-    cell::{Cell, RefCell}, ffi::OsStr, fs, path::Path, rc::Rc, sync::{Arc, RwLock, mpsc::{Receiver, Sender, channel}}, thread, time::{Duration, Instant}
+    cell::Cell, fs, path::{Path, PathBuf}, rc::Rc, sync::{Arc, mpsc::Receiver}, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}
 };
 
 #[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -54,6 +67,10 @@ impl Model {
         matches!(self, Model::GameBoy(_) | Model::SuperGameBoy(_))
     }
 
+    const fn is_super(&self) -> bool {
+        matches!(self, Model::SuperGameBoy(_))
+    }
+
     const fn is_color(&self) -> bool {
         matches!(self, Model::GameBoyColor(_) | Model::GameBoyAdvance(_))
     }
@@ -84,93 +101,508 @@ pub enum AGBRevision {
     AGB,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum GbMode {
     DMG,
     CGB
 }
 
+save_state_enum!(GbMode { DMG, CGB });
+
+impl GbMode {
+    /// Selects the mode the given model starts in. Color models running a boot ROM always start in CGB mode,
+    /// leaving the boot ROM to switch to DMG compatibility mode (through KEY0) if the cartridge doesn't support CGB mode.
+    fn select(model: &Model, header: &RomHeader, has_boot_rom: bool) -> Self {
+        match model.is_color() && (has_boot_rom || header.supports_cgb_mode()) {
+            true => GbMode::CGB,
+            false => GbMode::DMG
+        }
+    }
+}
+
 
 pub struct GameBoy {
     apu: Apu,
     cpu: Option<Cpu>,
     memory: MemoryMap,
     ppu: Ppu,
-
-    channel: Receiver<EmuMessage>,
-    device: Arc<Device>,
-    queue: Arc<Queue>,
+    rom: RomHeader,
+
+    channel: Option<Receiver<EmuMessage>>,
+    rom_path: Option<PathBuf>,
+    rewind: RewindBuffer,
+    movie: Option<MovieSession>,
+    debugger: Debugger,
+    trace: Option<TraceLogger>,
+    gdb: Option<GdbStub>,
+    viewers: Viewers,
+    shade_palette: ShadePalette,
 
     running: bool,
     turbo: bool,
-    dump_cpu: bool,
+    rewinding: bool,
     next_frame_instant: Instant,
+    frame_complete: bool,
+    dot_count: u64,
 }
 
+/// Reads a ROM file, warning about any problems with its header that don't stop it from being run.
 fn read_as_rom(path: &Path) -> Result<RomHeader, HydraIOError> {
-    Ok(RomHeader::from_vec(fs::read(path)?)?)
+    let romvec = fs::read(path)?;
+    let header = CartridgeHeader::parse(&romvec)?;
+    let rom = RomHeader::from_parsed(romvec, &header)?;
+    let problems = header.get_problems();
+    if !problems.is_empty() {
+        println!("Cartridge header has problems, so the ROM may be a corrupt dump: {}", problems.join("; "));
+    }
+    Ok(rom)
 }
 
 impl GameBoy {
-    pub fn new(path: &Path, model: Model, app: &HydraApp) -> Result<Sender<EmuMessage>, HydraIOError> {
+    #[cfg(feature = "frontend")]
+    pub fn new(path: &Path, model: Model, app: &HydraApp) -> Result<EmuHandle, HydraIOError> {
         let ext = path.extension().and_then(OsStr::to_str);
         if !model.is_extension_valid(ext) {
             return Err(HydraIOError::InvalidEmulator(model.as_str(), ext.map(str::to_string)));
         }
 
         let header = read_as_rom(path)?;
-        let mode = match model.is_color() && header.supports_cgb_mode() {
-            true => GbMode::CGB,
-            false => GbMode::DMG
-        };
+        let boot_rom = app.get_config().gb.boot_roms.get(&model).map(|path| BootRom::from_file(path, &model)).transpose()?;
+        let mode = GbMode::select(&model, &header, boot_rom.is_some());
 
-        GameBoy::with_mode(header, model, mode, app)
+        GameBoy::with_frontend(header, model, mode, boot_rom, path.to_path_buf(), app)
     }
 
-    fn with_mode(header: RomHeader, model: Model, mode: GbMode, app: &HydraApp) -> Result<Sender<EmuMessage>, HydraIOError> {
+    /// Builds a Game Boy with no window, audio device or controllers attached.
+    /// Frames and samples are retrieved with `get_frame` and `take_audio_samples`, and input is given with `press_button` and `press_dpad`.
+    pub fn headless(rom: Vec<u8>, model: Model) -> Result<GameBoy, HydraIOError> {
+        let header = RomHeader::from_vec(rom)?;
+        let mode = GbMode::select(&model, &header, false);
+
+        GameBoy::with_mode(header, model, mode, Arc::new(NoControllers), None, None, rand::random())
+    }
+
+    /// Builds a headless Game Boy which starts by running the given boot ROM, rather than skipping straight to the cartridge.
+    pub fn headless_with_boot_rom(rom: Vec<u8>, boot_rom: Vec<u8>, model: Model) -> Result<GameBoy, HydraIOError> {
+        let header = RomHeader::from_vec(rom)?;
+        let boot_rom = BootRom::from_vec(boot_rom, &model)?;
+        let mode = GbMode::select(&model, &header, true);
+
+        GameBoy::with_mode(header, model, mode, Arc::new(NoControllers), Some(boot_rom), None, rand::random())
+    }
+
+    fn with_mode(header: RomHeader, model: Model, mode: GbMode, controllers: Arc<dyn ControllerPort>, boot_rom: Option<BootRom>, rom_path: Option<PathBuf>, seed: u64) -> Result<GameBoy, HydraIOError> {
+        let model = Rc::new(model);
+        let mode = Rc::new(Cell::new(mode));
+
+        let ppu = Ppu::new(mode.clone());
+        let apu = Apu::new();
+        let cpu = Some(match boot_rom {
+            Some(_) => Cpu::power_on(),
+            None => Cpu::new(&header, &model, &mode.get()),
+        });
+        let mut memory = MemoryMap::new(model.clone(), mode.clone(), controllers, boot_rom, seed)?;
+        let rom = header.clone();
+        memory.select_compatibility_palettes(&header);
+        memory.hot_swap_rom(header, rom_path.as_ref().map(|path| path.with_extension("sav")))?;
+
+        let mut gameboy = GameBoy {
+            apu,
+            cpu,
+            memory,
+            ppu,
+            rom,
+
+            channel: None,
+            rom_path,
+            rewind: RewindBuffer::disabled(),
+            movie: None,
+            debugger: Debugger::new(),
+            trace: None,
+            gdb: None,
+            viewers: Viewers::new(),
+            shade_palette: ShadePalette::GRAYSCALE,
+
+            running: true,
+            turbo: false,
+            rewinding: false,
+            next_frame_instant: Instant::now(),
+            frame_complete: false,
+            dot_count: 0,
+        };
+        gameboy.load_symbols_alongside_rom();
+        Ok(gameboy)
+    }
+
+    #[cfg(feature = "frontend")]
+    fn with_frontend(header: RomHeader, model: Model, mode: GbMode, boot_rom: Option<BootRom>, rom_path: PathBuf, app: &HydraApp) -> Result<EmuHandle, HydraIOError> {
         let (send, recv) = channel();
         let controllers = app.clone_controllers();
         let graphics = app.clone_graphics();
-        let (device, queue) = {
-            let gtemp = graphics.read().unwrap();
-            (gtemp.get_device(), gtemp.get_queue())
-        };
         let audio = app.clone_audio();
         let proxy = app.clone_proxy();
+        let rewind_config = app.get_config().gb.rewind;
+        let gdb_port = app.get_config().gb.gdb_port;
+        let shade_palette = app.get_config().gb.palettes.get_selected();
 
-        Self::init_graphics(&graphics);
+        Self::init_graphics(&graphics, &model);
 
         // Build Game Boy on a new thread
-        thread::spawn(move || {
-            let model = Rc::new(model);
-            let mode = Rc::new(mode);
-
-            let ppu = Ppu::new(model.clone());
-            let apu = Apu::new(audio);
-            let cpu = Some(Cpu::new(&header, &model, &mode));
-            let mut memory = MemoryMap::new(model.clone(), mode.clone(), controllers, graphics, proxy).unwrap(); // TODO: Error should be handled rather than unwrapped
-            memory.hot_swap_rom(header, device.clone(), queue.clone()).unwrap();
-
-            GameBoy {
-                apu,
-                cpu,
-                memory,
-                ppu,
-
-                channel: recv,
-                device,
-                queue,
-
-                running: true,
-                turbo: false,
-                dump_cpu: false,
-                next_frame_instant: Instant::now()
-            }.main_thread();
+        let thread = thread::spawn(move || {
+            let mut gameboy = GameBoy::with_mode(header, model, mode, controllers, boot_rom, Some(rom_path), rand::random()).unwrap(); // TODO: Error should be handled rather than unwrapped
+            gameboy.memory.attach_video_output(Box::new(Viewport::new(graphics, proxy.clone())));
+            gameboy.attach_viewer_output(Box::new(ViewerProxy::new(proxy)));
+            gameboy.apu.attach_output(Box::new(audio.write().unwrap().open_stream()));
+            gameboy.channel = Some(recv);
+            gameboy.set_rewind_config(rewind_config);
+            gameboy.set_shade_palette(shade_palette);
+            if gdb_port != 0 {
+                match gameboy.start_gdb_stub(gdb_port) {
+                    Ok(port) => println!("GDB stub listening on localhost:{}.", port),
+                    Err(e) => println!("Unable to start GDB stub: {}", e),
+                }
+            }
+            gameboy.main_thread();
         });
-        Ok(send)
+        Ok(EmuHandle::new(send, thread))
+    }
+
+    #[cfg(feature = "frontend")]
+    fn init_graphics(graphics: &Arc<RwLock<Graphics>>, model: &Model) {
+        // The SGB draws a border around the screen
+        let (width, height) = match model.is_super() {
+            true => (sgb::FRAME_WIDTH as u32, sgb::FRAME_HEIGHT as u32),
+            false => (ppu::SCREEN_WIDTH as u32, ppu::SCREEN_HEIGHT as u32),
+        };
+        graphics.write().unwrap().init_emulator(width, height);
+    }
+
+    /// Returns the most recently completed frame as tightly packed RGBA8 pixels.
+    /// Frames are 160x144, or 256x224 on an SGB, whose border is drawn around the screen.
+    pub fn get_frame(&self) -> &[u8] {
+        self.memory.get_frame()
+    }
+
+    /// Returns the width and height of the frames returned by `get_frame`.
+    pub fn get_frame_size(&self) -> (u32, u32) {
+        self.memory.get_frame_size()
+    }
+
+    /// Returns the rate of the samples produced by `take_audio_samples`.
+    pub fn get_audio_sample_rate(&self) -> u32 {
+        self.apu.get_sample_rate()
+    }
+
+    /// Drains all audio generated since the last call, as interleaved left/right samples.
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.apu.take_samples()
+    }
+
+    /// Drains the bytes the game has sent over the link cable since the last call.
+    /// Test ROMs print their results this way.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.memory.serial.take_sent_bytes()
+    }
+
+    /// Presses or releases one of the A/B/Select/Start buttons. Takes effect at the start of the next frame.
+    pub fn press_button(&mut self, button: JoypButton, is_pressed: bool) {
+        self.memory.joypad.keyboard_vecs.press_button(button, is_pressed);
+    }
+
+    /// Presses or releases one of the directions on the D-pad. Takes effect at the start of the next frame.
+    pub fn press_dpad(&mut self, dpad: JoypDpad, is_pressed: bool) {
+        self.memory.joypad.keyboard_vecs.press_dpad(dpad, is_pressed);
+    }
+
+    /// Runs until the current frame is complete, then returns. The last instruction of the frame is always finished.
+    /// Returns early if the debugger pauses the machine, and does nothing while it's paused.
+    pub fn run_frame(&mut self) {
+        let mut cpu = self.cpu.take().unwrap();
+        self.frame_complete = false;
+        while !self.frame_complete && !self.debugger.is_paused() {
+            let pc = cpu.get_register16(Register16::PC);
+            let executed = cpu.step(self, true);
+            self.debugger.after_step(&cpu, &self.memory, pc, executed);
+        }
+        self.cpu = Some(cpu);
+        self.capture_rewind_snapshot();
+    }
+
+    /// Executes a single instruction (or interrupt dispatch), then returns the number of dots that elapsed.
+    /// While the CPU is halted or stopped, this advances by a single idle cycle instead.
+    pub fn step_instruction(&mut self) -> u64 {
+        let start = self.dot_count;
+        let mut cpu = self.cpu.take().unwrap();
+        loop {
+            let pc = cpu.get_register16(Register16::PC);
+            let executed = cpu.step(self, true);
+            self.debugger.after_step(&cpu, &self.memory, pc, executed);
+            if executed || cpu.is_sleeping() {
+                break;
+            }
+        }
+        self.cpu = Some(cpu);
+        self.capture_rewind_snapshot();
+        self.dot_count - start
+    }
+
+    /// Runs whole instructions until at least `dots` dots (4 per M-cycle at normal speed) have elapsed,
+    /// then returns the number of dots that actually elapsed. Like `run_frame`, this stops early if the debugger pauses the machine.
+    pub fn run_cycles(&mut self, dots: u64) -> u64 {
+        let start = self.dot_count;
+        let mut cpu = self.cpu.take().unwrap();
+        while self.dot_count - start < dots && !self.debugger.is_paused() {
+            let pc = cpu.get_register16(Register16::PC);
+            let executed = cpu.step(self, true);
+            self.debugger.after_step(&cpu, &self.memory, pc, executed);
+        }
+        self.cpu = Some(cpu);
+        self.capture_rewind_snapshot();
+        self.dot_count - start
+    }
+
+    const SAVE_STATE_MAGIC: &[u8; 8] = b"HYDRA-GB";
+    /// Bumped whenever the layout of any component's saved state changes, since older states can't be read correctly afterwards.
+    const SAVE_STATE_VERSION: u32 = 10;
+    pub const SAVE_STATE_SLOTS: u8 = 10;
+
+    /// Captures the entire machine (everything but the ROM itself) in a versioned binary save state.
+    /// Battery-backed cartridge RAM is included, but is only written to the `.sav` file as usual.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bytes(Self::SAVE_STATE_MAGIC);
+        Self::SAVE_STATE_VERSION.save_state(&mut state);
+        self.save_state_header(&mut state);
+
+        self.cpu.as_ref().unwrap().save_state(&mut state);
+        self.memory.save_state(&mut state);
+        self.ppu.save_state(&mut state);
+        self.apu.save_state(&mut state);
+        self.dot_count.save_state(&mut state);
+        state.into_inner()
+    }
+
+    /// Restores the machine from a save state made by `save_state`.
+    /// If the state turns out to be unreadable partway through, the machine is left exactly as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), HydraIOError> {
+        let backup = self.save_state();
+        if let Err(e) = self.read_state(data) {
+            self.read_state(&backup).expect("Failed to restore the state that was just saved");
+            return Err(e);
+        }
+        self.memory.resend_frame();
+        Ok(())
+    }
+
+    fn save_state_header(&self, state: &mut StateWriter) {
+        // Identifies the machine the state belongs to, so loading a state from another game or model fails cleanly
+        let model = self.memory.model.as_str().as_bytes();
+        state.write_bytes(&[model.len() as u8]);
+        state.write_bytes(model);
+        self.memory.cartridge_crc.save_state(state);
+    }
+
+    fn read_state(&mut self, data: &[u8]) -> Result<(), HydraIOError> {
+        let mut state = StateReader::new(data);
+        if state.read_bytes(Self::SAVE_STATE_MAGIC.len()).ok() != Some(Self::SAVE_STATE_MAGIC) {
+            return Err(HydraIOError::MalformedSaveState("Not a Game Boy save state"));
+        }
+        let mut version = 0u32;
+        version.load_state(&mut state)?;
+        if version != Self::SAVE_STATE_VERSION {
+            return Err(HydraIOError::IncompatibleSaveState("Made by a different version of Hydra"));
+        }
+
+        let mut expected_header = StateWriter::new();
+        self.save_state_header(&mut expected_header);
+        let expected_header = expected_header.into_inner();
+        if state.read_bytes(expected_header.len()).ok() != Some(expected_header.as_slice()) {
+            return Err(HydraIOError::IncompatibleSaveState("Made with a different ROM or console model"));
+        }
+
+        self.cpu.as_mut().unwrap().load_state(&mut state)?;
+        self.memory.load_state(&mut state)?;
+        self.ppu.load_state(&mut state)?;
+        self.apu.load_state(&mut state)?;
+        self.dot_count.load_state(&mut state)?;
+        match state.is_empty() {
+            true => Ok(()),
+            false => Err(HydraIOError::MalformedSaveState("Unexpected data after end of state")),
+        }
     }
 
-    fn init_graphics(graphics: &Arc<RwLock<Graphics>>) {
-        graphics.write().unwrap().init_emulator(ppu::SCREEN_WIDTH as u32, ppu::SCREEN_HEIGHT as u32);
+    /// Returns the file used for the given save state slot, alongside the ROM. Only available when the ROM was loaded from a file.
+    pub fn get_state_slot_path(&self, slot: u8) -> Option<PathBuf> {
+        self.rom_path.as_ref().map(|path| path.with_extension(format!("ss{}", slot)))
+    }
+
+    /// Writes a save state to the given slot.
+    pub fn save_state_to_slot(&self, slot: u8) -> Result<(), HydraIOError> {
+        let path = self.get_state_slot_path(slot).ok_or(HydraIOError::IncompatibleSaveState("No ROM file to save alongside"))?;
+        Ok(fs::write(path, self.save_state())?)
+    }
+
+    /// Restores the save state in the given slot.
+    pub fn load_state_from_slot(&mut self, slot: u8) -> Result<(), HydraIOError> {
+        let path = self.get_state_slot_path(slot).ok_or(HydraIOError::IncompatibleSaveState("No ROM file to load alongside"))?;
+        self.load_state(&fs::read(path)?)
+    }
+
+    /// Replaces the rewind buffer with one using the given settings, discarding any snapshots taken so far.
+    pub fn set_rewind_config(&mut self, config: GBRewindConfig) {
+        self.rewind = Self::new_rewind_buffer(config);
+    }
+
+    fn new_rewind_buffer(config: GBRewindConfig) -> RewindBuffer {
+        RewindBuffer::new(config.frames_per_snapshot, (config.memory_cap_mib as usize) << 20)
+    }
+
+    /// Steps back to the most recent rewind snapshot, returning false if there are none left.
+    /// Snapshots are taken every few frames (see `GBRewindConfig`), so repeated calls play the game backwards.
+    pub fn rewind(&mut self) -> bool {
+        let Some(state) = self.rewind.pop() else {return false};
+        match self.load_state(&state) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Unable to rewind: {}", e);
+                self.rewind.clear();
+                false
+            }
+        }
+    }
+
+    fn capture_rewind_snapshot(&mut self) {
+        // The per-frame hook fires partway through an instruction, while the CPU is checked out of `self`,
+        // so it only marks a snapshot as due, and the snapshot itself is taken once the instruction is done
+        if self.rewind.is_snapshot_due() {
+            let state = self.save_state();
+            self.rewind.push(state);
+        }
+    }
+
+    /// Turns the console off and on again, drawing any power-on values that vary between boots from `seed`.
+    /// Battery-backed RAM is written out and read back in, as if the cartridge had been pulled and reinserted.
+    pub fn power_on(&mut self, seed: u64) -> Result<(), HydraIOError> {
+        let model = self.memory.model.clone();
+        let mode = self.memory.mode.clone();
+        let boot_rom = self.memory.boot_rom.clone();
+        let has_boot_rom = boot_rom.is_some();
+        // The boot ROM may have left the console in DMG compatibility mode, which only lasts until power-off
+        mode.set(GbMode::select(&model, &self.rom, has_boot_rom));
+        let mut memory = MemoryMap::new(model.clone(), mode.clone(), self.memory.joypad.controllers.clone(), boot_rom, seed)?;
+        self.memory.flush_battery()?;
+        memory.hot_swap_rom(self.rom.clone(), self.rom_path.as_ref().map(|path| path.with_extension("sav")))?;
+        memory.joypad.keyboard_vecs = self.memory.joypad.keyboard_vecs;
+        memory.select_compatibility_palettes(&self.rom);
+        memory.color_map.set_shade_palette(self.shade_palette);
+        // Debugging aids aren't part of the machine, so they survive a power cycle
+        memory.watchpoints = std::mem::take(&mut self.memory.watchpoints);
+        memory.symbols = std::mem::take(&mut self.memory.symbols);
+        if let Some(output) = self.memory.take_video_output() {
+            memory.attach_video_output(output);
+        }
+
+        self.memory = memory;
+        self.cpu = Some(match has_boot_rom {
+            true => Cpu::power_on(),
+            false => Cpu::new(&self.rom, &model, &mode.get()),
+        });
+        self.ppu = Ppu::new(mode);
+        self.apu.reset();
+        self.dot_count = 0;
+        self.rewind.clear();
+        Ok(())
+    }
+
+    /// Starts recording the joypad into a new movie, beginning either from a fresh power-on or from the current state.
+    /// While recording, inputs are latched once per frame, so that playback sees them at exactly the same points.
+    pub fn start_recording(&mut self, from_power_on: bool) -> Result<(), HydraIOError> {
+        self.stop_movie();
+        let start_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+        let start = match from_power_on {
+            true => {
+                let seed = rand::random();
+                self.power_on(seed)?;
+                let cartridge_ram = self.memory.cartridge.as_ref().and_then(|mbc| mbc.save_data()).unwrap_or_default();
+                MovieStart::PowerOn { seed, cartridge_ram }
+            }
+            false => MovieStart::SaveState(self.save_state()),
+        };
+        let movie = Movie::new(self.memory.model.as_str(), self.memory.cartridge_crc, start, start_time);
+        // The starting point is restored just as playback will, so that the cartridge's clock is in step with it from the first frame
+        self.restore_movie_start(&movie)?;
+        self.begin_movie(MovieSession::Recording(movie));
+        Ok(())
+    }
+
+    /// Restores the movie's starting point, then feeds its inputs to the joypad frame by frame, until it runs out or is stopped.
+    /// Loading a state or rewinding mid-movie will desync it, so callers should stop the movie first.
+    /// The cartridge's save file is left untouched until the next ROM is loaded, so that playback can't overwrite real progress.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), HydraIOError> {
+        if movie.model != self.memory.model.as_str() || movie.rom_crc != self.memory.cartridge_crc {
+            return Err(HydraIOError::IncompatibleMovie("Made with a different ROM or console model"));
+        }
+        self.stop_movie();
+        if let MovieStart::PowerOn { seed, .. } = &movie.start {
+            self.power_on(*seed)?;
+        }
+        self.memory.detach_battery()?;
+        self.restore_movie_start(&movie)?;
+        self.begin_movie(MovieSession::Playing(movie, 0));
+        Ok(())
+    }
+
+    /// Hands the cartridge's clock over to emulated time, counting up from the movie's start time, then restores the cartridge RAM or save state the movie starts from.
+    /// Powering on for movies that start from power-on is left to the caller.
+    fn restore_movie_start(&mut self, movie: &Movie) -> Result<(), HydraIOError> {
+        if let Some(ref mut mbc) = self.memory.cartridge {
+            mbc.set_emulated_clock(Some(UNIX_EPOCH + Duration::from_secs(movie.start_time)));
+        }
+        match &movie.start {
+            MovieStart::PowerOn { cartridge_ram, .. } => if let Some(ref mut mbc) = self.memory.cartridge {mbc.load_save_data(cartridge_ram)},
+            MovieStart::SaveState(data) => self.load_state(data)?,
+        }
+        Ok(())
+    }
+
+    fn begin_movie(&mut self, mut session: MovieSession) {
+        // Inputs are latched as the movie starts too, since the first frame may be partway done
+        if session.frame(&mut self.memory.joypad, &mut self.memory.interrupt_flags) {
+            self.movie = Some(session);
+        }
+    }
+
+    /// Stops recording or playing the current movie, handing the joypad back to the host. Returns the movie, if there was one.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        let session = self.movie.take()?;
+        self.memory.joypad.override_inputs(None, &mut self.memory.interrupt_flags);
+        if let Some(ref mut mbc) = self.memory.cartridge {mbc.set_emulated_clock(None)};
+        Some(session.into_movie())
+    }
+
+    /// Returns whether a movie is currently being recorded or played.
+    pub fn is_movie_active(&self) -> bool {
+        self.movie.is_some()
+    }
+
+    /// Returns the file that recorded movies are saved to, alongside the ROM. Only available when the ROM was loaded from a file.
+    pub fn get_movie_path(&self) -> Option<PathBuf> {
+        self.rom_path.as_ref().map(|path| path.with_extension("gbm"))
+    }
+
+    /// Stops the current movie, writing it to the movie file if it was being recorded.
+    fn finish_movie(&mut self) {
+        let is_recording = matches!(self.movie, Some(MovieSession::Recording(_)));
+        let Some(movie) = self.stop_movie() else {return};
+        if !is_recording {
+            println!("Stopped movie playback.");
+            return;
+        }
+        let Some(path) = self.get_movie_path() else {return};
+        match fs::write(&path, movie.to_bytes()) {
+            Ok(()) => println!("Saved {} frames of input to {}.", movie.len(), path.display()),
+            Err(e) => println!("Unable to save movie: {}", e),
+        }
     }
 
     pub fn is_running(&self) -> bool {
@@ -181,12 +613,98 @@ impl GameBoy {
         for y in 0..=0xFFF {
             print!("{:#06X}:   ", y << 4);
             for x in 0..=0xF {
-                print!("{:02X} ", self.memory.read_u8(x | (y << 4), true));
+                print!("{:02X} ", self.memory.debug_read_u8(x | (y << 4)));
             }
             println!("");
         }
     }
 
+    const FRAMES_PER_SAVE: u32 = 60 * 5;
+
+    fn wait_for_next_frame(&mut self) {
+        if self.turbo {
+            // If turbo is on, instantly render next frame without delays
+            self.next_frame_instant = Instant::now();
+        } else {
+            // Delay thread until next frame if turbo is off
+            let duration_until_next = self.next_frame_instant.saturating_duration_since(Instant::now());
+            thread::sleep(duration_until_next);
+        }
+        // Set expected timing for next frame
+        const SECS_PER_FRAME: f64 = 1f64 / 60f64;
+        self.next_frame_instant += Duration::from_secs_f64(SECS_PER_FRAME);
+    }
+
+    fn process_messages(&mut self) {
+        let Some(channel) = &self.channel else {return};
+        let messages: Vec<EmuMessage> = channel.try_iter().collect();
+        for msg in messages {
+            let memory = &mut self.memory;
+            match msg {
+                // TODO: Allow remapping controls in the future
+                #[cfg(feature = "frontend")]
+                EmuMessage::KeyboardInput(KeyEvent {state, physical_key: PhysicalKey::Code(keycode), repeat, .. }) => match keycode {
+                    KeyCode::KeyW => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Up, state.is_pressed()),
+                    KeyCode::KeyS => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Down, state.is_pressed()),
+                    KeyCode::KeyA => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Left, state.is_pressed()),
+                    KeyCode::KeyD => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Right, state.is_pressed()),
+                    KeyCode::KeyK => memory.joypad.keyboard_vecs.press_button(JoypButton::A, state.is_pressed()),
+                    KeyCode::KeyJ => memory.joypad.keyboard_vecs.press_button(JoypButton::B, state.is_pressed()),
+                    KeyCode::Enter => memory.joypad.keyboard_vecs.press_button(JoypButton::Start, state.is_pressed()),
+                    KeyCode::ShiftRight => memory.joypad.keyboard_vecs.press_button(JoypButton::Select, state.is_pressed()),
+                    KeyCode::Space => self.turbo = state.is_pressed(),
+                    KeyCode::Backspace => {
+                        if state.is_pressed() {self.finish_movie()}
+                        self.rewinding = state.is_pressed();
+                    }
+                    KeyCode::AltLeft if state.is_pressed() && !repeat => self.toggle_trace(),
+                    _ => {}
+                }
+                EmuMessage::HotSwap(path) => {
+                    self.finish_movie();
+                    let memory = &mut self.memory;
+                    match read_as_rom(path).and_then(|rom| memory.hot_swap_rom(rom.clone(), Some(path.with_extension("sav"))).map(|()| rom)) {
+                        Ok(rom) => {
+                            self.rom = rom;
+                            self.rom_path = Some(path.to_path_buf());
+                            self.rewind.clear();
+                            self.load_symbols_alongside_rom();
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                },
+                EmuMessage::SaveState(slot) => match self.save_state_to_slot(slot) {
+                    Ok(()) => println!("Saved state to slot {}.", slot),
+                    Err(e) => println!("Unable to save state: {}", e),
+                },
+                EmuMessage::LoadState(slot) => {
+                    self.finish_movie();
+                    match self.load_state_from_slot(slot) {
+                        Ok(()) => println!("Loaded state from slot {}.", slot),
+                        Err(e) => println!("Unable to load state: {}", e),
+                    }
+                }
+                EmuMessage::RecordMovie { from_power_on } => match self.start_recording(from_power_on) {
+                    Ok(()) => println!("Recording movie."),
+                    Err(e) => println!("Unable to record movie: {}", e),
+                },
+                EmuMessage::PlayMovie(path) => match fs::read(path).map_err(HydraIOError::from).and_then(|data| self.play_movie(Movie::from_bytes(&data)?)) {
+                    Ok(()) => println!("Playing movie."),
+                    Err(e) => println!("Unable to play movie: {}", e),
+                },
+                EmuMessage::StopMovie => self.finish_movie(),
+                EmuMessage::DebugCommand(line) => println!("{}", self.execute_debug_command(&line)),
+                EmuMessage::OpenViewer(viewer) => self.open_viewer(viewer),
+                EmuMessage::CloseViewer(viewer) => self.close_viewer(viewer),
+                EmuMessage::SetTilePalette(palette) => self.set_tile_palette(palette),
+                EmuMessage::SetShadePalette(shades) => self.set_shade_palette(shades),
+                EmuMessage::ScrollViewer { viewer, x, y, delta } => self.scroll_viewer(viewer, x, y, delta),
+                EmuMessage::Stop => self.running = false,
+                _ => {} // Do nothing
+            }
+        }
+    }
+
     fn cycle_components(&mut self) {
         let memory = &mut self.memory;
         // Finish current M-cycle
@@ -199,58 +717,29 @@ impl GameBoy {
             
             // Every frame
             if memory.timer.is_new_frame() {
-                // Sleep until next frame (unless turbo is active)
-                if self.turbo {
-                    // If turbo is on, instantly render next frame without delays
-                    self.next_frame_instant = Instant::now();
-                } else {
-                    // Delay thread until next frame if turbo is off
-                    let duration_until_next = self.next_frame_instant.saturating_duration_since(Instant::now());
-                    thread::sleep(duration_until_next);
-                }
-                // Set expected timing for next frame
-                const SECS_PER_FRAME: f64 = 1f64 / 60f64;
-                self.next_frame_instant += Duration::from_secs_f64(SECS_PER_FRAME);
-
                 // Process rumble (if applicable)
                 if let Some(ref mut mbc) = memory.cartridge {mbc.frame();};
 
                 // Send audio for playback
                 self.apu.frame();
 
-                // Process any new messages
-                for msg in self.channel.try_iter() {
-                    match msg {
-                        // TODO: Allow remapping controls in the future
-                        EmuMessage::KeyboardInput(KeyEvent {state, physical_key: PhysicalKey::Code(keycode), .. }) => match keycode {
-                            KeyCode::KeyW => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Up, state.is_pressed()),
-                            KeyCode::KeyS => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Down, state.is_pressed()),
-                            KeyCode::KeyA => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Left, state.is_pressed()),
-                            KeyCode::KeyD => memory.joypad.keyboard_vecs.press_dpad(JoypDpad::Right, state.is_pressed()),
-                            KeyCode::KeyK => memory.joypad.keyboard_vecs.press_button(JoypButton::A, state.is_pressed()),
-                            KeyCode::KeyJ => memory.joypad.keyboard_vecs.press_button(JoypButton::B, state.is_pressed()),
-                            KeyCode::Enter => memory.joypad.keyboard_vecs.press_button(JoypButton::Start, state.is_pressed()),
-                            KeyCode::ShiftRight => memory.joypad.keyboard_vecs.press_button(JoypButton::Select, state.is_pressed()),
-                            KeyCode::Space => self.turbo = state.is_pressed(),
-                            KeyCode::AltLeft => self.dump_cpu = state.is_pressed(),
-                            _ => {}
-                        }
-                        EmuMessage::HotSwap(path) => {
-                            if let Err(e) = read_as_rom(path).and_then(|rom| memory.hot_swap_rom(rom, self.device.clone(), self.queue.clone())) {
-                                println!("{}", e);
-                            }
-                        },
-                        EmuMessage::Stop => self.running = false,
-                        _ => {} // Do nothing
-                    }
-                }
+                // Count down to the next rewind snapshot
+                self.rewind.frame();
 
                 memory.joypad.update_controller_vecs(&mut memory.interrupt_flags);
+                if let Some(movie) = &mut self.movie && !movie.frame(&mut memory.joypad, &mut memory.interrupt_flags) {
+                    // Playback has run out of inputs, so hand the joypad and the cartridge's clock back to the host
+                    memory.joypad.override_inputs(None, &mut memory.interrupt_flags);
+                    if let Some(ref mut mbc) = memory.cartridge {mbc.set_emulated_clock(None)};
+                    self.movie = None;
+                }
+                self.frame_complete = true;
             }
 
             // Next T-cycle
             memory.timer.tick(&mut memory.interrupt_flags, &mut memory.ppu_state, &mut memory.apu_state);
             self.apu.dot_tick(&mut memory.apu_state);
+            self.dot_count += 1;
 
             // Break for next M-cycle when applicable
             if memory.timer.is_system_cycle() {break;}
@@ -268,10 +757,35 @@ impl Emulator for GameBoy {
         println!("Launching Wyrm");
 
         // Start main loop
-        let mut cpu = self.cpu.take().unwrap();
-        cpu.coro(&mut self, true);
+        let mut frames_until_save = Self::FRAMES_PER_SAVE;
+        while self.is_running() {
+            match self.rewinding {
+                true => {self.rewind();},
+                false => self.run_frame(),
+            }
+            if let Some(reason) = self.debugger.take_stop_reason() {
+                println!("{}\n{}", reason, self.describe_registers());
+            }
+            self.present_viewers();
+            self.wait_for_next_frame();
+            self.process_messages();
+            self.poll_gdb();
+
+            // Periodically write battery-backed RAM, in case the program doesn't exit cleanly
+            frames_until_save -= 1;
+            if frames_until_save == 0 {
+                frames_until_save = Self::FRAMES_PER_SAVE;
+                if let Err(e) = self.memory.flush_battery() {
+                    println!("Unable to write save file: {}", e);
+                }
+            }
+        }
 
         println!("Exiting Wyrm");
+        self.finish_movie();
+        if let Err(e) = self.memory.flush_battery() {
+            println!("Unable to write save file: {}", e);
+        }
 
         // Dump memory (for debugging)
         self.dump_mem();
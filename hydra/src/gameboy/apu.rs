@@ -1,12 +1,9 @@
 pub mod channel;
 pub mod state;
 
-use std::{cell::RefCell, f32, rc::Rc, sync::{Arc, RwLock}, time::Instant};
+use std::{f32, mem};
 
-use cpal::{OutputCallbackInfo, Sample, Stream};
-use ringbuf::{HeapProd, traits::{Observer, Producer}};
-
-use crate::{audio::Audio, common::audio, gameboy::{apu::{channel::{Noise, Pulse, PulseType, Wave}, state::ApuState}, memory::MemoryMap, timer::MasterTimer}};
+use crate::{common::{errors::HydraIOError, output::AudioOutput, savestate::{SaveState, StateReader, StateWriter}}, gameboy::{apu::state::ApuState, timer::MasterTimer}};
 
 pub struct Apu {
     dot_counter: u8,
@@ -14,26 +11,51 @@ pub struct Apu {
     global_sample_rate: u32,
     local_buffer_l: Vec<f32>,
     local_buffer_r: Vec<f32>,
-    ring_buffer: HeapProd<f32>,
+    output_buffer: Vec<f32>,
+    output: Option<Box<dyn AudioOutput>>,
 }
 
 impl Apu {
     const SAMPLE_RATE: u32 = MasterTimer::PPU_DOTS_PER_FRAME * 30;
+    /// Output sample rate used when no audio output is attached.
+    pub const DEFAULT_OUTPUT_SAMPLE_RATE: u32 = 48000;
 
-    pub fn new(audio: Arc<RwLock<Audio>>) -> Self {
-        let global_sample_rate = audio.read().unwrap().get_sample_rate();
-        let ring_buffer = audio.write().unwrap().get_producer();
-
+    pub fn new() -> Self {
         Apu { 
             dot_counter: 0,
 
-            global_sample_rate,
+            global_sample_rate: Self::DEFAULT_OUTPUT_SAMPLE_RATE,
             local_buffer_l: Vec::new(),
             local_buffer_r: Vec::new(),
-            ring_buffer,
+            output_buffer: Vec::new(),
+            output: None,
         }
     }
 
+    /// Sends all future samples to the given output (such as a playback stream), resampled to its rate.
+    pub fn attach_output(&mut self, output: Box<dyn AudioOutput>) {
+        self.global_sample_rate = output.sample_rate();
+        self.output = Some(output);
+    }
+
+    /// Returns to the state of a freshly powered-on console, keeping the attached output.
+    pub fn reset(&mut self) {
+        self.dot_counter = 0;
+        self.local_buffer_l.clear();
+        self.local_buffer_r.clear();
+    }
+
+    /// Returns the rate of the samples produced by `take_samples`.
+    pub fn get_sample_rate(&self) -> u32 {
+        self.global_sample_rate
+    }
+
+    /// Drains all samples generated since the last call, as interleaved left/right pairs.
+    /// Samples only accumulate here while no audio output is attached.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        mem::take(&mut self.output_buffer)
+    }
+
     /// Tick function to be called on every master cycle to generate audio samples.
     pub fn dot_tick(&mut self, apu_state: &mut ApuState) {
         self.dot_counter = self.dot_counter.wrapping_add(1);
@@ -44,15 +66,32 @@ impl Apu {
         }
     }
 
-    /// Tick function to be called every frame to push to the global ringbuf.
+    /// Tick function to be called every frame to push to the audio output.
     pub fn frame(&mut self) {
         let old_sample_count = self.local_buffer_l.len();
         let new_sample_count = self.global_sample_rate as usize * old_sample_count / Self::SAMPLE_RATE as usize;
         
-        let new_buffer = (0..new_sample_count).into_iter().map(|index| index * old_sample_count / new_sample_count).flat_map(|new_index| [self.local_buffer_l[new_index], self.local_buffer_r[new_index]]).collect::<Vec<_>>();
-        self.ring_buffer.push_slice(new_buffer.as_slice());
+        let new_buffer = (0..new_sample_count).into_iter().map(|index| index * old_sample_count / new_sample_count).flat_map(|new_index| [self.local_buffer_l[new_index], self.local_buffer_r[new_index]]);
+        match &mut self.output {
+            Some(output) => output.push_samples(new_buffer.collect::<Vec<_>>().as_slice()),
+            None => self.output_buffer.extend(new_buffer),
+        }
+
+        self.local_buffer_l.clear();
+        self.local_buffer_r.clear();
+    }
+}
+
+/// Samples not yet sent to the output are dropped when loading, rather than played out of context.
+impl SaveState for Apu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.dot_counter.save_state(state);
+    }
 
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.dot_counter.load_state(state)?;
         self.local_buffer_l.clear();
         self.local_buffer_r.clear();
+        Ok(())
     }
 }
\ No newline at end of file
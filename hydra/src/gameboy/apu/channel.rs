@@ -1,8 +1,14 @@
 use std::sync::Arc;
 
-use cpal::{Sample, Stream};
+use crate::{common::{bit::BitVec, errors::HydraIOError, timing::{DynamicModuloCounter, DynamicOverflowCounter, ModuloCounter, OverflowCounter, Resettable}}, deserialize, gameboy::memory::MemoryMapped, save_state_enum, save_state_fields, serialize};
 
-use crate::{audio::Audio, common::{audio, bit::BitVec, errors::HydraIOError, timing::{DynamicModuloCounter, DynamicOverflowCounter, ModuloCounter, OverflowCounter, Resettable}}, deserialize, gameboy::memory::MemoryMapped, serialize};
+/// The level of a silent channel.
+const EQUILIBRIUM: f32 = 0.0;
+
+/// Converts an unsigned 8-bit sample into the range [-1.0, 1.0), centered on `EQUILIBRIUM`.
+fn to_analog(digital: u8) -> f32 {
+    (digital as f32 - 128.0) / 128.0
+}
 
 pub struct Pulse {
     enabled: bool,
@@ -23,6 +29,21 @@ pub struct Pulse {
     length_timer_enabled: bool,
 }
 
+save_state_fields!(Pulse {
+    enabled,
+    period_timer,
+    period_sweep_direction,
+    period_sweep_timer,
+    period_sweep_step,
+    volume,
+    volume_sweep_timer,
+    volume_sweep_direction,
+    duty_index,
+    wavetable_index,
+    length_timer,
+    length_timer_enabled,
+});
+
 impl Pulse {
     const WAVETABLES: [[u8; 8]; 4] = [
         [0x0, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF],
@@ -71,10 +92,10 @@ impl Pulse {
             }
 
             let digital = Self::WAVETABLES[self.duty_index][self.wavetable_index.value];
-            let analog = (digital * 0x11).to_sample::<f32>() * -1.0;
-            analog.mul_amp(self.volume.current as f32 / 0xF as f32)
+            let analog = to_analog(digital * 0x11) * -1.0;
+            analog * (self.volume.current as f32 / 0xF as f32)
         } else {
-            Sample::EQUILIBRIUM
+            EQUILIBRIUM
         }
     }
 
@@ -219,6 +240,8 @@ enum Direction {
     Decreasing = -1
 }
 
+save_state_enum!(Direction { Increasing, Decreasing });
+
 impl From<bool> for Direction {
     fn from(value: bool) -> Self {
         match value {
@@ -254,6 +277,17 @@ pub struct Wave {
     length_timer_enabled: bool,
 }
 
+save_state_fields!(Wave {
+    dac_enabled,
+    enabled,
+    period_timer,
+    volume,
+    wavetable,
+    wavetable_index,
+    length_timer,
+    length_timer_enabled,
+});
+
 impl Wave {
     pub fn new() -> Self {
         Wave { 
@@ -280,9 +314,9 @@ impl Wave {
                 self.wavetable_index.increment();
             }
             self.period_timer.reset_value.reset();
-            ((self.wavetable[self.wavetable_index.value] >> Self::VOLUME_SHIFT_TABLE[self.volume as usize]) * 0x11).to_sample::<f32>()
+            to_analog((self.wavetable[self.wavetable_index.value] >> Self::VOLUME_SHIFT_TABLE[self.volume as usize]) * 0x11)
         } else {
-            Sample::EQUILIBRIUM
+            EQUILIBRIUM
         }
     }
 
@@ -398,6 +432,21 @@ pub struct Noise {
     shifted_out: bool,
 }
 
+save_state_fields!(Noise {
+    enabled,
+    volume,
+    volume_sweep_timer,
+    volume_sweep_direction,
+    initial_shift,
+    initial_divider,
+    lfsr_timer,
+    lfsr,
+    use_bit_7,
+    length_timer,
+    length_timer_enabled,
+    shifted_out,
+});
+
 impl Noise {
     pub fn new() -> Self {
         Noise { 
@@ -433,9 +482,9 @@ impl Noise {
                 self.lfsr >>= 1;
                 self.shifted_out = bit0;
             }
-            (!self.shifted_out as u8 * self.volume.current * 0x11).to_sample::<f32>()
+            to_analog(!self.shifted_out as u8 * self.volume.current * 0x11)
         } else {
-            Sample::EQUILIBRIUM
+            EQUILIBRIUM
         }
     }
 
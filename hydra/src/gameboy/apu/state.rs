@@ -1,8 +1,7 @@
 use std::rc::Rc;
 
-use cpal::Sample;
 
-use crate::{common::errors::HydraIOError, deserialize, gameboy::{Model, apu::channel::{Noise, Pulse, PulseType, Wave}, memory::MemoryMapped}, serialize};
+use crate::{common::errors::HydraIOError, deserialize, gameboy::{Model, apu::channel::{Noise, Pulse, PulseType, Wave}, memory::MemoryMapped}, save_state_fields, serialize};
 
 pub struct ApuState {
     model: Rc<Model>,
@@ -25,6 +24,22 @@ pub struct ApuState {
     amplitudes_r: [u8; 4],
 }
 
+save_state_fields!(ApuState {
+    master_enable,
+    master_amp_l,
+    master_amp_r,
+    div,
+    pulse1,
+    pulse2,
+    wave,
+    noise,
+    vin_amp_l,
+    vin_amp_r,
+    prev_samples,
+    amplitudes_l,
+    amplitudes_r,
+});
+
 impl ApuState {
     pub fn new(model: Rc<Model>) -> Self {
         ApuState {
@@ -81,8 +96,8 @@ impl ApuState {
             }
         }
 
-        [self.prev_samples.iter().enumerate().fold(0.0, |l, (index, sample)| l + sample.mul_amp(Self::amp_from_u1(self.amplitudes_l[index]))).mul_amp(Self::amp_from_u3(self.master_amp_l) / 4.0),
-         self.prev_samples.iter().enumerate().fold(0.0, |r, (index, sample)| r + sample.mul_amp(Self::amp_from_u1(self.amplitudes_r[index]))).mul_amp(Self::amp_from_u3(self.master_amp_r) / 4.0)]
+        [self.prev_samples.iter().enumerate().fold(0.0, |l, (index, sample)| l + sample * Self::amp_from_u1(self.amplitudes_l[index])) * (Self::amp_from_u3(self.master_amp_l) / 4.0),
+         self.prev_samples.iter().enumerate().fold(0.0, |r, (index, sample)| r + sample * Self::amp_from_u1(self.amplitudes_r[index])) * (Self::amp_from_u3(self.master_amp_r) / 4.0)]
     }
 
     fn amp_from_u1(u1: u8) -> f32 {
@@ -1,3 +1,4 @@
+pub mod disassembler;
 mod opcode;
 
 use std::{cell::RefCell, collections::VecDeque, rc::Rc, time::Duration};
@@ -6,10 +7,10 @@ use futures::FutureExt;
 
 use crate::{
     common::{bit::BitVec, timing::{DelayedTickCounter, ModuloCounter}}, gameboy::{
-        AGBRevision, CGBRevision, GBRevision, GameBoy, GbMode, Joypad, Model, SGBRevision, cpu::opcode::{CondOperand, ConstOperand16, IntOperand, OpcodeFn}, interrupt::{Interrupt, InterruptEnable, InterruptFlags}, memory::{
-            MemoryMap, rom::{Rom, RomHeader}
+        AGBRevision, CGBRevision, GBRevision, GameBoy, GbMode, Model, SGBRevision, cpu::opcode::{CondOperand, ConstOperand16, IntOperand, OpcodeFn}, interrupt::{Interrupt, InterruptEnable, InterruptFlags}, memory::{
+            BusAccessor, MemoryMap, rom::{Rom, RomHeader}
         }, timer::MasterTimer
-    },
+    }, save_state_enum, save_state_fields,
 };
 
 pub enum Register8 {
@@ -109,7 +110,7 @@ impl Cpu {
                 let mut hl_bytes = [0x7C, 0x00];
                 if header.has_publisher_rnd1() {
                     // If either licensee code is 0x01, B = sum of all title bytes
-                    b = header.get_title().iter().sum();
+                    b = header.get_title().iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
                     if b == 0x43 || b == 0x58 {
                         // And, check special cases for HL
                         hl_bytes = [0x1A, 0x99];
@@ -126,7 +127,7 @@ impl Cpu {
                 let mut f = 0b00000000;
                 if header.has_publisher_rnd1() {
                     // If either licensee code is 0x01, B = sum of all title bytes
-                    b = header.get_title().iter().sum();
+                    b = header.get_title().iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
                     if b & 0b1111 == 0 {
                         // Last op is an INC; set h flag...
                         f |= 0b0010 << 4;
@@ -176,6 +177,26 @@ impl Cpu {
         }
     }
 
+    /// Builds a CPU as it is at power-on, with every register cleared, for running a boot ROM from 0x0000.
+    pub fn power_on() -> Self {
+        Cpu {
+            mode: CpuMode::Normal,
+
+            af: [0x00, 0x00],
+            bc: [0x00, 0x00],
+            de: [0x00, 0x00],
+            hl: [0x00, 0x00],
+            sp: 0x0000,
+            pc: 0x0000,
+            ir: 0x00,
+
+            ime: false,
+            ei_queue: [false, false],
+            cycles_until_halt_bug: None,
+            unhalt_timer: DelayedTickCounter::new(None),
+        }
+    }
+
     pub fn queue_ime(&mut self) {
         self.ei_queue[1] = true;
     }
@@ -243,23 +264,14 @@ impl Cpu {
                 cpu_inner.pc = jump_addr;
             });
         } else {
+            // Log the state the instruction starts from, before fetching it
+            if debug && let Some(trace) = &mut system.trace && let Err(e) = trace.log(self, &system.memory) {
+                println!("Stopped tracing: {}", e);
+                system.trace = None;
+            }
+
             // Fetch instruction from memory
             self.ir = self.step_u8(system);
-            if debug && system.dump_cpu {
-                println!(
-                    "{:#06X}: {:02X} {:02X} {:02X}  ---  A: {:#04X}   F: {:08b}   BC: {:#06X}   DE: {:#06X}   HL: {:#06X}   SP: {:#06X}",
-                    self.pc - 1,
-                    self.ir,
-                    system.memory.read_u8(self.pc, false),
-                    system.memory.read_u8(self.pc + 1, false),
-                    self.af[1],
-                    self.af[0],
-                    u16::from_le_bytes(self.bc),
-                    u16::from_le_bytes(self.de),
-                    u16::from_le_bytes(self.hl),
-                    self.sp
-                );
-            }
             let op_index = self.ir as usize;
             return Box::new(move |cpu_inner, system_inner| Self::OP_TABLE[op_index](cpu_inner, system_inner));
         }
@@ -268,7 +280,7 @@ impl Cpu {
     #[inline(always)]
     fn step_u8(&mut self, system: &mut GameBoy) -> u8 {
         system.cycle_components();
-        let result = system.memory.read_u8(self.pc, false);
+        let result = system.memory.read_u8(self.pc, BusAccessor::Cpu);
         self.pc = self.pc.wrapping_add(1);
         result
     }
@@ -276,48 +288,113 @@ impl Cpu {
     #[inline(always)]
     fn read_u8(&self, address: u16, system: &mut GameBoy) -> u8 {
         system.cycle_components();
-        let result = system.memory.read_u8(address, false);
+        let result = system.memory.read_u8(address, BusAccessor::Cpu);
         result
     }
 
     #[inline(always)]
     fn write_u8(&self, address: u16, value: u8, system: &mut GameBoy) -> () {
         system.cycle_components();
-        system.memory.write_u8(value, address);
-    }
-
-    pub fn coro(&mut self, system: &mut GameBoy, debug: bool) {
-        while system.is_running() {
-            // Skip iterations if halted or stopped
-            self.mode = match self.mode {
-                CpuMode::Normal => CpuMode::Normal,
-                CpuMode::Halted if self.interrupt_pending(system) || self.unhalt_timer.increment() => CpuMode::Normal,
-                CpuMode::Stopped if system.memory.interrupt_flags.is_requested(Interrupt::Joypad) => CpuMode::Normal,
-                _ => {
-                    system.cycle_components();
-                    continue;  
-                }
-            };
-
-            // If not halted, process HDMA. Skip this cycle during HDMA transfer.
-            // TODO: Remove unsafe block?
-            let hdma_active = unsafe {
-                let memory: *mut MemoryMap = &mut system.memory;
-                (*memory).hdma.tick(&mut system.memory) 
-            };
-            if hdma_active {continue;}
-
-            // Fetch cycle
-            let pc_old = self.pc;
-            let next = self.fetch(system, debug);
-
-            // Execute cycle(s)
-            next(self, system);
-            self.refresh_interrupt_handler(pc_old);
+        system.memory.write_u8(value, address, BusAccessor::Cpu);
+    }
+
+    /// Advances the CPU by one step: a full instruction (or interrupt dispatch), or a single idle cycle while halted, stopped or paused for HDMA.
+    /// Returns whether an instruction was executed.
+    pub fn step(&mut self, system: &mut GameBoy, debug: bool) -> bool {
+        // Skip iterations if halted or stopped
+        self.mode = match self.mode {
+            CpuMode::Normal => CpuMode::Normal,
+            CpuMode::Halted if self.interrupt_pending(system) || self.unhalt_timer.increment() => CpuMode::Normal,
+            CpuMode::Stopped if system.memory.interrupt_flags.is_requested(Interrupt::Joypad) => CpuMode::Normal,
+            _ => {
+                system.cycle_components();
+                return false;
+            }
+        };
+
+        // If not halted, process HDMA. Skip this cycle during HDMA transfer.
+        // TODO: Remove unsafe block?
+        let hdma_active = unsafe {
+            let memory: *mut MemoryMap = &mut system.memory;
+            (*memory).hdma.tick(&mut system.memory) 
+        };
+        if hdma_active {return false;}
+
+        // Fetch cycle
+        let pc_old = self.pc;
+        let next = self.fetch(system, debug);
+
+        // Execute cycle(s)
+        next(self, system);
+        self.refresh_interrupt_handler(pc_old);
+        true
+    }
+
+    /// Returns whether the CPU is idling in HALT or STOP.
+    pub fn is_sleeping(&self) -> bool {
+        !matches!(self.mode, CpuMode::Normal)
+    }
+
+    pub fn get_register8(&self, register: Register8) -> u8 {
+        match register {
+            Register8::A => self.af[1],
+            Register8::F => self.af[0],
+            Register8::B => self.bc[1],
+            Register8::C => self.bc[0],
+            Register8::D => self.de[1],
+            Register8::E => self.de[0],
+            Register8::H => self.hl[1],
+            Register8::L => self.hl[0],
+        }
+    }
+
+    pub fn set_register8(&mut self, register: Register8, value: u8) {
+        match register {
+            Register8::A => self.af[1] = value,
+            Register8::F => self.af[0] = value & 0xF0,
+            Register8::B => self.bc[1] = value,
+            Register8::C => self.bc[0] = value,
+            Register8::D => self.de[1] = value,
+            Register8::E => self.de[0] = value,
+            Register8::H => self.hl[1] = value,
+            Register8::L => self.hl[0] = value,
+        }
+    }
+
+    pub fn get_register16(&self, register: Register16) -> u16 {
+        match register {
+            Register16::AF => u16::from_le_bytes(self.af),
+            Register16::BC => u16::from_le_bytes(self.bc),
+            Register16::DE => u16::from_le_bytes(self.de),
+            Register16::HL => u16::from_le_bytes(self.hl),
+            Register16::SP => self.sp,
+            Register16::PC => self.pc,
         }
     }
+
+    pub fn set_register16(&mut self, register: Register16, value: u16) {
+        match register {
+            Register16::AF => self.af = u16::to_le_bytes(value & 0xFFF0),
+            Register16::BC => self.bc = u16::to_le_bytes(value),
+            Register16::DE => self.de = u16::to_le_bytes(value),
+            Register16::HL => self.hl = u16::to_le_bytes(value),
+            Register16::SP => self.sp = value,
+            Register16::PC => self.pc = value,
+        }
+    }
+
+    /// Returns the opcode of the most recently fetched instruction.
+    pub fn get_ir(&self) -> u8 {
+        self.ir
+    }
+
+    pub fn is_ime_enabled(&self) -> bool {
+        self.ime
+    }
 }
 
+save_state_fields!(Cpu { mode, af, bc, de, hl, sp, pc, ir, ime, ei_queue, cycles_until_halt_bug, unhalt_timer });
+
 // Opcode Helpers
 macro_rules! _offset {
     (z) => {
@@ -897,4 +974,6 @@ enum CpuMode {
     Normal,
     Halted,
     Stopped,
-}
\ No newline at end of file
+}
+
+save_state_enum!(CpuMode { Normal, Halted, Stopped });
\ No newline at end of file
@@ -0,0 +1,246 @@
+use std::fmt;
+
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const REGISTER_PAIRS: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const STACK_PAIRS: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const INDIRECT_PAIRS: [&str; 4] = ["(BC)", "(DE)", "(HL+)", "(HL-)"];
+const CONDITIONS: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU_OPS: [&str; 8] = ["ADD", "ADC", "SUB", "SBC", "AND", "XOR", "OR", "CP"];
+const ACCUMULATOR_OPS: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+const SHIFT_OPS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const BIT_OPS: [&str; 3] = ["BIT", "RES", "SET"];
+
+/// A single decoded SM83 instruction.
+pub struct Instruction {
+    pub address: u16,
+    pub length: u16,
+    pub text: String,
+    /// The address this instruction jumps to or accesses, if it's given as an operand.
+    pub operand_address: Option<u16>,
+    bytes: [u8; 3],
+}
+
+impl Instruction {
+    /// Returns the bytes this instruction was decoded from.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.length as usize]
+    }
+
+    /// Returns this instruction's text, with its address operand replaced by a label.
+    pub fn text_with_label(&self, label: &str) -> String {
+        match self.operand_address {
+            Some(address) => self.text.replacen(&format!("${:04X}", address), label, 1),
+            None => self.text.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Decodes the instruction at `address`, reading its bytes through `read`.
+/// Relative jumps are shown with their absolute targets, and opcodes the CPU doesn't implement as `DB` directives.
+pub fn disassemble(address: u16, read: impl Fn(u16) -> u8) -> Instruction {
+    let opcode = read(address);
+    let bytes = [opcode, read(address.wrapping_add(1)), read(address.wrapping_add(2))];
+    let n8 = bytes[1];
+    let n16 = u16::from_le_bytes([bytes[1], bytes[2]]);
+    let relative_target = address.wrapping_add(2).wrapping_add(n8 as i8 as u16);
+
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 0b111, opcode & 0b111);
+    let (p, q) = ((y >> 1) as usize, y & 1 == 1);
+    let (y, z) = (y as usize, z as usize);
+
+    let (length, text) = match (x, z) {
+        (0, 0) => match y {
+            0 => (1, "NOP".to_string()),
+            1 => (3, format!("LD (${:04X}), SP", n16)),
+            2 => (2, "STOP".to_string()),
+            3 => (2, format!("JR ${:04X}", relative_target)),
+            _ => (2, format!("JR {}, ${:04X}", CONDITIONS[y - 4], relative_target)),
+        },
+        (0, 1) if !q => (3, format!("LD {}, ${:04X}", REGISTER_PAIRS[p], n16)),
+        (0, 1) => (1, format!("ADD HL, {}", REGISTER_PAIRS[p])),
+        (0, 2) if !q => (1, format!("LD {}, A", INDIRECT_PAIRS[p])),
+        (0, 2) => (1, format!("LD A, {}", INDIRECT_PAIRS[p])),
+        (0, 3) if !q => (1, format!("INC {}", REGISTER_PAIRS[p])),
+        (0, 3) => (1, format!("DEC {}", REGISTER_PAIRS[p])),
+        (0, 4) => (1, format!("INC {}", REGISTERS[y])),
+        (0, 5) => (1, format!("DEC {}", REGISTERS[y])),
+        (0, 6) => (2, format!("LD {}, ${:02X}", REGISTERS[y], n8)),
+        (0, _) => (1, ACCUMULATOR_OPS[y].to_string()),
+
+        (1, 6) if y == 6 => (1, "HALT".to_string()),
+        (1, _) => (1, format!("LD {}, {}", REGISTERS[y], REGISTERS[z])),
+
+        (2, _) => (1, format!("{} {}", ALU_OPS[y], REGISTERS[z])),
+
+        (_, 0) => match y {
+            0..=3 => (1, format!("RET {}", CONDITIONS[y])),
+            4 => (2, format!("LDH (${:04X}), A", 0xFF00 | n8 as u16)),
+            5 => (2, format!("ADD SP, {}", signed_offset(n8))),
+            6 => (2, format!("LDH A, (${:04X})", 0xFF00 | n8 as u16)),
+            _ => (2, format!("LD HL, SP{}", signed_offset(n8))),
+        },
+        (_, 1) if !q => (1, format!("POP {}", STACK_PAIRS[p])),
+        (_, 1) => (1, ["RET", "RETI", "JP HL", "LD SP, HL"][p].to_string()),
+        (_, 2) => match y {
+            0..=3 => (3, format!("JP {}, ${:04X}", CONDITIONS[y], n16)),
+            4 => (1, "LDH (C), A".to_string()),
+            5 => (3, format!("LD (${:04X}), A", n16)),
+            6 => (1, "LDH A, (C)".to_string()),
+            _ => (3, format!("LD A, (${:04X})", n16)),
+        },
+        (_, 3) => match y {
+            0 => (3, format!("JP ${:04X}", n16)),
+            1 => (2, disassemble_cb(n8)),
+            6 => (1, "DI".to_string()),
+            7 => (1, "EI".to_string()),
+            _ => (1, format!("DB ${:02X}", opcode)),
+        },
+        (_, 4) if y < 4 => (3, format!("CALL {}, ${:04X}", CONDITIONS[y], n16)),
+        (_, 5) if !q => (1, format!("PUSH {}", STACK_PAIRS[p])),
+        (_, 5) if p == 0 => (3, format!("CALL ${:04X}", n16)),
+        (_, 6) => (2, format!("{} ${:02X}", ALU_OPS[y], n8)),
+        (_, 7) => (1, format!("RST ${:02X}", y * 8)),
+        _ => (1, format!("DB ${:02X}", opcode)),
+    };
+    let operand_address = match opcode {
+        0x18 | 0x20 | 0x28 | 0x30 | 0x38 => Some(relative_target),
+        0x08 | 0xC2 | 0xC3 | 0xC4 | 0xCA | 0xCC | 0xCD | 0xD2 | 0xD4 | 0xDA | 0xDC | 0xEA | 0xFA => Some(n16),
+        0xE0 | 0xF0 => Some(0xFF00 | n8 as u16),
+        _ => None,
+    };
+    Instruction { address, length, text, operand_address, bytes }
+}
+
+fn disassemble_cb(opcode: u8) -> String {
+    let (x, y, z) = ((opcode >> 6) as usize, ((opcode >> 3) & 0b111) as usize, (opcode & 0b111) as usize);
+    match x {
+        0 => format!("{} {}", SHIFT_OPS[y], REGISTERS[z]),
+        _ => format!("{} {}, {}", BIT_OPS[x - 1], y, REGISTERS[z]),
+    }
+}
+
+fn signed_offset(value: u8) -> String {
+    match (value as i8) < 0 {
+        true => format!("-${:02X}", (value as i8).unsigned_abs()),
+        false => format!("+${:02X}", value),
+    }
+}
+
+/// Decodes a block of raw bytes (such as a ROM bank) as consecutive instructions, as if it were mapped starting at `base`.
+/// An instruction cut off by the end of the block is shown as `DB` directives instead.
+pub fn disassemble_bytes(bytes: &[u8], base: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = base.wrapping_add(offset as u16);
+        let mut instruction = disassemble(address, |at| bytes.get(at.wrapping_sub(base) as usize).copied().unwrap_or(0));
+        if offset + instruction.length as usize > bytes.len() {
+            instruction = Instruction { address, length: 1, text: format!("DB ${:02X}", bytes[offset]), operand_address: None, bytes: [bytes[offset], 0, 0] };
+        }
+        offset += instruction.length as usize;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes `bytes` as if they were mapped at `address`, with zeroes after them.
+    fn decode(address: u16, bytes: &[u8]) -> Instruction {
+        disassemble(address, |at| bytes.get(at.wrapping_sub(address) as usize).copied().unwrap_or(0))
+    }
+
+    #[test]
+    fn base_opcodes() {
+        let cases: &[(u16, &[u8], &str, u16)] = &[
+            (0x0100, &[0x00], "NOP", 1),
+            (0x0100, &[0x01, 0x34, 0x12], "LD BC, $1234", 3),
+            (0x0100, &[0x08, 0x00, 0xC0], "LD ($C000), SP", 3),
+            (0x0100, &[0x10, 0x00], "STOP", 2),
+            (0x0100, &[0x22], "LD (HL+), A", 1),
+            (0x0100, &[0x3E, 0x42], "LD A, $42", 2),
+            (0x0100, &[0x40], "LD B, B", 1),
+            (0x0100, &[0x7E], "LD A, (HL)", 1),
+            (0x0100, &[0x76], "HALT", 1),
+            (0x0100, &[0xAF], "XOR A", 1),
+            (0x0100, &[0xFE, 0x90], "CP $90", 2),
+            (0x0100, &[0xC0], "RET NZ", 1),
+            (0x0100, &[0xD9], "RETI", 1),
+            (0x0100, &[0xE9], "JP HL", 1),
+            (0x0100, &[0xF1], "POP AF", 1),
+            (0x0100, &[0xC3, 0x50, 0x01], "JP $0150", 3),
+            (0x0100, &[0xC4, 0x34, 0x12], "CALL NZ, $1234", 3),
+            (0x0100, &[0xCD, 0x00, 0x40], "CALL $4000", 3),
+            (0x0100, &[0xE0, 0x44], "LDH ($FF44), A", 2),
+            (0x0100, &[0xF0, 0x00], "LDH A, ($FF00)", 2),
+            (0x0100, &[0xE2], "LDH (C), A", 1),
+            (0x0100, &[0xFA, 0x00, 0xD0], "LD A, ($D000)", 3),
+            (0x0100, &[0xE8, 0xFC], "ADD SP, -$04", 2),
+            (0x0100, &[0xF8, 0x05], "LD HL, SP+$05", 2),
+            (0x0100, &[0xFF], "RST $38", 1),
+        ];
+        for &(address, bytes, text, length) in cases {
+            let instruction = decode(address, bytes);
+            assert_eq!((instruction.text.as_str(), instruction.length), (text, length), "decoding {:02X?}", bytes);
+            assert_eq!(instruction.bytes(), &bytes[..length as usize]);
+        }
+    }
+
+    #[test]
+    fn relative_jumps() {
+        let cases: &[(u16, &[u8], &str)] = &[
+            (0x0150, &[0x18, 0xFE], "JR $0150"),
+            (0x0200, &[0x20, 0x05], "JR NZ, $0207"),
+            (0x0100, &[0x38, 0x80], "JR C, $0082"),
+            (0xFFFE, &[0x18, 0x10], "JR $0010"),
+        ];
+        for &(address, bytes, text) in cases {
+            let instruction = decode(address, bytes);
+            assert_eq!(instruction.text, text, "decoding {:02X?} at ${:04X}", bytes, address);
+            assert_eq!(instruction.operand_address.map(|target| format!("${:04X}", target)), text.rsplit(' ').next().map(str::to_string));
+        }
+    }
+
+    #[test]
+    fn cb_opcodes() {
+        let cases: &[(u8, &str)] = &[
+            (0x00, "RLC B"),
+            (0x11, "RL C"),
+            (0x2E, "SRA (HL)"),
+            (0x37, "SWAP A"),
+            (0x7C, "BIT 7, H"),
+            (0x86, "RES 0, (HL)"),
+            (0xFF, "SET 7, A"),
+        ];
+        for &(opcode, text) in cases {
+            let instruction = decode(0x0100, &[0xCB, opcode]);
+            assert_eq!((instruction.text.as_str(), instruction.length), (text, 2), "decoding CB {:02X}", opcode);
+        }
+    }
+
+    #[test]
+    fn illegal_opcodes() {
+        for opcode in [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD] {
+            let instruction = decode(0x0100, &[opcode, 0x12, 0x34]);
+            assert_eq!((instruction.text, instruction.length), (format!("DB ${:02X}", opcode), 1));
+        }
+    }
+
+    #[test]
+    fn labels_and_blocks() {
+        assert_eq!(decode(0x0200, &[0x20, 0x05]).text_with_label("Main.loop"), "JR NZ, Main.loop");
+        assert_eq!(decode(0x0100, &[0xE0, 0x40]).text_with_label("rLCDC"), "LDH (rLCDC), A");
+
+        // The second instruction is cut off by the end of the block
+        let texts: Vec<String> = disassemble_bytes(&[0x00, 0x3E], 0x4000).iter().map(ToString::to_string).collect();
+        assert_eq!(texts, ["NOP", "DB $3E"]);
+    }
+}
@@ -0,0 +1,646 @@
+use std::{fmt, path::PathBuf};
+
+use crate::{common::errors::HydraIOError, gameboy::{GameBoy, GbMode, SymbolTable, TraceLogger, cpu::{Cpu, Register8, Register16, disassembler::{self, Instruction}}, memory::{BusAccessor, MemoryMap}}};
+
+/// Stops execution when the CPU is about to run the instruction at `address`.
+/// If a bank is given, the breakpoint only applies while that bank is mapped there.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub bank: Option<usize>,
+}
+
+impl Breakpoint {
+    fn is_hit(&self, pc: u16, memory: &MemoryMap) -> bool {
+        self.address == pc && self.bank.is_none_or(|bank| memory.get_bank(pc) == bank)
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.address),
+            None => write!(f, "{:04X}", self.address),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Stops execution when the given range of addresses is accessed, by the CPU or either kind of DMA.
+/// If a value is given, the watchpoint only applies when that value is read or written.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+    pub value: Option<u8>,
+}
+
+impl Watchpoint {
+    pub(super) fn is_hit(&self, address: u16, value: u8, is_write: bool) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Read => !is_write,
+            WatchKind::Write => is_write,
+            WatchKind::ReadWrite => true,
+        };
+        kind_matches && (self.start..=self.end).contains(&address) && self.value.is_none_or(|expected| expected == value)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::ReadWrite => "read/write",
+        };
+        write!(f, "{} {:04X}", kind, self.start)?;
+        if self.end != self.start {
+            write!(f, "-{:04X}", self.end)?;
+        }
+        match self.value {
+            Some(value) => write!(f, " == {:02X}", value),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An access which triggered a watchpoint, as recorded by the memory map.
+#[derive(Copy, Clone)]
+pub struct WatchHit {
+    pub(super) index: usize,
+    pub(super) address: u16,
+    pub(super) bank: usize,
+    pub(super) value: u8,
+    pub(super) accessor: BusAccessor,
+    pub(super) is_write: bool,
+}
+
+enum DebugState {
+    Running,
+    Paused,
+    /// Running until the instruction after a CALL or RST, once its stack frame has been popped.
+    StepOver { return_address: u16, sp: u16 },
+    /// Running until a return pops the stack frame that was current when stepping out began.
+    StepOut { sp: u16 },
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    state: DebugState,
+    stop_reason: Option<String>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger { breakpoints: Vec::new(), state: DebugState::Running, stop_reason: None }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(self.state, DebugState::Paused)
+    }
+
+    pub fn get_breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    /// Returns why execution last stopped, if it hasn't been reported yet.
+    pub fn take_stop_reason(&mut self) -> Option<String> {
+        self.stop_reason.take()
+    }
+
+    fn stop(&mut self, reason: String) {
+        self.state = DebugState::Paused;
+        self.stop_reason = Some(match self.stop_reason.take() {
+            Some(earlier) => format!("{}\n{}", earlier, reason),
+            None => reason,
+        });
+    }
+
+    /// Checks whether execution should stop, now that the CPU has finished a step starting at `step_pc`.
+    /// `executed` is whether that step ran an instruction, rather than idling. Returns whether the machine is paused.
+    pub(super) fn after_step(&mut self, cpu: &Cpu, memory: &MemoryMap, step_pc: u16, executed: bool) -> bool {
+        if let Some(hit) = memory.watch_hit.take() {
+            let (access, preposition) = match hit.is_write {
+                true => ("write", "to"),
+                false => ("read", "from"),
+            };
+            self.stop(format!(
+                "Hit watchpoint {}: {} {} of {:02X} {} {} (PC={})",
+                hit.index, hit.accessor, access, hit.value, preposition, describe_location(memory, hit.bank, hit.address), memory.describe_address(step_pc),
+            ));
+        }
+        if !executed {
+            return self.is_paused();
+        }
+
+        let pc = cpu.get_register16(Register16::PC);
+        let sp = cpu.get_register16(Register16::SP);
+        if let Some(index) = self.breakpoints.iter().position(|breakpoint| breakpoint.is_hit(pc, memory)) {
+            self.stop(format!("Hit breakpoint {} at {}", index, memory.describe_address(pc)));
+        }
+
+        match self.state {
+            DebugState::StepOver { return_address, sp: start_sp } if pc == return_address && sp >= start_sp => {
+                self.stop(format!("Stepped over to {}", memory.describe_address(pc)));
+            }
+            DebugState::StepOut { sp: start_sp } if is_return(cpu.get_ir()) && sp > start_sp => {
+                self.stop(format!("Stepped out to {}", memory.describe_address(pc)));
+            }
+            _ => {}
+        }
+        self.is_paused()
+    }
+}
+
+/// Formats a location as `BB:AAAA`, followed by the label it falls under, if any.
+fn describe_location(memory: &MemoryMap, bank: usize, address: u16) -> String {
+    match memory.symbols.locate(bank, address) {
+        Some(label) => format!("{:02X}:{:04X} <{}>", bank, address, label),
+        None => format!("{:02X}:{:04X}", bank, address),
+    }
+}
+
+fn describe_breakpoint(memory: &MemoryMap, breakpoint: &Breakpoint) -> String {
+    let bank = breakpoint.bank.unwrap_or_else(|| memory.get_bank(breakpoint.address));
+    match memory.symbols.locate(bank, breakpoint.address) {
+        Some(label) => format!("{} <{}>", breakpoint, label),
+        None => breakpoint.to_string(),
+    }
+}
+
+const fn is_return(opcode: u8) -> bool {
+    matches!(opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9)
+}
+
+/// Returns the length of the instruction if it's a CALL or RST, i.e. one that returns to the instruction after it.
+const fn call_length(opcode: u8) -> Option<u16> {
+    match opcode {
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => Some(3),
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => Some(1),
+        _ => None,
+    }
+}
+
+const HELP: &str = "\
+Addresses, banks and values are in hexadecimal, optionally prefixed with $ or 0x.
+Where noted, a label from the loaded symbols can be given in place of an address.
+Labels win over bare hexadecimal, so prefix an address with $ if a label shares its name (e.g. $Fade).
+  break <[bank:]addr|label>
+                         Add a breakpoint, optionally only while the given bank is mapped
+  delete <n>             Remove breakpoint n
+  breakpoints            List all breakpoints
+  watch <r|w|rw> <start>[-end] [value]
+                         Add a watchpoint on reads and/or writes to a range, optionally only of the given value
+  unwatch <n>            Remove watchpoint n
+  watchpoints            List all watchpoints
+  disasm [[bank:]addr|label] [count]
+                         Disassemble instructions at PC or the given address, reading banked ROM directly if a bank is given
+  symbols <file>         Load labels from an RGBDS .sym file
+  continue | c           Resume execution
+  pause | p              Pause execution
+  step | s               Run a single instruction, stepping into calls
+  next | n               Run a single instruction, stepping over calls
+  finish | f             Run until the current function returns
+  regs | r               Show registers and flags
+  set <reg> <value>      Set a register (a, f, b, c, d, e, h, l, af, bc, de, hl, sp or pc)
+  flag <z|n|h|c> <0|1>   Set or reset a flag
+  trace on [file]        Log every instruction in Game Boy Doctor's format (to a .log file next to the ROM by default)
+  trace off              Stop logging instructions
+  trace pc <start-end|any>
+                         Only log instructions within the given range of addresses
+  trace bank <bank|any>  Only log instructions run from the given bank
+  trace labels <on|off>  Append labels to logged instructions (off by default, keeping logs comparable with Game Boy Doctor's)
+  palettes               List every palette's colors, as shades on DMG or RGB555 on CGB
+  palette <name> <n> <value>
+                         Set color n of a palette (e.g. BGP, OBP1, BG0 or OBJ7)
+  gdb <port|off>         Listen for a GDB client on the given localhost port
+  help                   Show this message";
+
+impl GameBoy {
+    /// Pauses the machine after the current instruction, stopping `run_frame` and `run_cycles` partway through if necessary.
+    pub fn debug_pause(&mut self) {
+        self.debugger.state = DebugState::Paused;
+    }
+
+    pub fn debug_continue(&mut self) {
+        self.debugger.state = DebugState::Running;
+    }
+
+    /// Runs a single instruction, following any calls, then stays paused.
+    pub fn debug_step_into(&mut self) {
+        self.debugger.state = DebugState::Paused;
+        self.step_instruction();
+    }
+
+    /// Runs a single instruction, treating calls as one instruction. Calls resume execution until they return (or a breakpoint is hit).
+    pub fn debug_step_over(&mut self) {
+        let cpu = self.cpu.as_ref().unwrap();
+        let pc = cpu.get_register16(Register16::PC);
+        match call_length(self.memory.debug_read_u8(pc)) {
+            Some(length) => {
+                let sp = cpu.get_register16(Register16::SP);
+                self.debugger.state = DebugState::StepOver { return_address: pc.wrapping_add(length), sp };
+            }
+            None => self.debug_step_into(),
+        }
+    }
+
+    /// Resumes execution until the current function returns (or a breakpoint is hit).
+    pub fn debug_step_out(&mut self) {
+        let sp = self.cpu.as_ref().unwrap().get_register16(Register16::SP);
+        self.debugger.state = DebugState::StepOut { sp };
+    }
+
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        &self.memory.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.memory.watchpoints.contains(&watchpoint) {
+            self.memory.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.memory.watchpoints.len()).then(|| self.memory.watchpoints.remove(index))
+    }
+
+    pub fn get_debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Disassembles `count` consecutive instructions from live memory, starting at `address`.
+    pub fn disassemble(&self, address: u16, count: usize) -> Vec<Instruction> {
+        let mut instructions = Vec::with_capacity(count);
+        let mut address = address;
+        for _ in 0..count {
+            let instruction = disassembler::disassemble(address, |at| self.memory.debug_read_u8(at));
+            address = address.wrapping_add(instruction.length);
+            instructions.push(instruction);
+        }
+        instructions
+    }
+
+    /// Disassembles an entire ROM bank, whether or not it's currently mapped. Returns `None` if the ROM has no such bank.
+    pub fn disassemble_rom_bank(&self, bank: usize) -> Option<Vec<Instruction>> {
+        let base = match bank {
+            0 => 0x0000,
+            _ => 0x4000,
+        };
+        self.rom.get_bank(bank).map(|bytes| disassembler::disassemble_bytes(bytes, base))
+    }
+
+    /// Formats an instruction as a line of a listing, preceded by a line naming its label (if any).
+    /// `operand_bank` gives the bank an address operand refers to, to label it too.
+    fn format_instruction(&self, bank: usize, instruction: &Instruction, label: Option<String>, operand_bank: impl Fn(u16) -> usize) -> String {
+        let symbols = &self.memory.symbols;
+        let bytes: Vec<String> = instruction.bytes().iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text = match instruction.operand_address.and_then(|address| symbols.get_label(operand_bank(address), address)) {
+            Some(operand_label) => instruction.text_with_label(operand_label),
+            None => instruction.text.clone(),
+        };
+        let line = format!("{:02X}:{:04X}  {:<8}  {}", bank, instruction.address, bytes.join(" "), text);
+        match label {
+            Some(label) => format!("{}:\n{}", label, line),
+            None => line,
+        }
+    }
+
+    /// Formats instructions read from live memory as a listing.
+    fn format_listing(&self, instructions: &[Instruction]) -> Vec<String> {
+        instructions.iter().map(|instruction| {
+            let bank = self.memory.get_bank(instruction.address);
+            let label = self.memory.symbols.get_label(bank, instruction.address).map(str::to_string);
+            self.format_instruction(bank, instruction, label, |address| self.memory.get_bank(address))
+        }).collect()
+    }
+
+    /// Parses a location as `[bank:]address` or a label. Labels outside bank 0 are tied to their bank.
+    /// Labels are looked up first, since names like `Fade` are also valid hexadecimal; a `$` or `0x` prefix forces an address.
+    fn parse_location(&self, text: &str) -> Result<(u16, Option<usize>), HydraIOError> {
+        if let Some((bank, address)) = text.split_once(':') {
+            return Ok((parse_hex(address)? as u16, Some(parse_hex(bank)? as usize)));
+        }
+        if let Some((bank, address)) = self.memory.symbols.find(text) {
+            return Ok((address, (bank != 0).then_some(bank)));
+        }
+        parse_hex(text)
+            .map(|address| (address as u16, None))
+            .map_err(|_| HydraIOError::InvalidDebugCommand("Not an address or known label"))
+    }
+
+    /// Describes the CPU's registers and flags, along with the bank and instruction at PC.
+    pub fn describe_registers(&self) -> String {
+        let cpu = self.cpu.as_ref().unwrap();
+        let pc = cpu.get_register16(Register16::PC);
+        let bank = self.memory.get_bank(pc);
+        let f = cpu.get_register8(Register8::F);
+        let flags: String = [(7, 'Z'), (6, 'N'), (5, 'H'), (4, 'C')].iter()
+            .map(|&(bit, name)| if f & (1 << bit) != 0 {name} else {'-'})
+            .collect();
+        format!(
+            "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} [{}] IME={}\n{}",
+            cpu.get_register16(Register16::AF),
+            cpu.get_register16(Register16::BC),
+            cpu.get_register16(Register16::DE),
+            cpu.get_register16(Register16::HL),
+            cpu.get_register16(Register16::SP),
+            flags,
+            cpu.is_ime_enabled() as u8,
+            self.format_instruction(bank, &self.disassemble(pc, 1)[0], self.memory.symbols.locate(bank, pc), |address| self.memory.get_bank(address)),
+        )
+    }
+
+    /// Runs a single line of the debug console, returning its output.
+    pub fn execute_debug_command(&mut self, line: &str) -> String {
+        match self.try_debug_command(line) {
+            Ok(output) => output,
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn try_debug_command(&mut self, line: &str) -> Result<String, HydraIOError> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {return Ok(String::new())};
+        let args: Vec<&str> = words.collect();
+        let arg = |index: usize| args.get(index).copied().ok_or(HydraIOError::InvalidDebugCommand("Missing argument"));
+
+        match command {
+            "break" | "b" => {
+                let (address, bank) = self.parse_location(arg(0)?)?;
+                let breakpoint = Breakpoint { address, bank };
+                self.debugger.add_breakpoint(breakpoint);
+                Ok(format!("Added breakpoint at {}", describe_breakpoint(&self.memory, &breakpoint)))
+            }
+            "delete" | "d" => {
+                let index = arg(0)?.parse().map_err(|_| HydraIOError::InvalidDebugCommand("Invalid breakpoint number"))?;
+                match self.debugger.remove_breakpoint(index) {
+                    Some(breakpoint) => Ok(format!("Removed breakpoint at {}", describe_breakpoint(&self.memory, &breakpoint))),
+                    None => Err(HydraIOError::InvalidDebugCommand("No such breakpoint")),
+                }
+            }
+            "breakpoints" | "bl" => Ok(self.debugger.breakpoints.iter().enumerate()
+                .map(|(index, breakpoint)| format!("{}: {}", index, describe_breakpoint(&self.memory, breakpoint)))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "watch" => {
+                let kind = match arg(0)?.to_ascii_lowercase().as_str() {
+                    "r" => WatchKind::Read,
+                    "w" => WatchKind::Write,
+                    "rw" => WatchKind::ReadWrite,
+                    _ => return Err(HydraIOError::InvalidDebugCommand("Watchpoints must be r, w or rw")),
+                };
+                let (start, end) = match arg(1)?.split_once('-') {
+                    Some((start, end)) => (parse_hex(start)? as u16, parse_hex(end)? as u16),
+                    None => {
+                        let address = parse_hex(arg(1)?)? as u16;
+                        (address, address)
+                    }
+                };
+                if end < start {
+                    return Err(HydraIOError::InvalidDebugCommand("Watchpoint range ends before it starts"));
+                }
+                let value = args.get(2).map(|value| parse_hex(value)).transpose()?.map(|value| value as u8);
+                let watchpoint = Watchpoint { start, end, kind, value };
+                self.add_watchpoint(watchpoint);
+                Ok(format!("Added watchpoint on {}", watchpoint))
+            }
+            "unwatch" => {
+                let index = arg(0)?.parse().map_err(|_| HydraIOError::InvalidDebugCommand("Invalid watchpoint number"))?;
+                match self.remove_watchpoint(index) {
+                    Some(watchpoint) => Ok(format!("Removed watchpoint on {}", watchpoint)),
+                    None => Err(HydraIOError::InvalidDebugCommand("No such watchpoint")),
+                }
+            }
+            "watchpoints" | "wl" => Ok(self.memory.watchpoints.iter().enumerate()
+                .map(|(index, watchpoint)| format!("{}: {}", index, watchpoint))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "disasm" | "x" => {
+                let count = args.get(1).map(|count| count.parse()).transpose()
+                    .map_err(|_| HydraIOError::InvalidDebugCommand("Invalid instruction count"))?
+                    .unwrap_or(10);
+                let pc = self.cpu.as_ref().unwrap().get_register16(Register16::PC);
+                let (start, bank) = match args.first() {
+                    Some(location) => self.parse_location(location)?,
+                    None => (pc, None),
+                };
+                let lines: Vec<String> = match bank {
+                    Some(bank) => {
+                        // Read straight from the ROM, so banks can be inspected without being mapped
+                        let base = if bank == 0 {0x0000} else {0x4000};
+                        let bytes = self.rom.get_bank(bank).ok_or(HydraIOError::InvalidDebugCommand("No such ROM bank"))?;
+                        let operand_bank = |address: u16| match address {
+                            0x0000..=0x3FFF => 0,
+                            0x4000..=0x7FFF => bank,
+                            _ => self.memory.get_bank(address),
+                        };
+                        let mut address = start;
+                        (0..count).map(|_| {
+                            let instruction = disassembler::disassemble(address, |at| bytes.get(at.wrapping_sub(base) as usize).copied().unwrap_or(0xFF));
+                            address = address.wrapping_add(instruction.length);
+                            let label = self.memory.symbols.get_label(bank, instruction.address).map(str::to_string);
+                            self.format_instruction(bank, &instruction, label, operand_bank)
+                        }).collect()
+                    }
+                    None => self.format_listing(&self.disassemble(start, count)),
+                };
+                Ok(lines.join("\n"))
+            }
+            "continue" | "c" => {
+                self.debug_continue();
+                Ok("Continuing".to_string())
+            }
+            "pause" | "p" => {
+                self.debug_pause();
+                Ok(self.describe_registers())
+            }
+            "step" | "s" => {
+                self.debug_step_into();
+                Ok(self.describe_registers())
+            }
+            "next" | "n" => {
+                self.debug_step_over();
+                Ok(match self.debugger.is_paused() {
+                    true => self.describe_registers(),
+                    false => "Stepping over call".to_string(),
+                })
+            }
+            "finish" | "f" => {
+                self.debug_step_out();
+                Ok("Stepping out".to_string())
+            }
+            "regs" | "r" => Ok(self.describe_registers()),
+            "set" => {
+                let value = parse_hex(arg(1)?)?;
+                let cpu = self.cpu.as_mut().unwrap();
+                match arg(0)?.to_ascii_lowercase().as_str() {
+                    "a" => cpu.set_register8(Register8::A, value as u8),
+                    "f" => cpu.set_register8(Register8::F, value as u8),
+                    "b" => cpu.set_register8(Register8::B, value as u8),
+                    "c" => cpu.set_register8(Register8::C, value as u8),
+                    "d" => cpu.set_register8(Register8::D, value as u8),
+                    "e" => cpu.set_register8(Register8::E, value as u8),
+                    "h" => cpu.set_register8(Register8::H, value as u8),
+                    "l" => cpu.set_register8(Register8::L, value as u8),
+                    "af" => cpu.set_register16(Register16::AF, value as u16),
+                    "bc" => cpu.set_register16(Register16::BC, value as u16),
+                    "de" => cpu.set_register16(Register16::DE, value as u16),
+                    "hl" => cpu.set_register16(Register16::HL, value as u16),
+                    "sp" => cpu.set_register16(Register16::SP, value as u16),
+                    "pc" => cpu.set_register16(Register16::PC, value as u16),
+                    _ => return Err(HydraIOError::InvalidDebugCommand("Unknown register")),
+                }
+                Ok(self.describe_registers())
+            }
+            "flag" => {
+                let bit = match arg(0)?.to_ascii_lowercase().as_str() {
+                    "z" => 7,
+                    "n" => 6,
+                    "h" => 5,
+                    "c" => 4,
+                    _ => return Err(HydraIOError::InvalidDebugCommand("Unknown flag")),
+                };
+                let is_set = match arg(1)? {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err(HydraIOError::InvalidDebugCommand("Flags can only be set to 0 or 1")),
+                };
+                let cpu = self.cpu.as_mut().unwrap();
+                let f = cpu.get_register8(Register8::F) & !(1 << bit) | ((is_set as u8) << bit);
+                cpu.set_register8(Register8::F, f);
+                Ok(self.describe_registers())
+            }
+            "trace" => match arg(0)? {
+                "on" => {
+                    let path = match args.get(1) {
+                        Some(path) => PathBuf::from(path),
+                        None => self.get_trace_path().ok_or(HydraIOError::InvalidDebugCommand("No ROM file to trace alongside; give a file instead"))?,
+                    };
+                    self.start_trace(TraceLogger::create(&path)?)?;
+                    Ok(format!("Tracing to {}", path.display()))
+                }
+                "off" => {
+                    self.stop_trace()?;
+                    Ok("Stopped tracing".to_string())
+                }
+                "pc" => {
+                    let range = match arg(1)? {
+                        "any" => None,
+                        range => {
+                            let (start, end) = range.split_once('-').ok_or(HydraIOError::InvalidDebugCommand("Expected a range of addresses"))?;
+                            Some((parse_hex(start)? as u16, parse_hex(end)? as u16))
+                        }
+                    };
+                    self.get_trace().ok_or(HydraIOError::InvalidDebugCommand("Not tracing"))?.set_pc_range(range);
+                    Ok("Updated trace filter".to_string())
+                }
+                "bank" => {
+                    let bank = match arg(1)? {
+                        "any" => None,
+                        bank => Some(parse_hex(bank)? as usize),
+                    };
+                    self.get_trace().ok_or(HydraIOError::InvalidDebugCommand("Not tracing"))?.set_bank(bank);
+                    Ok("Updated trace filter".to_string())
+                }
+                "labels" => {
+                    let show_labels = match arg(1)? {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(HydraIOError::InvalidDebugCommand("Expected on or off")),
+                    };
+                    self.get_trace().ok_or(HydraIOError::InvalidDebugCommand("Not tracing"))?.set_show_labels(show_labels);
+                    Ok("Updated trace labels".to_string())
+                }
+                _ => Err(HydraIOError::InvalidDebugCommand("Expected on, off, pc, bank or labels")),
+            },
+            "symbols" => {
+                let symbols = SymbolTable::load(&PathBuf::from(arg(0)?))?;
+                let count = symbols.len();
+                self.load_symbols(symbols);
+                Ok(format!("Loaded {} symbols", count))
+            }
+            "palettes" => Ok(self.get_palettes().iter()
+                .map(|palette| {
+                    let values = palette.values.map(|value| match self.memory.mode.get() {
+                        GbMode::DMG => value.to_string(),
+                        GbMode::CGB => format!("{:04X}", value),
+                    });
+                    format!("{:<5} {}", palette.name, values.join(" "))
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "palette" => {
+                let name = arg(0)?;
+                let palettes = self.get_palettes();
+                let index = palettes.iter().position(|palette| palette.name.eq_ignore_ascii_case(name))
+                    .ok_or(HydraIOError::InvalidDebugCommand("Unknown palette (try `palettes`)"))?;
+                let color_index = match arg(1)? {
+                    "0" => 0,
+                    "1" => 1,
+                    "2" => 2,
+                    "3" => 3,
+                    _ => return Err(HydraIOError::InvalidDebugCommand("Palettes only have colors 0-3")),
+                };
+                self.set_palette_color(index, color_index, parse_hex(arg(2)?)? as u16);
+                Ok(format!("Set color {} of {}", color_index, palettes[index].name))
+            }
+            "gdb" => match arg(0)? {
+                "off" => {
+                    self.stop_gdb_stub();
+                    Ok("Stopped GDB stub".to_string())
+                }
+                port => {
+                    let port = port.parse().map_err(|_| HydraIOError::InvalidDebugCommand("Invalid port"))?;
+                    let port = self.start_gdb_stub(port)?;
+                    Ok(format!("GDB stub listening on localhost:{}", port))
+                }
+            },
+            "help" | "h" | "?" => Ok(HELP.to_string()),
+            _ => Err(HydraIOError::InvalidDebugCommand("Unknown command (try `help`)")),
+        }
+    }
+}
+
+fn parse_hex(text: &str) -> Result<u32, HydraIOError> {
+    let digits = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")).unwrap_or(text);
+    u32::from_str_radix(digits, 16).map_err(|_| HydraIOError::InvalidDebugCommand("Invalid hexadecimal number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{GBRevision, Model, symbols::SymbolTable};
+
+    #[test]
+    fn labels_are_preferred_over_hexadecimal() {
+        let mut gb = GameBoy::headless(vec![0; 0x8000], Model::GameBoy(GBRevision::DMG)).unwrap();
+        gb.load_symbols(SymbolTable::parse("00:0150 Add\n01:4000 Fade\n00:C000 cafe\n"));
+
+        assert!(matches!(gb.parse_location("Add"), Ok((0x0150, None))));
+        assert!(matches!(gb.parse_location("Fade"), Ok((0x4000, Some(1)))));
+        assert!(matches!(gb.parse_location("cafe"), Ok((0xC000, None))));
+        assert!(matches!(gb.parse_location("$Fade"), Ok((0xFADE, None))));
+        assert!(matches!(gb.parse_location("0xcafe"), Ok((0xCAFE, None))));
+        assert!(matches!(gb.parse_location("Bed"), Ok((0x0BED, None))));
+        assert!(matches!(gb.parse_location("2:4abc"), Ok((0x4ABC, Some(2)))));
+        assert!(gb.parse_location("Missing").is_err());
+    }
+}
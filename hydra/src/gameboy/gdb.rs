@@ -0,0 +1,515 @@
+use std::{io::{self, ErrorKind, Read, Write}, net::{Ipv4Addr, TcpListener, TcpStream}};
+
+use crate::{common::errors::HydraIOError, gameboy::{Breakpoint, GameBoy, WatchKind, Watchpoint, cpu::Register16, memory::MemoryMap}};
+
+/// The registers exposed to GDB, in the order of the `g` packet.
+const REGISTERS: [Register16; 6] = [Register16::AF, Register16::BC, Register16::DE, Register16::HL, Register16::SP, Register16::PC];
+
+/// Describes the registers to clients, since GDB has no built-in SM83 architecture.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.hydra.sm83">
+    <reg name="af" bitsize="16" type="int"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="int"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// A GDB remote serial protocol server, listening for a single client on localhost.
+/// It never blocks: incoming packets are handled whenever the emulator polls it.
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    received: Vec<u8>,
+    /// Whether the client has resumed execution, and is waiting to hear when it stops.
+    awaiting_stop: bool,
+}
+
+impl GdbStub {
+    pub fn listen(port: u16) -> Result<Self, HydraIOError> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbStub { listener, client: None, received: Vec::new(), awaiting_stop: false })
+    }
+
+    pub fn get_port(&self) -> Option<u16> {
+        self.listener.local_addr().ok().map(|address| address.port())
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accepts a new client, if none is connected, then reads whatever it has sent. Returns false if the client disconnected.
+    fn receive(&mut self) -> io::Result<bool> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    println!("GDB client connected from {}.", address);
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    self.client = Some(stream);
+                    self.received.clear();
+                    self.awaiting_stop = false;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let client = self.client.as_mut().unwrap();
+        let mut buffer = [0; 4096];
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => return Ok(false),
+                Ok(length) => self.received.extend_from_slice(&buffer[..length]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Removes the next complete packet (or interrupt request) from the received data.
+    fn next_packet(&mut self) -> Option<Packet> {
+        loop {
+            match self.received.first()? {
+                b'$' => {
+                    let end = self.received.iter().position(|&byte| byte == b'#')?;
+                    if self.received.len() < end + 3 {
+                        return None; // Checksum hasn't arrived yet
+                    }
+                    let packet: Vec<u8> = self.received.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = std::str::from_utf8(&packet[end + 1..]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    return Some(match checksum == Some(checksum_of(data)) {
+                        true => Packet::Command(String::from_utf8_lossy(&unescape(data)).into_owned()),
+                        false => Packet::Corrupted,
+                    });
+                }
+                0x03 => {
+                    self.received.remove(0);
+                    return Some(Packet::Interrupt);
+                }
+                _ => {
+                    // Acknowledgements, or noise between packets
+                    self.received.remove(0);
+                }
+            }
+        }
+    }
+
+    fn send_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        let Some(client) = &mut self.client else {return Ok(())};
+        // Replies are small, so it's simplest to block until they're sent
+        client.set_nonblocking(false)?;
+        let result = client.write_all(data);
+        client.set_nonblocking(true)?;
+        result
+    }
+
+    fn send(&mut self, reply: &str) -> io::Result<()> {
+        self.send_raw(&frame_packet(reply))
+    }
+}
+
+enum Packet {
+    Command(String),
+    Corrupted,
+    Interrupt,
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Bytes that would be mistaken for framing (or run-length encoding) are sent as `}` followed by the byte XORed with $20.
+const ESCAPE: u8 = b'}';
+
+fn frame_packet(reply: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(reply.len());
+    for byte in reply.bytes() {
+        match byte {
+            b'$' | b'#' | b'*' | ESCAPE => data.extend([ESCAPE, byte ^ 0x20]),
+            _ => data.push(byte),
+        }
+    }
+    let checksum = checksum_of(&data);
+    let mut packet = vec![b'$'];
+    packet.extend(data);
+    packet.extend(format!("#{:02x}", checksum).bytes());
+    packet
+}
+
+/// Reverses the escaping of a packet's data, after its checksum (which covers the escaped form) has been checked.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            ESCAPE => unescaped.extend(bytes.next().map(|next| next ^ 0x20)),
+            _ => unescaped.push(byte),
+        }
+    }
+    unescaped
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn encode_u16(value: u16) -> String {
+    // Registers are sent in target (little-endian) byte order
+    let [low, high] = value.to_le_bytes();
+    format!("{:02x}{:02x}", low, high)
+}
+
+fn decode_u16(hex: &str) -> Option<u16> {
+    let low = u8::from_str_radix(hex.get(0..2)?, 16).ok()?;
+    let high = u8::from_str_radix(hex.get(2..4)?, 16).ok()?;
+    Some(u16::from_le_bytes([low, high]))
+}
+
+/// Parses the `addr,length` arguments shared by memory and breakpoint packets.
+fn parse_address_pair(text: &str) -> Option<(u16, u32)> {
+    let (address, length) = text.split_once(',')?;
+    let address = parse_hex(address)?;
+    (address <= 0xFFFF).then_some((address as u16, parse_hex(length)?))
+}
+
+impl GameBoy {
+    /// Starts listening for a GDB client on the given localhost port (0 picks any free port), replacing any existing stub.
+    pub fn start_gdb_stub(&mut self, port: u16) -> Result<u16, HydraIOError> {
+        let stub = GdbStub::listen(port)?;
+        let port = stub.get_port().unwrap_or(port);
+        self.gdb = Some(stub);
+        Ok(port)
+    }
+
+    pub fn stop_gdb_stub(&mut self) {
+        self.gdb = None;
+    }
+
+    /// Handles anything sent by a GDB client, and tells it if execution has stopped since it resumed.
+    /// Called once per frame by the frontend; headless callers should call this regularly themselves.
+    pub fn poll_gdb(&mut self) {
+        let Some(mut stub) = self.gdb.take() else {return};
+        if let Err(e) = self.serve_gdb(&mut stub) {
+            println!("GDB client disconnected: {}", e);
+            stub.client = None;
+        }
+        self.gdb = Some(stub);
+    }
+
+    fn serve_gdb(&mut self, stub: &mut GdbStub) -> io::Result<()> {
+        let was_attached = stub.is_attached();
+        if !stub.receive()? {
+            println!("GDB client disconnected.");
+            stub.client = None;
+            return Ok(());
+        }
+        if !was_attached && stub.is_attached() {
+            // Clients expect the target to be stopped once they've attached
+            self.debug_pause();
+        }
+
+        while let Some(packet) = stub.next_packet() {
+            match packet {
+                Packet::Corrupted => stub.send_raw(b"-")?,
+                Packet::Interrupt => {
+                    self.debug_pause();
+                    stub.awaiting_stop = false;
+                    stub.send(&format!("S{:02x}", SIGINT))?;
+                }
+                Packet::Command(command) => {
+                    stub.send_raw(b"+")?;
+                    let reply = self.handle_gdb_command(stub, &command);
+                    if let Some(reply) = reply {
+                        stub.send(&reply)?;
+                    }
+                    if command == "D" || command.starts_with('k') {
+                        stub.client = None;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if stub.awaiting_stop && self.debugger.is_paused() {
+            stub.awaiting_stop = false;
+            stub.send(&format!("S{:02x}", SIGTRAP))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the reply to a single packet, or `None` if the reply is deferred until execution stops.
+    fn handle_gdb_command(&mut self, stub: &mut GdbStub, command: &str) -> Option<String> {
+        const ERROR: &str = "E01";
+        let cpu = self.cpu.as_mut().unwrap();
+        let (kind, args) = command.split_at(command.chars().next().map_or(0, char::len_utf8));
+
+        Some(match kind {
+            "?" => format!("S{:02x}", if self.debugger.is_paused() {SIGTRAP} else {0}),
+            "g" => REGISTERS.into_iter().map(|register| encode_u16(cpu.get_register16(register))).collect(),
+            "G" => {
+                let values: Option<Vec<u16>> = (0..REGISTERS.len()).map(|index| decode_u16(args.get(index * 4..)?)).collect();
+                match values {
+                    Some(values) => {
+                        REGISTERS.into_iter().zip(values).for_each(|(register, value)| cpu.set_register16(register, value));
+                        "OK".to_string()
+                    }
+                    None => ERROR.to_string(),
+                }
+            }
+            "p" => match parse_hex(args).and_then(|index| REGISTERS.into_iter().nth(index as usize)) {
+                Some(register) => encode_u16(cpu.get_register16(register)),
+                None => ERROR.to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(index, value)| {
+                    Some((REGISTERS.into_iter().nth(parse_hex(index)? as usize)?, decode_u16(value)?))
+                });
+                match parsed {
+                    Some((register, value)) => {
+                        cpu.set_register16(register, value);
+                        "OK".to_string()
+                    }
+                    None => ERROR.to_string(),
+                }
+            }
+            "m" => match parse_address_pair(args) {
+                Some((address, length)) => (0..length.min(0x10000 - address as u32))
+                    .map(|offset| format!("{:02x}", self.memory.debug_read_u8(address.wrapping_add(offset as u16))))
+                    .collect(),
+                None => ERROR.to_string(),
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| {
+                    let (address, length) = parse_address_pair(range)?;
+                    let bytes: Option<Vec<u8>> = (0..length as usize)
+                        .map(|index| u8::from_str_radix(data.get(index * 2..index * 2 + 2)?, 16).ok())
+                        .collect();
+                    Some((address, bytes?))
+                });
+                match parsed {
+                    // Writing to the cartridge would switch banks or enable RAM instead, so the whole write is refused
+                    Some((address, bytes)) if (0..bytes.len()).any(|offset| MemoryMap::is_cartridge_address(address.wrapping_add(offset as u16))) => ERROR.to_string(),
+                    Some((address, bytes)) => {
+                        for (offset, byte) in bytes.into_iter().enumerate() {
+                            self.memory.debug_write_u8(byte, address.wrapping_add(offset as u16));
+                        }
+                        "OK".to_string()
+                    }
+                    None => ERROR.to_string(),
+                }
+            }
+            "c" => {
+                if let Some(address) = parse_hex(args) {
+                    cpu.set_register16(Register16::PC, address as u16);
+                }
+                self.debug_continue();
+                stub.awaiting_stop = true;
+                return None;
+            }
+            "s" => {
+                if let Some(address) = parse_hex(args) {
+                    cpu.set_register16(Register16::PC, address as u16);
+                }
+                self.debug_step_into();
+                format!("S{:02x}", SIGTRAP)
+            }
+            "Z" | "z" => {
+                let Some((breakpoint_type, rest)) = args.split_once(',') else {return Some(ERROR.to_string())};
+                let Some((address, length)) = parse_address_pair(rest) else {return Some(ERROR.to_string())};
+                let is_insert = kind == "Z";
+                let end = address.saturating_add(length.saturating_sub(1).min(0xFFFF) as u16);
+                let watch_kind = match breakpoint_type {
+                    "0" | "1" => {
+                        let breakpoint = Breakpoint { address, bank: None };
+                        match is_insert {
+                            true => self.debugger.add_breakpoint(breakpoint),
+                            false => {
+                                let index = self.debugger.get_breakpoints().iter().position(|existing| *existing == breakpoint);
+                                index.map(|index| self.debugger.remove_breakpoint(index));
+                            }
+                        }
+                        return Some("OK".to_string());
+                    }
+                    "2" => WatchKind::Write,
+                    "3" => WatchKind::Read,
+                    "4" => WatchKind::ReadWrite,
+                    _ => return Some(String::new()),
+                };
+                let watchpoint = Watchpoint { start: address, end, kind: watch_kind, value: None };
+                match is_insert {
+                    true => self.add_watchpoint(watchpoint),
+                    false => {
+                        let index = self.get_watchpoints().iter().position(|existing| *existing == watchpoint);
+                        index.map(|index| self.remove_watchpoint(index));
+                    }
+                }
+                "OK".to_string()
+            }
+            "D" => {
+                self.debug_continue();
+                "OK".to_string()
+            }
+            "k" => {
+                self.debug_continue();
+                return None;
+            }
+            "H" | "T" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => "PacketSize=4000;qXfer:features:read+".to_string(),
+            "q" if args == "Attached" => "1".to_string(),
+            "q" if args == "C" => "QC1".to_string(),
+            "q" if args == "fThreadInfo" => "m1".to_string(),
+            "q" if args == "sThreadInfo" => "l".to_string(),
+            "q" if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") => {
+                match parse_address_pair(range).map(|(offset, length)| (offset as usize, length as usize)) {
+                    Some((offset, _)) if offset >= TARGET_XML.len() => "l".to_string(),
+                    Some((offset, length)) => {
+                        let chunk = &TARGET_XML[offset..(offset + length).min(TARGET_XML.len())];
+                        let marker = if offset + length >= TARGET_XML.len() {'l'} else {'m'};
+                        format!("{}{}", marker, chunk)
+                    }
+                    None => ERROR.to_string(),
+                }
+            }
+            _ => String::new(), // An empty reply tells the client the packet isn't supported
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{GBRevision, Model};
+
+    fn stub_with(received: &[u8]) -> GdbStub {
+        let mut stub = GdbStub::listen(0).unwrap();
+        stub.received = received.to_vec();
+        stub
+    }
+
+    /// An MBC1 cartridge with four ROM banks, each starting with its own number.
+    fn attached() -> (GameBoy, GdbStub) {
+        let mut rom = vec![0; 0x10000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+        rom[0x0147] = 0x01;
+        rom[0x0148] = 0x01;
+        for bank in 0..4 {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        (GameBoy::headless(rom, Model::GameBoy(GBRevision::DMG)).unwrap(), stub_with(&[]))
+    }
+
+    fn command(gb: &mut GameBoy, stub: &mut GdbStub, command: &str) -> String {
+        gb.handle_gdb_command(stub, command).unwrap()
+    }
+
+    #[test]
+    fn packets_are_framed_and_checksummed() {
+        let mut stub = stub_with(b"+$g#67$m0,2#fb");
+        assert!(matches!(stub.next_packet(), Some(Packet::Command(command)) if command == "g"));
+        assert!(matches!(stub.next_packet(), Some(Packet::Command(command)) if command == "m0,2"));
+        assert!(stub.next_packet().is_none());
+
+        let mut stub = stub_with(b"$g#68\x03");
+        assert!(matches!(stub.next_packet(), Some(Packet::Corrupted)));
+        assert!(matches!(stub.next_packet(), Some(Packet::Interrupt)));
+
+        assert_eq!(frame_packet("OK"), b"$OK#9a");
+        assert_eq!(frame_packet(""), b"$#00");
+    }
+
+    #[test]
+    fn partial_packets_wait_for_the_rest() {
+        let mut stub = stub_with(b"$g#6");
+        assert!(stub.next_packet().is_none());
+        stub.received.push(b'7');
+        assert!(matches!(stub.next_packet(), Some(Packet::Command(command)) if command == "g"));
+        assert!(stub.received.is_empty());
+    }
+
+    #[test]
+    fn special_bytes_are_escaped() {
+        assert_eq!(frame_packet("a$b#c}d*"), b"$a}\x04b}\x03c}]d}\x0a#ec");
+        assert_eq!(unescape(b"a}\x04b}\x03c}]d}\x0a"), b"a$b#c}d*");
+
+        // The checksum covers the escaped bytes, not the decoded ones
+        let mut stub = stub_with(&frame_packet("X0,1:}"));
+        assert!(matches!(stub.next_packet(), Some(Packet::Command(command)) if command == "X0,1:}"));
+    }
+
+    #[test]
+    fn registers() {
+        let (mut gb, mut stub) = attached();
+        let cpu = gb.cpu.as_mut().unwrap();
+        cpu.set_register16(Register16::AF, 0x1230);
+        cpu.set_register16(Register16::PC, 0xBEEF);
+        let registers = command(&mut gb, &mut stub, "g");
+        assert_eq!(registers.len(), 24);
+        assert_eq!(&registers[..4], "3012");
+        assert_eq!(&registers[20..], "efbe");
+        assert_eq!(command(&mut gb, &mut stub, "p5"), "efbe");
+        assert_eq!(command(&mut gb, &mut stub, "p6"), "E01");
+
+        assert_eq!(command(&mut gb, &mut stub, "P2=3412"), "OK");
+        assert_eq!(gb.cpu.as_ref().unwrap().get_register16(Register16::DE), 0x1234);
+        assert_eq!(command(&mut gb, &mut stub, "Gf0000100020003000400"), "E01");
+        assert_eq!(command(&mut gb, &mut stub, "Gf0000100020003000400050f"), "OK");
+        let cpu = gb.cpu.as_ref().unwrap();
+        assert_eq!(cpu.get_register16(Register16::AF), 0x00F0);
+        assert_eq!(cpu.get_register16(Register16::HL), 0x0003);
+        assert_eq!(cpu.get_register16(Register16::PC), 0x0F05);
+    }
+
+    #[test]
+    fn memory() {
+        let (mut gb, mut stub) = attached();
+        assert_eq!(command(&mut gb, &mut stub, "MC000,3:0a0b0c"), "OK");
+        assert_eq!(command(&mut gb, &mut stub, "mC000,3"), "0a0b0c");
+        assert_eq!(command(&mut gb, &mut stub, "mE001,1"), "0b");
+        // $FF03 isn't mapped to anything
+        assert_eq!(command(&mut gb, &mut stub, "mFF03,1"), "ff");
+        assert_eq!(command(&mut gb, &mut stub, "mC000"), "E01");
+        assert_eq!(command(&mut gb, &mut stub, "MC000,2:0a"), "E01");
+    }
+
+    #[test]
+    fn cartridge_writes_are_refused() {
+        let (mut gb, mut stub) = attached();
+        assert_eq!(command(&mut gb, &mut stub, "m4000,1"), "01");
+        // Would select bank 2 if it reached the MBC
+        assert_eq!(command(&mut gb, &mut stub, "M2000,1:02"), "E01");
+        assert_eq!(command(&mut gb, &mut stub, "M9FFF,2:0000"), "E01");
+        assert_eq!(command(&mut gb, &mut stub, "m4000,1"), "01");
+        assert_eq!(command(&mut gb, &mut stub, "m9FFF,1"), "00");
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let (mut gb, mut stub) = attached();
+        assert_eq!(command(&mut gb, &mut stub, "Z0,150,1"), "OK");
+        assert!(gb.debugger.get_breakpoints() == [Breakpoint { address: 0x150, bank: None }]);
+        assert_eq!(command(&mut gb, &mut stub, "z0,150,1"), "OK");
+        assert!(gb.debugger.get_breakpoints().is_empty());
+
+        assert_eq!(command(&mut gb, &mut stub, "Z2,c000,4"), "OK");
+        assert_eq!(command(&mut gb, &mut stub, "Z3,ff40,1"), "OK");
+        let write = Watchpoint { start: 0xC000, end: 0xC003, kind: WatchKind::Write, value: None };
+        let read = Watchpoint { start: 0xFF40, end: 0xFF40, kind: WatchKind::Read, value: None };
+        assert!(gb.get_watchpoints() == [write, read]);
+        assert_eq!(command(&mut gb, &mut stub, "z2,c000,4"), "OK");
+        assert!(gb.get_watchpoints() == [read]);
+
+        assert_eq!(command(&mut gb, &mut stub, "Z5,0,1"), "");
+        assert_eq!(command(&mut gb, &mut stub, "Z0"), "E01");
+    }
+}
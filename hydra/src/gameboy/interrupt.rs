@@ -1,4 +1,4 @@
-use crate::{common::{bit::MaskedBitVec, errors::HydraIOError}, gameboy::memory::MemoryMapped};
+use crate::{common::{bit::MaskedBitVec, errors::HydraIOError}, gameboy::memory::MemoryMapped, save_state_fields};
 
 pub struct InterruptFlags {
     interrupts: MaskedBitVec<u8, true>
@@ -48,6 +48,8 @@ impl MemoryMapped for InterruptFlags {
     }
 }
 
+save_state_fields!(InterruptFlags { interrupts });
+
 pub struct InterruptEnable {
     interrupts: u8
 }
@@ -84,6 +86,8 @@ impl MemoryMapped for InterruptEnable {
     }
 }
 
+save_state_fields!(InterruptEnable { interrupts });
+
 #[repr(u8)]
 pub enum Interrupt {
     Vblank = 0b00000001,
@@ -1,26 +1,74 @@
-use std::{cell::RefCell, rc::Rc, sync::{Arc, RwLock}};
+use std::{rc::Rc, sync::Arc};
+#[cfg(feature = "frontend")]
+use std::sync::RwLock;
+
+#[cfg(feature = "frontend")]
+use sdl3::{gamepad::Button, sensor::SensorType};
+
+use crate::{common::bit::{BitVec, MaskedBitVec}, gameboy::{Model, interrupt::{Interrupt, InterruptFlags}, sgb::packet::{Packet, SgbLink}}, save_state_fields};
+#[cfg(feature = "frontend")]
+use crate::input::{ControllerMessage, ControllerState, Direction};
+
+/// The host's game controllers, as seen by the joypad and by cartridges with rumble or motion sensors.
+pub trait ControllerPort {
+    fn poll_inputs(&self, vecs: &mut InputVectors);
+    fn poll_accelerometer(&self) -> [f32; 3] { [0.0; 3] }
+    fn rumble(&self, _intensity: u16) { /* Do nothing by default */ }
+}
+
+/// A `ControllerPort` with nothing plugged in, for running without any host input devices.
+pub struct NoControllers;
+
+impl ControllerPort for NoControllers {
+    fn poll_inputs(&self, _vecs: &mut InputVectors) { /* Do nothing */ }
+}
 
-use sdl3::gamepad::Button;
+#[cfg(feature = "frontend")]
+impl ControllerPort for RwLock<ControllerState> {
+    fn poll_inputs(&self, vecs: &mut InputVectors) {
+        let controllers = self.read().unwrap();
+        vecs.press_button(JoypButton::A, controllers.poll_button(Button::East) | controllers.poll_button(Button::West));
+        vecs.press_button(JoypButton::B, controllers.poll_button(Button::North) | controllers.poll_button(Button::South));
+        vecs.press_button(JoypButton::Start, controllers.poll_button(Button::Start));
+        vecs.press_button(JoypButton::Select, controllers.poll_button(Button::Back));
+        vecs.press_dpad(JoypDpad::Up, controllers.poll_direction(Direction::Up));
+        vecs.press_dpad(JoypDpad::Down, controllers.poll_direction(Direction::Down));
+        vecs.press_dpad(JoypDpad::Left, controllers.poll_direction(Direction::Left));
+        vecs.press_dpad(JoypDpad::Right, controllers.poll_direction(Direction::Right));
+    }
+
+    fn poll_accelerometer(&self) -> [f32; 3] {
+        self.read().unwrap().poll_sensor(SensorType::Accelerometer)
+    }
 
-use crate::{common::{bit::{BitVec, MaskedBitVec}, errors::HydraIOError}, gameboy::{Model, interrupt::{Interrupt, InterruptFlags}, memory::{MemoryMap, MemoryMapped}}, input::{ControllerState, Direction, SdlContainer}};
+    fn rumble(&self, intensity: u16) {
+        let _ = self.read().unwrap().channel().send(ControllerMessage::Rumble(intensity));
+    }
+}
 
 pub struct Joypad {
     pub keyboard_vecs: InputVectors,
     controller_vecs: InputVectors,
-    pub controllers: Arc<RwLock<ControllerState>>,
+    pub controllers: Arc<dyn ControllerPort>,
+    /// Inputs that replace the keyboard and controllers entirely (e.g. while a movie is recording or playing).
+    input_override: Option<InputVectors>,
     joyp: MaskedBitVec<u8, true>,
+    /// Only present on SGB models.
+    sgb_link: Option<SgbLink>,
 }
 
 impl Joypad {
-    pub fn new(model: &Rc<Model>, controllers: Arc<RwLock<ControllerState>>) -> Self {
+    pub fn new(model: &Rc<Model>, controllers: Arc<dyn ControllerPort>) -> Self {
         Joypad { 
             keyboard_vecs: InputVectors::new(),
             controller_vecs: InputVectors::new(),
             controllers,
+            input_override: None,
             joyp: MaskedBitVec::new(match model.is_monochrome() {
                 true => 0xCF,
                 false => 0xFF,
             }, 0b00111111, 0b00110000),
+            sgb_link: model.is_super().then(SgbLink::new),
         }
     }
 
@@ -33,14 +81,23 @@ impl Joypad {
     }
 
     fn refresh(&mut self, interrupt_flags: &mut InterruptFlags) {
+        let player = self.sgb_link.as_ref().map_or(0, SgbLink::get_player);
+        // Only the first controller is connected to anything on the host
+        let inputs = match player {
+            0 => self.get_inputs(),
+            _ => InputVectors::new(),
+        };
         let mut after = 0b0000;
-        if self.is_polling_buttons() {after |= self.keyboard_vecs.button_vector | self.controller_vecs.button_vector}
-        if self.is_polling_dpad() {after |= self.keyboard_vecs.dpad_vector | self.controller_vecs.dpad_vector}
+        if self.is_polling_buttons() {after |= inputs.button_vector}
+        if self.is_polling_dpad() {after |= inputs.dpad_vector}
         
         if *self.joyp & after != 0 {
             interrupt_flags.request(Interrupt::Joypad);
         }
 
+        // With neither line selected, the SGB reports which controller is being read
+        if !self.is_polling_buttons() && !self.is_polling_dpad() {after = player}
+
         *self.joyp = (*self.joyp & 0b00110000) | (after ^ 0b1111);
     }
 
@@ -49,18 +106,29 @@ impl Joypad {
     }
 
     pub fn update_controller_vecs(&mut self, interrupt_flags: &mut InterruptFlags) {
-        {
-            let controllers = self.controllers.read().unwrap();
-            self.controller_vecs.press_button(JoypButton::A, controllers.poll_button(Button::East) | controllers.poll_button(Button::West));
-            self.controller_vecs.press_button(JoypButton::B, controllers.poll_button(Button::North) | controllers.poll_button(Button::South));
-            self.controller_vecs.press_button(JoypButton::Start, controllers.poll_button(Button::Start));
-            self.controller_vecs.press_button(JoypButton::Select, controllers.poll_button(Button::Back));
-            self.controller_vecs.press_dpad(JoypDpad::Up, controllers.poll_direction(Direction::Up));
-            self.controller_vecs.press_dpad(JoypDpad::Down, controllers.poll_direction(Direction::Down));
-            self.controller_vecs.press_dpad(JoypDpad::Left, controllers.poll_direction(Direction::Left));
-            self.controller_vecs.press_dpad(JoypDpad::Right, controllers.poll_direction(Direction::Right));
+        self.controllers.poll_inputs(&mut self.controller_vecs);
+        self.refresh(interrupt_flags);
+    }
+
+    /// Returns the inputs currently seen by the console.
+    pub fn get_inputs(&self) -> InputVectors {
+        match self.input_override {
+            Some(inputs) => inputs,
+            None => self.get_host_inputs(),
+        }
+    }
+
+    /// Returns the inputs from the keyboard and controllers, even while they're overridden.
+    pub fn get_host_inputs(&self) -> InputVectors {
+        InputVectors {
+            button_vector: self.keyboard_vecs.button_vector | self.controller_vecs.button_vector,
+            dpad_vector: self.keyboard_vecs.dpad_vector | self.controller_vecs.dpad_vector,
         }
-                
+    }
+
+    /// Replaces the keyboard and controllers with the given inputs, or hands control back to them if `None`.
+    pub fn override_inputs(&mut self, inputs: Option<InputVectors>, interrupt_flags: &mut InterruptFlags) {
+        self.input_override = inputs;
         self.refresh(interrupt_flags);
     }
 }
@@ -72,10 +140,29 @@ impl Joypad {
 
     pub fn write_joyp(&mut self, val: u8, interrupt_flags: &mut InterruptFlags) {
         self.joyp.write(val);
+        if let Some(sgb_link) = &mut self.sgb_link {
+            sgb_link.write_lines(val >> 4);
+        }
+        self.refresh(interrupt_flags);
+    }
+
+    /// Returns the last packet sent to the SGB, if one has been completed since this was last called.
+    pub fn take_sgb_packet(&mut self) -> Option<Packet> {
+        self.sgb_link.as_mut().and_then(SgbLink::take_packet)
+    }
+
+    /// Sets how many controllers the SGB cycles through (as requested by MLT_REQ), starting again from the first.
+    pub fn set_sgb_player_count(&mut self, player_count: u8, interrupt_flags: &mut InterruptFlags) {
+        if let Some(sgb_link) = &mut self.sgb_link {
+            sgb_link.set_player_count(player_count);
+        }
         self.refresh(interrupt_flags);
     }
 }
 
+// Input vectors aren't saved, since they mirror the host's controllers rather than the console
+save_state_fields!(Joypad { joyp, sgb_link });
+
 #[repr(u8)]
 pub enum JoypDpad {
     Right = 0b00000001,
@@ -92,6 +179,7 @@ pub enum JoypButton {
     Start  = 0b00001000,
 }
 
+#[derive(Copy, Clone, Default, PartialEq)]
 pub struct InputVectors {
     button_vector: u8,
     dpad_vector: u8,
@@ -112,4 +200,14 @@ impl InputVectors {
     pub fn press_dpad(&mut self, dpad: JoypDpad, is_pressed: bool) {
         self.dpad_vector.map_bits(dpad as u8, is_pressed);
     }
-}
\ No newline at end of file
+
+    pub fn is_button_pressed(&self, button: JoypButton) -> bool {
+        self.button_vector & button as u8 != 0
+    }
+
+    pub fn is_dpad_pressed(&self, dpad: JoypDpad) -> bool {
+        self.dpad_vector & dpad as u8 != 0
+    }
+}
+
+save_state_fields!(InputVectors { button_vector, dpad_vector });
\ No newline at end of file
@@ -1,4 +1,7 @@
+mod battery;
+pub mod boot;
 pub mod hdma;
+pub mod header;
 mod mbc;
 pub mod oam;
 pub mod rom;
@@ -6,21 +9,43 @@ mod sram;
 pub mod vram;
 pub mod wram;
 
-use wgpu::{Device, Queue};
-use winit::event_loop::EventLoopProxy;
-
 use crate::{
-    common::errors::HydraIOError, deserialize, gameboy::{
-        GbMode, Model, apu::{Apu, channel::{Noise, Pulse, PulseType, Wave}, state::ApuState}, interrupt::{InterruptEnable, InterruptFlags}, joypad::Joypad, memory::{hdma::HdmAccessor, oam::Oam, rom::{Rom, RomHeader}, vram::Vram, wram::Wram}, ppu::{PpuMode, colormap::{self, ColorMap}, state::PpuState}, serial::SerialConnection, timer::MasterTimer
-    }, input::{ControllerState, SdlContainer}, graphics::Graphics, serialize, window::UserEvent
+    common::{bit::BitVec, errors::HydraIOError, output::VideoOutput, savestate::{SaveState, StateReader, StateWriter}}, deserialize, gameboy::{
+        GbMode, Model, apu::{Apu, channel::{Noise, Pulse, PulseType, Wave}, state::ApuState}, debugger::{WatchHit, Watchpoint}, symbols::SymbolTable, interrupt::{InterruptEnable, InterruptFlags}, joypad::{ControllerPort, Joypad}, memory::{battery::BatteryFile, boot::BootRom, hdma::HdmAccessor, oam::Oam, rom::{Rom, RomHeader}, vram::Vram, wram::Wram}, ppu::{self, PpuMode, colormap::{self, ColorMap}, compatibility::CompatibilityPalettes, state::PpuState}, serial::SerialConnection, sgb::{self, Sgb}, timer::MasterTimer
+    }, serialize
 };
-use std::{cell::{Cell, RefCell}, fs, path::Path, rc::Rc, sync::{Arc, RwLock}, time::Duration};
+use rand::{SeedableRng, rngs::StdRng};
+use std::{cell::{Cell, RefCell}, fmt, fs, path::{Path, PathBuf}, rc::Rc, sync::{Arc, RwLock}, time::Duration};
+
+/// Whatever is driving the bus during a memory access.
+#[derive(Copy, Clone, PartialEq)]
+pub enum BusAccessor {
+    Cpu,
+    /// OAM DMA, which ignores the usual restrictions on which memory the CPU may access.
+    OamDma,
+    Hdma,
+}
+
+impl fmt::Display for BusAccessor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BusAccessor::Cpu => "CPU",
+            BusAccessor::OamDma => "OAM DMA",
+            BusAccessor::Hdma => "HDMA",
+        })
+    }
+}
 
 pub struct MemoryMap {
-    model: Rc<Model>,
-    mode: Rc<GbMode>,
+    pub(super) model: Rc<Model>,
+    pub(super) mode: Rc<Cell<GbMode>>,
 
     pub(super) cartridge: Option<Box<dyn mbc::MemoryBankController>>,
+    pub(super) cartridge_crc: u32,
+    battery: Option<BatteryFile>,
+    pub(super) boot_rom: Option<BootRom>,
+    /// Whether the boot ROM still covers the start of the cartridge, until a write to FF50 unmaps it for good.
+    boot_rom_mapped: bool,
     pub(super) vram: Vram,
     wram: Wram,
     pub(super) oam: Oam,
@@ -33,6 +58,8 @@ pub struct MemoryMap {
     pub(super) ppu_state: PpuState,
     pub(super) color_map: Box<dyn ColorMap>,
     pub(super) hdma: Box<dyn HdmAccessor>,
+    /// Only present on SGB models.
+    pub(super) sgb: Option<Sgb>,
 
     dma_source: u8,
     dma_cycle: Option<u8>,
@@ -40,32 +67,50 @@ pub struct MemoryMap {
     hram: [u8; 0x7F],
     cgb_misc: [u8; 4],
     pub(super) interrupt_enable: InterruptEnable,
+
+    pub(super) symbols: SymbolTable,
+    pub(super) watchpoints: Vec<Watchpoint>,
+    /// The first watchpoint hit since the debugger last checked.
+    pub(super) watch_hit: Cell<Option<WatchHit>>,
 }
 
 impl MemoryMap {
-    pub fn new(model: Rc<Model>, mode: Rc<GbMode>, controllers: Arc<RwLock<ControllerState>>, graphics: Arc<RwLock<Graphics>>, proxy: EventLoopProxy<UserEvent>) -> Result<MemoryMap, HydraIOError> {
+    /// Builds the memory map of a freshly powered-on console. Any power-on values that vary between boots are drawn from `seed`.
+    /// Without a boot ROM, everything starts out as the boot ROM would have left it.
+    pub fn new(model: Rc<Model>, mode: Rc<Cell<GbMode>>, controllers: Arc<dyn ControllerPort>, boot_rom: Option<BootRom>, seed: u64) -> Result<MemoryMap, HydraIOError> {
+        let mut rng = StdRng::seed_from_u64(seed);
         let interrupt_flags = InterruptFlags::new();
         let interrupt_enable = InterruptEnable::new();
         let joypad = Joypad::new(&model, controllers);
         let serial = SerialConnection::new(mode.clone());
         let vram = Vram::new(model.clone(), mode.clone());
         let wram = Wram::new(mode.clone());
-        let ppu_state = PpuState::new(&model, graphics, proxy);
-        let timer = MasterTimer::new(model.clone(), mode.clone());
-        let color_map = colormap::from_mode(&mode);
+        let ppu_state = PpuState::new(&model, boot_rom.is_some(), &mut rng);
+        let timer = MasterTimer::new(model.clone(), mode.clone(), boot_rom.is_some(), &mut rng);
+        let color_map = match (mode.get(), model.is_color()) {
+            // Without a boot ROM, DMG games' palettes are picked later on by `select_compatibility_palettes`.
+            // Otherwise, the CGB boot ROM colorizes them by filling in palette RAM in CGB mode, then switches to compatibility mode through KEY0.
+            (GbMode::DMG, true) => colormap::for_compatibility(None),
+            _ => colormap::from_mode(&mode.get()),
+        };
         let oam = Oam::new(mode.clone());
         let apu_state = ApuState::new(model.clone());
         let dma_source = match model.is_monochrome() {
             true => 0xFF,
             false => 0x00,
         };
-        let hdma = hdma::from_mode(&mode);
+        let hdma = hdma::from_mode(&mode.get());
+        let sgb = model.is_super().then(Sgb::new);
 
         Ok(MemoryMap {
             model,
             mode,
 
             cartridge: None,
+            cartridge_crc: 0,
+            battery: None,
+            boot_rom_mapped: boot_rom.is_some(),
+            boot_rom,
             vram,
             wram,
             oam,
@@ -78,6 +123,7 @@ impl MemoryMap {
             ppu_state,
             color_map,
             hdma,
+            sgb,
 
             dma_source,
             dma_cycle: None,
@@ -85,14 +131,126 @@ impl MemoryMap {
             hram: [0; 0x7F],
             cgb_misc: [0; 4],
             interrupt_enable,
+
+            symbols: SymbolTable::default(),
+            watchpoints: Vec::new(),
+            watch_hit: Cell::new(None),
         })
     }
 
-    pub fn hot_swap_rom(&mut self, header: RomHeader, device: Arc<Device>, queue: Arc<Queue>) -> Result<(), HydraIOError> {
-        self.cartridge = Some(header.into_mbc(self.joypad.controllers.clone(), device, queue)?);
+    /// Colors a DMG game running on a CGB the way the boot ROM would have, when there isn't one to run.
+    /// Any button combination held on the host's controllers (or given with `press_button`/`press_dpad`) picks a palette manually.
+    pub fn select_compatibility_palettes(&mut self, header: &RomHeader) {
+        if !self.model.is_color() || !matches!(self.mode.get(), GbMode::DMG) || self.boot_rom.is_some() {
+            return;
+        }
+        self.joypad.update_controller_vecs(&mut self.interrupt_flags);
+        let palettes = CompatibilityPalettes::select(header, &self.joypad.get_host_inputs());
+        self.color_map = colormap::for_compatibility(Some(&palettes));
+    }
+
+    /// Replaces the inserted cartridge, saving the old cartridge's RAM first.
+    /// If the new cartridge has a battery and a save path is given, its RAM is loaded from (and later saved to) that file.
+    pub fn hot_swap_rom(&mut self, header: RomHeader, save_path: Option<PathBuf>) -> Result<(), HydraIOError> {
+        self.flush_battery()?;
+
+        let has_battery = header.has_battery();
+        self.cartridge_crc = header.get_crc32();
+        if let Some(sgb) = &mut self.sgb {
+            sgb.enable_commands(header.supports_sgb());
+        }
+        let mut cartridge = header.into_mbc(self.joypad.controllers.clone())?;
+        self.battery = match save_path {
+            Some(path) if has_battery => {
+                let mut battery = BatteryFile::new(path);
+                battery.load(cartridge.as_mut())?;
+                Some(battery)
+            }
+            _ => None,
+        };
+        self.cartridge = Some(cartridge);
+        Ok(())
+    }
+
+    /// Writes any unsaved cartridge RAM to its save file, then stops saving to it until the next `hot_swap_rom`.
+    pub fn detach_battery(&mut self) -> Result<(), HydraIOError> {
+        self.flush_battery()?;
+        self.battery = None;
         Ok(())
     }
 
+    /// Writes the cartridge's battery-backed RAM to its save file, if it has changed.
+    pub fn flush_battery(&mut self) -> Result<(), HydraIOError> {
+        match (&mut self.battery, &self.cartridge) {
+            (Some(battery), Some(mbc)) => battery.flush(mbc.as_ref()),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_joyp(&mut self, val: u8) {
+        self.joypad.write_joyp(val, &mut self.interrupt_flags);
+        if let Some(sgb) = &mut self.sgb
+            && let Some(packet) = self.joypad.take_sgb_packet()
+        {
+            sgb.receive_packet(packet, &mut self.joypad, &mut self.interrupt_flags);
+        }
+    }
+
+    /// Sends every completed frame to the given output (such as a window). On an SGB, frames are sent with the border drawn around them.
+    pub fn attach_video_output(&mut self, output: Box<dyn VideoOutput>) {
+        match &mut self.sgb {
+            Some(sgb) => sgb.attach_output(output),
+            None => self.ppu_state.attach_output(output),
+        }
+    }
+
+    /// Detaches and returns the current video output, if any.
+    pub fn take_video_output(&mut self) -> Option<Box<dyn VideoOutput>> {
+        match &mut self.sgb {
+            Some(sgb) => sgb.take_output(),
+            None => self.ppu_state.take_output(),
+        }
+    }
+
+    /// Sends the most recently completed frame to the attached output again.
+    pub fn resend_frame(&mut self) {
+        match &mut self.sgb {
+            Some(sgb) => sgb.resend_frame(),
+            None => self.ppu_state.resend_frame(),
+        }
+    }
+
+    /// Returns the most recently completed frame as tightly packed RGBA8 pixels, at the size given by `get_frame_size`.
+    pub fn get_frame(&self) -> &[u8] {
+        match &self.sgb {
+            Some(sgb) => sgb.get_frame(),
+            None => self.ppu_state.get_frame(),
+        }
+    }
+
+    /// Returns the width and height of each frame, which on an SGB includes the border.
+    pub fn get_frame_size(&self) -> (u32, u32) {
+        match &self.sgb {
+            Some(_) => (sgb::FRAME_WIDTH as u32, sgb::FRAME_HEIGHT as u32),
+            None => (ppu::SCREEN_WIDTH as u32, ppu::SCREEN_HEIGHT as u32),
+        }
+    }
+
+    /// Reads a byte from the boot ROM, or returns `None` if it's been unmapped or doesn't cover that address.
+    fn read_boot_rom(&self, address: u16) -> Option<u8> {
+        self.boot_rom.as_ref().filter(|_| self.boot_rom_mapped).and_then(|boot_rom| boot_rom.read(address))
+    }
+
+    /// Returns whether the boot ROM is still mapped (i.e. the cartridge hasn't started running yet).
+    pub fn is_boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
+    }
+
+    fn is_palette_ram_accessible(&self) -> bool {
+        // Locked in DMG compatibility mode, once the boot ROM has picked a palette
+        matches!(self.mode.get(), GbMode::CGB) || self.boot_rom_mapped
+    }
+
     fn is_cart_accessible(&self) -> bool {
         // Only when not performing OAM DMA (unless transferring from WRAM on GBC)
         matches!(self.dma_cycle, None) || (self.model.is_color() && !(0x0000..=0x7FFF).contains(&((self.dma_source as u16) << 8)))
@@ -117,7 +275,10 @@ impl MemoryMap {
         if let Some(cycle) = self.dma_cycle {
             let source_address = (self.dma_source as u16) << 8 | cycle as u16;
             let destination_address = 0xFE00 | cycle as u16;
-            self.oam.write(destination_address, self.read_u8(source_address, true));
+            let value = self.read_u8(source_address, BusAccessor::OamDma);
+            if self.oam.write(destination_address, value).is_ok() {
+                self.check_watchpoints(destination_address, value, BusAccessor::OamDma, true);
+            }
 
             let next_dma_cycle = cycle + 1;
             self.dma_cycle = if next_dma_cycle < 160 {
@@ -128,8 +289,74 @@ impl MemoryMap {
         }
     }
 
-    pub fn read_u8(&self, address: u16, is_dma: bool) -> u8 {
+    /// Returns the bank currently mapped to the given address in the banked regions (ROM, VRAM and WRAM), or 0 anywhere else.
+    pub fn get_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x7FFF => self.cartridge.as_ref().map_or(0, |mbc| mbc.rom_bank(address)),
+            0x8000..=0x9FFF => self.vram.get_bank_id() as usize,
+            0xC000..=0xDFFF => self.wram.get_bank(address) as usize,
+            0xE000..=0xFDFF => self.wram.get_bank(address - 0x2000) as usize,
+            _ => 0,
+        }
+    }
+
+    /// Describes an address for debugging output, as its current bank and address, followed by the nearest label (if any).
+    pub fn describe_address(&self, address: u16) -> String {
+        let bank = self.get_bank(address);
+        match self.symbols.locate(bank, address) {
+            Some(label) => format!("{:02X}:{:04X} <{}>", bank, address, label),
+            None => format!("{:02X}:{:04X}", bank, address),
+        }
+    }
+
+    /// Reads a byte for debugging purposes, regardless of whether the PPU or OAM DMA is currently blocking the CPU from it.
+    /// Unmapped addresses read as $FF without a warning, so dumping memory doesn't flood the console.
+    pub fn debug_read_u8(&self, address: u16) -> u8 {
         let read_result = match address {
+            0xE000..=0xFDFF => self.peek_bus(address - 0x2000, true),
+            0xFE00..=0xFEFF => self.oam.read(address),
+            0xFF00..=0xFFFF => self.peek_bus(address, false),
+            _ => self.peek_bus(address, true),
+        };
+        match read_result {
+            Ok(value) => value,
+            Err(HydraIOError::OpenBusAccess) => 0xFF,
+            Err(e) => panic!("Error reading from memory.\n{}", e),
+        }
+    }
+
+    /// Records the access if it triggers a watchpoint, unless another has already been hit.
+    fn check_watchpoints(&self, address: u16, value: u8, accessor: BusAccessor, is_write: bool) {
+        if self.watchpoints.is_empty() || self.watch_hit.get().is_some() {
+            return;
+        }
+        if let Some(index) = self.watchpoints.iter().position(|watchpoint| watchpoint.is_hit(address, value, is_write)) {
+            let bank = self.get_bank(address);
+            self.watch_hit.set(Some(WatchHit { index, address, bank, value, accessor, is_write }));
+        }
+    }
+
+    pub fn read_u8(&self, address: u16, accessor: BusAccessor) -> u8 {
+        let value = self.read_bus(address, accessor == BusAccessor::OamDma);
+        self.check_watchpoints(address, value, accessor, false);
+        value
+    }
+
+    fn read_bus(&self, address: u16, is_dma: bool) -> u8 {
+        match self.peek_bus(address, is_dma) {
+            Ok(value) => value,
+            Err(HydraIOError::OpenBusAccess) => {
+                println!("Warning: Read from open bus at address {}", self.describe_address(address));
+                0xFF
+            }
+            Err(e) => panic!("Error reading from memory.\n{}", e),
+        }
+    }
+
+    /// Looks up whatever is mapped at an address, or fails with `OpenBusAccess` if nothing is.
+    fn peek_bus(&self, address: u16, is_dma: bool) -> Result<u8, HydraIOError> {
+        match address {
+            0x0000..=0x00FF | 0x0200..=0x08FF if let Some(value) = self.read_boot_rom(address) => Ok(value),
             0x0000..=0x7FFF if is_dma || self.is_cart_accessible() => self.cartridge.as_ref().map(|this| this.read_rom_u8(address)).ok_or(HydraIOError::OpenBusAccess).flatten(),
             0x8000..=0x9FFF if is_dma || self.is_vram_accessible() => self.vram.read_u8(address),
             0xA000..=0xBFFF if is_dma || self.is_cart_accessible() => self.cartridge.as_ref().map(|this| this.read_ram_u8(address)).ok_or(HydraIOError::OpenBusAccess).flatten(),
@@ -144,29 +371,67 @@ impl MemoryMap {
             0xFF10..=0xFF14 | 0xFF16..=0xFF1E | 0xFF20..=0xFF26 | 0xFF30..=0xFF3F | 0xFF76..=0xFF77 => self.apu_state.read(address),
             0xFF40..=0xFF45 | 0xFF4A..=0xFF4B => self.ppu_state.read(address),
             0xFF46 => Ok(self.dma_source),
-            0xFF47..=0xFF49 | 0xFF68..=0xFF6B => self.color_map.read(address),
+            0xFF47..=0xFF49 => self.color_map.read(address),
+            0xFF68..=0xFF6B if self.is_palette_ram_accessible() => self.color_map.read(address),
             0xFF4F => self.vram.read_vbk(),
             0xFF51..=0xFF55 => self.hdma.read(address),
-            0xFF70 if matches!(*self.mode, GbMode::CGB) => Ok(self.wram.read_wbk()),
+            0xFF70 if matches!(self.mode.get(), GbMode::CGB) => Ok(self.wram.read_wbk()),
             0xFF72..=0xFF73 if self.model.is_color() => Ok(self.cgb_misc[address as usize - 0xFF72]),
-            0xFF74 if matches!(*self.mode, GbMode::CGB) => Ok(self.cgb_misc[2]),
+            0xFF74 if matches!(self.mode.get(), GbMode::CGB) => Ok(self.cgb_misc[2]),
             0xFF75 if self.model.is_color() => Ok(self.cgb_misc[3] | 0b10001111),
             0xFF80..=0xFFFE => Ok(self.hram[address as usize - 0xFF80]),
             0xFFFF => self.interrupt_enable.read(address),
             _ => Err(HydraIOError::OpenBusAccess)
+        }
+    }
+
+    /// Writes a byte for debugging purposes, regardless of whether the PPU or OAM DMA is currently blocking the CPU from it.
+    /// Watchpoints aren't triggered, but I/O registers still have their usual side effects.
+    /// Returns false if nothing was written, which is always the case for cartridge addresses (see `is_cartridge_address`).
+    pub fn debug_write_u8(&mut self, val: u8, address: u16) -> bool {
+        match address {
+            _ if Self::is_cartridge_address(address) => false,
+            0x8000..=0x9FFF => self.vram.write_u8(val, address).is_ok(),
+            0xC000..=0xDFFF => {self.wram.write_u8(val, address); true}
+            0xE000..=0xFDFF => {self.wram.write_u8(val, address - 0x2000); true}
+            0xFE00..=0xFEFF => self.oam.write(address, val).is_ok(),
+            _ => self.write_bus(val, address),
+        }
+    }
+
+    /// Whether an address belongs to the cartridge, where writes are commands to its memory bank controller
+    /// (switching banks, enabling RAM and so on), so debugging tools mustn't write there.
+    pub fn is_cartridge_address(address: u16) -> bool {
+        matches!(address, 0x0000..=0x7FFF | 0xA000..=0xBFFF)
+    }
+
+    pub fn write_u8(&mut self, val: u8, address: u16, accessor: BusAccessor) -> () {
+        // Writes blocked by the PPU or OAM DMA never reach memory, so they don't count as hits
+        if self.write_bus(val, address) {
+            self.check_watchpoints(address, val, accessor, true);
+        }
+    }
+
+    /// KEY0 picks between CGB mode and DMG compatibility mode. Only the boot ROM can write it, which it does just before unmapping itself.
+    fn write_key0(&mut self, val: u8) {
+        let mode = match val & 0b1100 {
+            0 => GbMode::CGB,
+            _ => GbMode::DMG,
         };
+        self.set_mode(mode);
+    }
 
-        match read_result {
-            Ok(value) => value,
-            Err(HydraIOError::OpenBusAccess) => {
-                println!("Warning: Read from open bus at address {:#06X}", address);
-                0xFF
-            }
-            Err(e) => panic!("Error reading from memory.\n{}", e),
+    /// Switches between CGB mode and DMG compatibility mode, which every other component sees through the shared `mode`.
+    fn set_mode(&mut self, mode: GbMode) {
+        if self.mode.get() != mode {
+            self.mode.set(mode);
+            self.hdma = hdma::from_mode(&mode);
         }
+        self.color_map.set_compatibility(matches!(mode, GbMode::DMG));
     }
 
-    pub fn write_u8(&mut self, val: u8, address: u16) -> () {
+    /// Returns whether the write landed, rather than being blocked (e.g. VRAM during mode 3) or going to open bus.
+    fn write_bus(&mut self, val: u8, address: u16) -> bool {
         let write_result = match address {
             0x0000..=0x7FFF if self.is_cart_accessible() => self.cartridge.as_mut().map(|this| this.write_rom_u8(val, address)).ok_or(HydraIOError::OpenBusAccess).flatten(),
             0x8000..=0x9FFF if self.is_vram_accessible() => self.vram.write_u8(val, address),
@@ -174,19 +439,22 @@ impl MemoryMap {
             0xC000..=0xDFFF if self.is_wram_accessible() => Ok(self.wram.write_u8(val, address)),
             0xE000..=0xFDFF if self.is_wram_accessible() => Ok(self.wram.write_u8(val, address - 0x2000)), // Treat exactly like WRAM
             0xFE00..=0xFEFF if self.is_oam_accessible() => self.oam.write(address, val),
-            0xFF00 => Ok(self.joypad.write_joyp(val, &mut self.interrupt_flags)),
+            0xFF00 => Ok(self.write_joyp(val)),
             0xFF01..=0xFF02 => self.serial.write(val, address),
             0xFF04..=0xFF07 | 0xFF4D => self.timer.write(val, address, &mut self.apu_state),
             0xFF0F => self.interrupt_flags.write(val, address),
             0xFF10..=0xFF14 | 0xFF16..=0xFF1E | 0xFF20..=0xFF26 | 0xFF30..=0xFF3F | 0xFF76..=0xFF77 => self.apu_state.write(val, address),
             0xFF40..=0xFF45 | 0xFF4A..=0xFF4B => self.ppu_state.write(val, address, &mut self.interrupt_flags),
             0xFF46 => Ok({self.dma_source = val; self.dma_cycle = Some(0);}),
-            0xFF47..=0xFF49 | 0xFF68..=0xFF6B => self.color_map.write(val, address),
+            0xFF47..=0xFF49 => self.color_map.write(val, address),
+            0xFF68..=0xFF6B if self.is_palette_ram_accessible() => self.color_map.write(val, address),
+            0xFF4C if self.model.is_color() && self.boot_rom_mapped => Ok(self.write_key0(val)),
             0xFF4F => self.vram.write_vbk(val),
+            0xFF50 => Ok(self.boot_rom_mapped &= !val.test_bit(0)),
             0xFF51..=0xFF55 => self.hdma.write(val, address, &self.ppu_state),
-            0xFF70 if matches!(*self.mode, GbMode::CGB) => Ok(self.wram.write_wbk(val)),
+            0xFF70 if matches!(self.mode.get(), GbMode::CGB) => Ok(self.wram.write_wbk(val)),
             0xFF72..=0xFF73 if self.model.is_color() => Ok(self.cgb_misc[address as usize - 0xFF72] = val),
-            0xFF74 if matches!(*self.mode, GbMode::CGB) => Ok(self.cgb_misc[2] = val),
+            0xFF74 if matches!(self.mode.get(), GbMode::CGB) => Ok(self.cgb_misc[2] = val),
             0xFF75 if self.model.is_color() => Ok(self.cgb_misc[3] = val | 0b10001111),
             0xFF80..=0xFFFE => Ok(self.hram[address as usize - 0xFF80] = val),
             0xFFFF => self.interrupt_enable.write(val, address),
@@ -194,13 +462,83 @@ impl MemoryMap {
         };
 
         match write_result {
-            Ok(_) => {}
-            Err(HydraIOError::OpenBusAccess) => {}//println!("Warning: Write to open bus at address {:#06X}", address),
+            Ok(_) => true,
+            Err(HydraIOError::OpenBusAccess) => false,//println!("Warning: Write to open bus at address {:#06X}", address),
             Err(e) => panic!("Error writing to memory.\n{}", e)
         }
     }
 }
 
+impl SaveState for MemoryMap {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.mode.get().save_state(state);
+        if let Some(mbc) = &self.cartridge {
+            mbc.save_state(state);
+        }
+        self.vram.save_state(state);
+        self.wram.save_state(state);
+        self.oam.save_state(state);
+
+        self.joypad.save_state(state);
+        self.serial.save_state(state);
+        self.timer.save_state(state);
+        self.interrupt_flags.save_state(state);
+        self.apu_state.save_state(state);
+        self.ppu_state.save_state(state);
+        self.color_map.save_state(state);
+        self.hdma.save_state(state);
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(state);
+        }
+
+        self.dma_source.save_state(state);
+        self.dma_cycle.save_state(state);
+
+        self.hram.save_state(state);
+        self.cgb_misc.save_state(state);
+        self.interrupt_enable.save_state(state);
+        self.boot_rom_mapped.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        // Comes first, since it decides which HDMA registers exist
+        let mut mode = self.mode.get();
+        mode.load_state(state)?;
+        self.set_mode(mode);
+        // Whether a cartridge is present is checked along with its checksum, before getting here
+        if let Some(mbc) = &mut self.cartridge {
+            mbc.load_state(state)?;
+        }
+        self.vram.load_state(state)?;
+        self.wram.load_state(state)?;
+        self.oam.load_state(state)?;
+
+        self.joypad.load_state(state)?;
+        self.serial.load_state(state)?;
+        self.timer.load_state(state)?;
+        self.interrupt_flags.load_state(state)?;
+        self.apu_state.load_state(state)?;
+        self.ppu_state.load_state(state)?;
+        self.color_map.load_state(state)?;
+        self.hdma.load_state(state)?;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.load_state(state)?;
+        }
+
+        self.dma_source.load_state(state)?;
+        self.dma_cycle.load_state(state)?;
+
+        self.hram.load_state(state)?;
+        self.cgb_misc.load_state(state)?;
+        self.interrupt_enable.load_state(state)?;
+        self.boot_rom_mapped.load_state(state)?;
+        if self.boot_rom_mapped && self.boot_rom.is_none() {
+            return Err(HydraIOError::IncompatibleSaveState("Made while a boot ROM was running, but no boot ROM is loaded"));
+        }
+        Ok(())
+    }
+}
+
 pub trait MemoryMapped {
     fn read(&self, address: u16) -> Result<u8, HydraIOError> {
         Err(HydraIOError::OpenBusAccess)
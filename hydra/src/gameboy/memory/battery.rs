@@ -0,0 +1,45 @@
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use crate::{common::errors::HydraIOError, gameboy::memory::mbc::MemoryBankController};
+
+/// A `.sav` file backing a cartridge's battery-buffered RAM.
+///
+/// The file holds the raw contents of cartridge RAM (banks in order, as used by most other emulators),
+/// followed by any extra state the cartridge appends, such as the MBC3 real-time clock.
+pub struct BatteryFile {
+    path: PathBuf,
+    last_written: Option<Vec<u8>>,
+}
+
+impl BatteryFile {
+    pub fn new(path: PathBuf) -> Self {
+        BatteryFile { path, last_written: None }
+    }
+
+    /// Restores a cartridge's RAM from this file, if it exists.
+    pub fn load(&mut self, mbc: &mut dyn MemoryBankController) -> Result<(), HydraIOError> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                mbc.load_save_data(&data);
+                self.last_written = Some(data);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                // Don't create a file until the game actually writes something
+                self.last_written = mbc.save_data();
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes a cartridge's RAM to this file, unless it's unchanged since the last write.
+    pub fn flush(&mut self, mbc: &dyn MemoryBankController) -> Result<(), HydraIOError> {
+        let Some(data) = mbc.save_data() else {return Ok(())};
+        if self.last_written.as_ref() != Some(&data) {
+            fs::write(&self.path, &data)?;
+            self.last_written = Some(data);
+        }
+        Ok(())
+    }
+}
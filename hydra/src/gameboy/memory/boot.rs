@@ -0,0 +1,34 @@
+use std::{fs, path::Path};
+
+use crate::{common::errors::HydraIOError, gameboy::Model};
+
+/// A dump of a console's boot ROM, which runs before the cartridge and is mapped over its first bytes until FF50 is written to.
+#[derive(Clone)]
+pub struct BootRom(Box<[u8]>);
+
+impl BootRom {
+    const MONOCHROME_SIZE: usize = 0x100;
+    /// CGB and AGB boot ROMs skip over the cartridge header, so 0x0100-0x01FF is left unused.
+    const COLOR_SIZE: usize = 0x900;
+
+    /// Wraps a boot ROM dump, checking that it's the right size for the given model.
+    pub fn from_vec(bytes: Vec<u8>, model: &Model) -> Result<Self, HydraIOError> {
+        match (model.is_color(), bytes.len()) {
+            (false, Self::MONOCHROME_SIZE) | (true, Self::COLOR_SIZE) => Ok(BootRom(bytes.into_boxed_slice())),
+            (false, _) => Err(HydraIOError::MalformedBootROM("DMG, MGB and SGB boot ROMs must be 256 bytes long")),
+            (true, _) => Err(HydraIOError::MalformedBootROM("CGB and AGB boot ROMs must be 2304 bytes long")),
+        }
+    }
+
+    pub fn from_file(path: &Path, model: &Model) -> Result<Self, HydraIOError> {
+        Self::from_vec(fs::read(path)?, model)
+    }
+
+    /// Reads a byte from the boot ROM, or returns `None` if the cartridge shows through at that address.
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x0000..=0x00FF | 0x0200..=0x08FF => self.0.get(address as usize).copied(),
+            _ => None,
+        }
+    }
+}
@@ -1,6 +1,6 @@
-use crate::{common::{errors::HydraIOError, timing::ModuloCounter}, deserialize, gameboy::{GbMode, memory::{MemoryMap, MemoryMapped}, ppu::{PpuMode, state::PpuState}}, serialize};
+use crate::{common::{errors::HydraIOError, savestate::{SaveState, StateReader, StateWriter}, timing::ModuloCounter}, deserialize, gameboy::{GbMode, memory::{BusAccessor, MemoryMap, MemoryMapped}, ppu::{PpuMode, state::PpuState}}, save_state_fields, serialize};
 
-pub trait HdmAccessor {
+pub trait HdmAccessor: SaveState {
     fn tick(&mut self, memory: &mut MemoryMap) -> bool;
     fn read(&self, address: u16) -> Result<u8, HydraIOError>;
     fn write(&mut self, val: u8, address: u16, ppu_state: &PpuState) -> Result<(), HydraIOError>;
@@ -29,6 +29,11 @@ impl HdmAccessor for DmgHdmAccessor {
     }
 }
 
+impl SaveState for DmgHdmAccessor {
+    fn save_state(&self, _state: &mut StateWriter) { /* Nothing to save */ }
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), HydraIOError> { Ok(()) }
+}
+
 pub struct CgbHdmAccessor {
     source_addr: u16,
     dest_addr: u16,
@@ -86,8 +91,8 @@ impl HdmAccessor for CgbHdmAccessor {
         if hdma_active_this_tick {
             let row_offset = self.row_counter.value as u16;
             let destination = (self.dest_addr & 0x1FF0) + 0x8000;
-            let val = memory.read_u8((self.source_addr & 0xFFF0) + row_offset, false);
-            memory.write_u8(val, destination + row_offset);
+            let val = memory.read_u8((self.source_addr & 0xFFF0) + row_offset, BusAccessor::Hdma);
+            memory.write_u8(val, destination + row_offset, BusAccessor::Hdma);
 
             if self.row_counter.increment() {
                 match self.length.checked_sub(1) {
@@ -130,6 +135,8 @@ impl HdmAccessor for CgbHdmAccessor {
     }
 }
 
+save_state_fields!(CgbHdmAccessor { source_addr, dest_addr, length, transfer_type, row_counter });
+
 enum HdmaType {
     General,
     Hblank(u8),
@@ -140,4 +147,33 @@ impl HdmaType {
     pub(self) fn as_u1(&self) -> u8 {
         matches!(*self, Self::Complete) as u8
     }
+}
+
+impl SaveState for HdmaType {
+    fn save_state(&self, state: &mut StateWriter) {
+        match self {
+            HdmaType::General => 0u8.save_state(state),
+            HdmaType::Hblank(next_hblank) => {
+                1u8.save_state(state);
+                next_hblank.save_state(state);
+            }
+            HdmaType::Complete => 2u8.save_state(state),
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let mut transfer_type = 0u8;
+        transfer_type.load_state(state)?;
+        *self = match transfer_type {
+            0 => HdmaType::General,
+            1 => {
+                let mut next_hblank = 0;
+                next_hblank.load_state(state)?;
+                HdmaType::Hblank(next_hblank)
+            }
+            2 => HdmaType::Complete,
+            _ => return Err(HydraIOError::MalformedSaveState("Invalid HDMA type")),
+        };
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,562 @@
+use std::fmt;
+
+use crate::{common::errors::HydraIOError, gameboy::memory::rom::{CGB_FLAG_ADDRESS, HARDWARE_ADDRESS, HEADER_CHECKSUM_ADDRESS, NEW_LICENSEE_CODE_ADDRESS, OLD_LICENSEE_CODE_ADDRESS, RAM_SIZE_ADDRESS, ROM_SIZE_ADDRESS, RomHeader, SGB_FLAG_ADDRESS}};
+
+pub const LOGO_ADDRESS: usize = 0x0104;
+pub const MANUFACTURER_CODE_ADDRESS: usize = 0x013F;
+pub const DESTINATION_CODE_ADDRESS: usize = 0x014A;
+pub const VERSION_ADDRESS: usize = 0x014C;
+pub const GLOBAL_CHECKSUM_ADDRESS: usize = 0x014E;
+const HEADER_END: usize = 0x0150;
+
+/// The logo every licensed cartridge carries, which the boot ROM refuses to run without.
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Everything described by a cartridge's header, read without rejecting anything invalid, so bad dumps can be diagnosed.
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    /// The old licensee code, or the new (two character) licensee code if the old one is $33.
+    pub licensee: Licensee,
+    pub cartridge_type: u8,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub destination_code: u8,
+    pub version: u8,
+    pub logo_valid: bool,
+    pub header_checksum: u8,
+    pub computed_header_checksum: u8,
+    pub global_checksum: u16,
+    pub computed_global_checksum: u16,
+    pub file_size: usize,
+}
+
+pub enum Licensee {
+    Old(u8),
+    New([u8; 2]),
+}
+
+impl CartridgeHeader {
+    /// Reads the header of a ROM file. Only fails if the file is too small to contain a header.
+    pub fn parse(rom: &[u8]) -> Result<Self, HydraIOError> {
+        if rom.len() < HEADER_END {
+            return Err(HydraIOError::MalformedROM("File is too small to contain a cartridge header"));
+        }
+
+        // Later cartridges took the end of the title for the CGB flag and a manufacturer code, but there's no flag saying which is which.
+        // A manufacturer code is assumed if the cartridge supports CGB and those four bytes are all uppercase letters or digits.
+        let cgb_flag = rom[CGB_FLAG_ADDRESS];
+        let has_cgb_flag = cgb_flag & 0x80 != 0;
+        let manufacturer_bytes = &rom[MANUFACTURER_CODE_ADDRESS..CGB_FLAG_ADDRESS];
+        let has_manufacturer_code = has_cgb_flag && manufacturer_bytes.iter().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+        let title_end = match (has_cgb_flag, has_manufacturer_code) {
+            (_, true) => MANUFACTURER_CODE_ADDRESS,
+            (true, false) => CGB_FLAG_ADDRESS,
+            (false, false) => CGB_FLAG_ADDRESS + 1,
+        };
+
+        let licensee = match rom[OLD_LICENSEE_CODE_ADDRESS] {
+            0x33 => Licensee::New([rom[NEW_LICENSEE_CODE_ADDRESS], rom[NEW_LICENSEE_CODE_ADDRESS + 1]]),
+            code => Licensee::Old(code),
+        };
+        let computed_header_checksum = rom[0x0134..HEADER_CHECKSUM_ADDRESS].iter().fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+        let global_checksum_bytes = GLOBAL_CHECKSUM_ADDRESS..GLOBAL_CHECKSUM_ADDRESS + 2;
+        let computed_global_checksum = rom.iter().enumerate()
+            .filter(|(address, _)| !global_checksum_bytes.contains(address))
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+
+        Ok(CartridgeHeader {
+            title: decode_ascii(&rom[0x0134..title_end]),
+            manufacturer_code: has_manufacturer_code.then(|| decode_ascii(manufacturer_bytes)),
+            cgb_flag,
+            sgb_flag: rom[SGB_FLAG_ADDRESS],
+            licensee,
+            cartridge_type: rom[HARDWARE_ADDRESS],
+            rom_size_code: rom[ROM_SIZE_ADDRESS],
+            ram_size_code: rom[RAM_SIZE_ADDRESS],
+            destination_code: rom[DESTINATION_CODE_ADDRESS],
+            version: rom[VERSION_ADDRESS],
+            logo_valid: rom[LOGO_ADDRESS..LOGO_ADDRESS + NINTENDO_LOGO.len()] == NINTENDO_LOGO,
+            header_checksum: rom[HEADER_CHECKSUM_ADDRESS],
+            computed_header_checksum,
+            global_checksum: u16::from_be_bytes([rom[GLOBAL_CHECKSUM_ADDRESS], rom[GLOBAL_CHECKSUM_ADDRESS + 1]]),
+            computed_global_checksum,
+            file_size: rom.len(),
+        })
+    }
+
+    pub fn get_rom_size(&self) -> Option<usize> {
+        RomHeader::rom_size_from_code(self.rom_size_code)
+    }
+
+    pub fn get_ram_size(&self) -> Option<usize> {
+        match self.ram_size_code {
+            0x00 => Some(0),
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        }
+    }
+
+    pub fn get_cartridge_type_name(&self) -> Option<&'static str> {
+        Some(match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => return None,
+        })
+    }
+
+    pub fn get_licensee_name(&self) -> Option<&'static str> {
+        match self.licensee {
+            Licensee::Old(code) => old_licensee_name(code),
+            Licensee::New(code) => new_licensee_name(&code),
+        }
+    }
+
+    /// Lists everything wrong with the header, most severe first. Cartridges with undefined sizes or hardware can't be run at all,
+    /// and real hardware refuses to boot cartridges with a bad logo or header checksum. The global checksum is never checked by hardware.
+    pub fn get_problems(&self) -> Vec<&'static str> {
+        let mut problems = Vec::new();
+        if self.get_rom_size().is_none() {
+            problems.push("undefined ROM size");
+        }
+        if self.get_ram_size().is_none() {
+            problems.push("undefined RAM size");
+        }
+        if self.get_cartridge_type_name().is_none() {
+            problems.push("undefined cartridge type");
+        }
+        if self.get_rom_size().is_some_and(|size| self.file_size < size) {
+            problems.push("file is smaller than the ROM size in its header");
+        }
+        if !self.logo_valid {
+            problems.push("Nintendo logo doesn't match");
+        }
+        if self.header_checksum != self.computed_header_checksum {
+            problems.push("header checksum doesn't match");
+        }
+        if self.global_checksum != self.computed_global_checksum {
+            problems.push("global checksum doesn't match");
+        }
+        problems
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let check = |is_valid: bool| if is_valid {"OK"} else {"MISMATCH"};
+        let size = |bytes: usize| match bytes {
+            0 => "None".to_string(),
+            bytes if bytes < 0x100000 => format!("{} KiB", bytes >> 10),
+            bytes => format!("{} KiB ({:.1} MiB)", bytes >> 10, bytes as f64 / 0x100000 as f64),
+        };
+
+        writeln!(f, "Title: {}", self.title)?;
+        if let Some(code) = &self.manufacturer_code {
+            writeln!(f, "Manufacturer code: {}", code)?;
+        }
+        let cgb_support = match self.cgb_flag {
+            0xC0 => "CGB only",
+            flag if flag & 0x80 != 0 => "CGB enhanced",
+            _ => "None",
+        };
+        writeln!(f, "CGB support: {} (${:02X})", cgb_support, self.cgb_flag)?;
+        writeln!(f, "SGB support: {} (${:02X})", if self.sgb_flag == 0x03 {"Yes"} else {"No"}, self.sgb_flag)?;
+        let licensee_code = match self.licensee {
+            Licensee::Old(code) => format!("${:02X}", code),
+            Licensee::New(code) => format!("\"{}\"", decode_ascii(&code)),
+        };
+        writeln!(f, "Licensee: {} ({})", self.get_licensee_name().unwrap_or("Unknown"), licensee_code)?;
+        writeln!(f, "Cartridge type: {} (${:02X})", self.get_cartridge_type_name().unwrap_or("Undefined"), self.cartridge_type)?;
+        let rom_size = self.get_rom_size().map(|bytes| format!("{}, {} banks", size(bytes), bytes / 0x4000));
+        writeln!(f, "ROM size: {} (${:02X})", rom_size.as_deref().unwrap_or("Undefined"), self.rom_size_code)?;
+        writeln!(f, "RAM size: {} (${:02X})", self.get_ram_size().map(size).as_deref().unwrap_or("Undefined"), self.ram_size_code)?;
+        writeln!(f, "File size: {}", size(self.file_size))?;
+        let destination = match self.destination_code {
+            0x00 => "Japan",
+            0x01 => "Overseas",
+            _ => "Unknown",
+        };
+        writeln!(f, "Destination: {} (${:02X})", destination, self.destination_code)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Nintendo logo: {}", check(self.logo_valid))?;
+        writeln!(f, "Header checksum: ${:02X}, computed ${:02X} ({})", self.header_checksum, self.computed_header_checksum, check(self.header_checksum == self.computed_header_checksum))?;
+        write!(f, "Global checksum: ${:04X}, computed ${:04X} ({})", self.global_checksum, self.computed_global_checksum, check(self.global_checksum == self.computed_global_checksum))
+    }
+}
+
+/// Decodes text from the header, stopping at the first NUL and replacing anything unprintable.
+fn decode_ascii(bytes: &[u8]) -> String {
+    bytes.iter()
+        .take_while(|byte| **byte != 0)
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' {byte as char} else {'?'})
+        .collect()
+}
+
+fn new_licensee_name(code: &[u8; 2]) -> Option<&'static str> {
+    Some(match code {
+        b"00" => "None",
+        b"01" => "Nintendo Research & Development 1",
+        b"08" => "Capcom",
+        b"13" => "EA (Electronic Arts)",
+        b"18" => "Hudson Soft",
+        b"19" => "B-AI",
+        b"20" => "KSS",
+        b"22" => "Planning Office WADA",
+        b"24" => "PCM Complete",
+        b"25" => "San-X",
+        b"28" => "Kemco",
+        b"29" => "SETA Corporation",
+        b"30" => "Viacom",
+        b"31" => "Nintendo",
+        b"32" => "Bandai",
+        b"33" => "Ocean Software/Acclaim Entertainment",
+        b"34" => "Konami",
+        b"35" => "HectorSoft",
+        b"37" => "Taito",
+        b"38" => "Hudson Soft",
+        b"39" => "Banpresto",
+        b"41" => "Ubi Soft",
+        b"42" => "Atlus",
+        b"44" => "Malibu Interactive",
+        b"46" => "Angel",
+        b"47" => "Bullet-Proof Software",
+        b"49" => "Irem",
+        b"50" => "Absolute",
+        b"51" => "Acclaim Entertainment",
+        b"52" => "Activision",
+        b"53" => "Sammy USA Corporation",
+        b"54" => "Konami",
+        b"55" => "Hi Tech Expressions",
+        b"56" => "LJN",
+        b"57" => "Matchbox",
+        b"58" => "Mattel",
+        b"59" => "Milton Bradley Company",
+        b"60" => "Titus Interactive",
+        b"61" => "Virgin Games Ltd.",
+        b"64" => "Lucasfilm Games",
+        b"67" => "Ocean Software",
+        b"69" => "EA (Electronic Arts)",
+        b"70" => "Infogrames",
+        b"71" => "Interplay Entertainment",
+        b"72" => "Broderbund",
+        b"73" => "Sculptured Software",
+        b"75" => "The Sales Curve Limited",
+        b"78" => "THQ",
+        b"79" => "Accolade",
+        b"80" => "Misawa Entertainment",
+        b"83" => "LOZC G.",
+        b"86" => "Tokuma Shoten",
+        b"87" => "Tsukuda Original",
+        b"91" => "Chunsoft Co.",
+        b"92" => "Video System",
+        b"93" => "Ocean Software/Acclaim Entertainment",
+        b"95" => "Varie",
+        b"96" => "Yonezawa/S'Pal",
+        b"97" => "Kaneko",
+        b"99" => "Pack-In-Video",
+        b"9H" => "Bottom Up",
+        b"A4" => "Konami (Yu-Gi-Oh!)",
+        b"BL" => "MTO",
+        b"DK" => "Kodansha",
+        _ => return None,
+    })
+}
+
+fn old_licensee_name(code: u8) -> Option<&'static str> {
+    Some(match code {
+        0x00 => "None",
+        0x01 => "Nintendo",
+        0x08 => "Capcom",
+        0x09 => "HOT-B",
+        0x0A => "Jaleco",
+        0x0B => "Coconuts Japan",
+        0x0C => "Elite Systems",
+        0x13 => "EA (Electronic Arts)",
+        0x18 => "Hudson Soft",
+        0x19 => "ITC Entertainment",
+        0x1A => "Yanoman",
+        0x1D => "Japan Clary",
+        0x1F => "Virgin Games Ltd.",
+        0x24 => "PCM Complete",
+        0x25 => "San-X",
+        0x28 => "Kemco",
+        0x29 => "SETA Corporation",
+        0x30 => "Infogrames",
+        0x31 => "Nintendo",
+        0x32 => "Bandai",
+        0x34 => "Konami",
+        0x35 => "HectorSoft",
+        0x38 => "Capcom",
+        0x39 => "Banpresto",
+        0x3C => "Entertainment Interactive",
+        0x3E => "Gremlin",
+        0x41 => "Ubi Soft",
+        0x42 => "Atlus",
+        0x44 => "Malibu Interactive",
+        0x46 => "Angel",
+        0x47 => "Spectrum HoloByte",
+        0x49 => "Irem",
+        0x4A => "Virgin Games Ltd.",
+        0x4D => "Malibu Interactive",
+        0x4F => "U.S. Gold",
+        0x50 => "Absolute",
+        0x51 => "Acclaim Entertainment",
+        0x52 => "Activision",
+        0x53 => "Sammy USA Corporation",
+        0x54 => "GameTek",
+        0x55 => "Park Place",
+        0x56 => "LJN",
+        0x57 => "Matchbox",
+        0x59 => "Milton Bradley Company",
+        0x5A => "Mindscape",
+        0x5B => "Romstar",
+        0x5C => "Naxat Soft",
+        0x5D => "Tradewest",
+        0x60 => "Titus Interactive",
+        0x61 => "Virgin Games Ltd.",
+        0x67 => "Ocean Software",
+        0x69 => "EA (Electronic Arts)",
+        0x6E => "Elite Systems",
+        0x6F => "Electro Brain",
+        0x70 => "Infogrames",
+        0x71 => "Interplay Entertainment",
+        0x72 => "Broderbund",
+        0x73 => "Sculptured Software",
+        0x75 => "The Sales Curve Limited",
+        0x78 => "THQ",
+        0x79 => "Accolade",
+        0x7A => "Triffix Entertainment",
+        0x7C => "MicroProse",
+        0x7F => "Kemco",
+        0x80 => "Misawa Entertainment",
+        0x83 => "LOZC G.",
+        0x86 => "Tokuma Shoten",
+        0x8B => "Bullet-Proof Software",
+        0x8C => "Vic Tokai Corp.",
+        0x8E => "Ape Inc.",
+        0x8F => "I'Max",
+        0x91 => "Chunsoft Co.",
+        0x92 => "Video System",
+        0x93 => "Tsubaraya Productions",
+        0x95 => "Varie",
+        0x96 => "Yonezawa/S'Pal",
+        0x97 => "Kemco",
+        0x99 => "Arc",
+        0x9A => "Nihon Bussan",
+        0x9B => "Tecmo",
+        0x9C => "Imagineer",
+        0x9D => "Banpresto",
+        0x9F => "Nova",
+        0xA1 => "Hori Electric",
+        0xA2 => "Bandai",
+        0xA4 => "Konami",
+        0xA6 => "Kawada",
+        0xA7 => "Takara",
+        0xA9 => "Technos Japan",
+        0xAA => "Broderbund",
+        0xAC => "Toei Animation",
+        0xAD => "Toho",
+        0xAF => "Namco",
+        0xB0 => "Acclaim Entertainment",
+        0xB1 => "ASCII Corporation or Nexsoft",
+        0xB2 => "Bandai",
+        0xB4 => "Square Enix",
+        0xB6 => "HAL Laboratory",
+        0xB7 => "SNK",
+        0xB9 => "Pony Canyon",
+        0xBA => "Culture Brain",
+        0xBB => "Sunsoft",
+        0xBD => "Sony Imagesoft",
+        0xBF => "Sammy Corporation",
+        0xC0 => "Taito",
+        0xC2 => "Kemco",
+        0xC3 => "Square",
+        0xC4 => "Tokuma Shoten",
+        0xC5 => "Data East",
+        0xC6 => "Tonkin House",
+        0xC8 => "Koei",
+        0xC9 => "UFL",
+        0xCA => "Ultra Games",
+        0xCB => "VAP, Inc.",
+        0xCC => "Use Corporation",
+        0xCD => "Meldac",
+        0xCE => "Pony Canyon",
+        0xCF => "Angel",
+        0xD0 => "Taito",
+        0xD1 => "SOFEL",
+        0xD2 => "Quest",
+        0xD3 => "Sigma Enterprises",
+        0xD4 => "ASK Kodansha Co.",
+        0xD6 => "Naxat Soft",
+        0xD7 => "Copya System",
+        0xD9 => "Banpresto",
+        0xDA => "Tomy",
+        0xDB => "LJN",
+        0xDD => "Nippon Computer Systems",
+        0xDE => "Human Ent.",
+        0xDF => "Altron",
+        0xE0 => "Jaleco",
+        0xE1 => "Towa Chiki",
+        0xE2 => "Yutaka",
+        0xE3 => "Varie",
+        0xE5 => "Epoch",
+        0xE7 => "Athena",
+        0xE8 => "Asmik Ace Entertainment",
+        0xE9 => "Natsume",
+        0xEA => "King Records",
+        0xEB => "Atlus",
+        0xEC => "Epic/Sony Records",
+        0xEE => "IGS",
+        0xF0 => "A Wave",
+        0xF3 => "Extreme Entertainment",
+        0xFF => "LJN",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a 32 KiB ROM-only cartridge with a valid logo and checksums, after letting `edit` change its header.
+    fn synthetic_rom(edit: impl FnOnce(&mut [u8])) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[LOGO_ADDRESS..LOGO_ADDRESS + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x0134..0x0134 + 6].copy_from_slice(b"TETRIS");
+        rom[OLD_LICENSEE_CODE_ADDRESS] = 0x01;
+        edit(&mut rom);
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    fn fix_checksums(rom: &mut [u8]) {
+        let mut header_checksum = 0u8;
+        for &byte in &rom[0x0134..=0x014C] {
+            header_checksum = header_checksum.wrapping_sub(byte).wrapping_sub(1);
+        }
+        rom[HEADER_CHECKSUM_ADDRESS] = header_checksum;
+        rom[GLOBAL_CHECKSUM_ADDRESS..GLOBAL_CHECKSUM_ADDRESS + 2].fill(0);
+        let global_checksum = rom.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        rom[GLOBAL_CHECKSUM_ADDRESS..GLOBAL_CHECKSUM_ADDRESS + 2].copy_from_slice(&global_checksum.to_be_bytes());
+    }
+
+    #[test]
+    fn valid_header() {
+        let header = CartridgeHeader::parse(&synthetic_rom(|_| {})).unwrap();
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.manufacturer_code, None);
+        assert_eq!(header.get_licensee_name(), Some("Nintendo"));
+        assert_eq!(header.get_cartridge_type_name(), Some("ROM ONLY"));
+        assert_eq!((header.get_rom_size(), header.get_ram_size()), (Some(0x8000), Some(0)));
+        assert!(header.logo_valid);
+        assert_eq!(header.header_checksum, header.computed_header_checksum);
+        assert_eq!(header.global_checksum, header.computed_global_checksum);
+        assert!(header.get_problems().is_empty(), "{:?}", header.get_problems());
+    }
+
+    #[test]
+    fn title_and_manufacturer_code() {
+        // Without a CGB flag, the title runs through 0x0143
+        let header = CartridgeHeader::parse(&synthetic_rom(|rom| rom[0x0134..0x0144].copy_from_slice(b"SIXTEEN CHARS AB"))).unwrap();
+        assert_eq!((header.title.as_str(), header.manufacturer_code), ("SIXTEEN CHARS AB", None));
+
+        // With one, a manufacturer code is only split off if it looks like one
+        let header = CartridgeHeader::parse(&synthetic_rom(|rom| {
+            rom[0x0134..0x0144].copy_from_slice(b"POKEMON_GLDAAUE\x80");
+        })).unwrap();
+        assert_eq!((header.title.as_str(), header.manufacturer_code.as_deref(), header.cgb_flag), ("POKEMON_GLD", Some("AAUE"), 0x80));
+        let header = CartridgeHeader::parse(&synthetic_rom(|rom| {
+            rom[0x0134..0x0144].copy_from_slice(b"ZELDA\0\0\0\0\0\0\0\0\0\0\xC0");
+        })).unwrap();
+        assert_eq!((header.title.as_str(), header.manufacturer_code), ("ZELDA", None));
+        let header = CartridgeHeader::parse(&synthetic_rom(|rom| {
+            rom[0x0134..0x0144].copy_from_slice(b"Mixed Case Name\x80");
+        })).unwrap();
+        assert_eq!((header.title.as_str(), header.manufacturer_code), ("Mixed Case Name", None));
+    }
+
+    #[test]
+    fn new_licensee_code() {
+        let header = CartridgeHeader::parse(&synthetic_rom(|rom| {
+            rom[OLD_LICENSEE_CODE_ADDRESS] = 0x33;
+            rom[NEW_LICENSEE_CODE_ADDRESS..NEW_LICENSEE_CODE_ADDRESS + 2].copy_from_slice(b"08");
+        })).unwrap();
+        assert!(matches!(header.licensee, Licensee::New(code) if &code == b"08"));
+        assert_eq!(header.get_licensee_name(), Some("Capcom"));
+    }
+
+    #[test]
+    fn checksums() {
+        // A changed header byte breaks both checksums, while a changed byte elsewhere only breaks the global one
+        let mut rom = synthetic_rom(|_| {});
+        rom[VERSION_ADDRESS] = 1;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.computed_header_checksum, header.header_checksum.wrapping_sub(1));
+        assert_eq!(header.computed_global_checksum, header.global_checksum.wrapping_add(1));
+        assert_eq!(header.get_problems(), ["header checksum doesn't match", "global checksum doesn't match"]);
+
+        let mut rom = synthetic_rom(|_| {});
+        rom[0x4000] = 0xFF;
+        assert_eq!(CartridgeHeader::parse(&rom).unwrap().get_problems(), ["global checksum doesn't match"]);
+
+        // The global checksum's own bytes are left out of the sum
+        let mut rom = synthetic_rom(|_| {});
+        rom[GLOBAL_CHECKSUM_ADDRESS] ^= 0xFF;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.computed_global_checksum, u16::from_be_bytes([rom[GLOBAL_CHECKSUM_ADDRESS] ^ 0xFF, rom[GLOBAL_CHECKSUM_ADDRESS + 1]]));
+    }
+
+    #[test]
+    fn problems() {
+        let header = CartridgeHeader::parse(&synthetic_rom(|rom| {
+            rom[LOGO_ADDRESS] = 0x00;
+            rom[HARDWARE_ADDRESS] = 0x04;
+            rom[ROM_SIZE_ADDRESS] = 0x20;
+            rom[RAM_SIZE_ADDRESS] = 0x06;
+        })).unwrap();
+        assert_eq!(header.get_problems(), ["undefined ROM size", "undefined RAM size", "undefined cartridge type", "Nintendo logo doesn't match"]);
+
+        let mut rom = synthetic_rom(|rom| rom[ROM_SIZE_ADDRESS] = 0x01);
+        rom.truncate(HEADER_END);
+        fix_checksums(&mut rom);
+        assert_eq!(CartridgeHeader::parse(&rom).unwrap().get_problems(), ["file is smaller than the ROM size in its header"]);
+
+        assert!(CartridgeHeader::parse(&rom[..HEADER_END - 1]).is_err());
+    }
+}
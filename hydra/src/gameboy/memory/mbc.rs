@@ -8,17 +8,30 @@ pub mod mbc7;
 // pub mod mmm01;
 pub mod huc1;
 pub mod huc3;
+#[cfg(feature = "frontend")]
 pub mod pocketcamera;
 pub mod tama5;
 
-use crate::common::errors::HydraIOError;
+use std::time::SystemTime;
 
-pub trait MemoryBankController {
+use crate::common::{errors::HydraIOError, savestate::SaveState};
+
+pub trait MemoryBankController: SaveState {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError>;
     fn read_ram_u8(&self, address: u16) -> Result<u8, HydraIOError>;
     fn write_rom_u8(&mut self, value: u8, address: u16) -> Result<(), HydraIOError>;
     fn write_ram_u8(&mut self, value: u8, address: u16) -> Result<(), HydraIOError>;
+    /// Returns the ROM bank currently mapped to the given address (`0x0000..=0x7FFF`).
+    fn rom_bank(&self, address: u16) -> usize;
 
     fn tick(&mut self) { /* Do nothing by default */ }
     fn frame(&mut self) { /* Do nothing by default */ }
+    /// Drives the cartridge's real-time clock (if any) from emulated frames, counting up from `start`, rather than the host's clock.
+    /// `None` hands it back to the host's clock.
+    fn set_emulated_clock(&mut self, _start: Option<SystemTime>) { /* Do nothing by default */ }
+
+    /// Returns the contents of this cartridge's RAM, laid out as in a `.sav` file.
+    fn save_data(&self) -> Option<Vec<u8>> { None }
+    /// Restores this cartridge's RAM from the contents of a `.sav` file.
+    fn load_save_data(&mut self, _data: &[u8]) { /* Do nothing by default */ }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
+use crate::save_state_fields;
 
 pub struct HuC1 {
     rom: Rom<0x4000>,
@@ -41,6 +42,8 @@ impl HuC1 {
     }
 }
 
+save_state_fields!(HuC1 { ram, ram_enabled, rom_bank, ram_bank });
+
 impl mbc::MemoryBankController for HuC1 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -73,4 +76,16 @@ impl mbc::MemoryBankController for HuC1 {
             Ok(())
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.ram.load_from(data);
+    }
 }
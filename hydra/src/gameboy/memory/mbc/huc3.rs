@@ -3,7 +3,7 @@ use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
-use crate::{deserialize, serialize};
+use crate::{deserialize, save_state_enum, save_state_fields, serialize};
 
 pub struct HuC3 {
     rom: Rom<0x4000>,
@@ -56,6 +56,8 @@ impl HuC3 {
     }
 }
 
+save_state_fields!(HuC3 { ram, ram_area_mode, rom_bank, ram_bank, rtc_command, rtc_argument, rtc_output, rtc_address, rtc_memory });
+
 impl mbc::MemoryBankController for HuC3 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -167,6 +169,18 @@ impl mbc::MemoryBankController for HuC3 {
             _ => Err(HydraIOError::OpenBusAccess)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.ram.load_from(data);
+    }
 }
 
 enum RamAreaMode {
@@ -177,4 +191,6 @@ enum RamAreaMode {
     RtcSemaphore,
     Infrared,
     Invalid,
-}
\ No newline at end of file
+}
+
+save_state_enum!(RamAreaMode { SramReadOnly, SramReadWrite, RtcCommandWrite, RtcCommandRead, RtcSemaphore, Infrared, Invalid });
\ No newline at end of file
@@ -3,6 +3,7 @@ use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
+use crate::save_state_fields;
 
 pub struct MBC0 {
     rom: Rom<0x4000>,
@@ -33,6 +34,8 @@ impl MBC0 {
     }
 }
 
+save_state_fields!(MBC0 { ram });
+
 impl mbc::MemoryBankController for MBC0 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -49,4 +52,16 @@ impl mbc::MemoryBankController for MBC0 {
         let BankedAddress { address, bank } = self.localize_ram_address(address);
         Ok(self.ram.write_bank(value, address, bank))
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.ram.load_from(data);
+    }
 }
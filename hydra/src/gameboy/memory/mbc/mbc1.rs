@@ -3,6 +3,7 @@ use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
+use crate::save_state_fields;
 
 pub struct MBC1 {
     rom: Rom<0x4000>,
@@ -51,6 +52,8 @@ impl MBC1 {
     }
 }
 
+save_state_fields!(MBC1 { ram, ram_enabled, rom_bank, ram_bank, dynamic_banking });
+
 impl mbc::MemoryBankController for MBC1 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -81,4 +84,16 @@ impl mbc::MemoryBankController for MBC1 {
             Err(HydraIOError::OpenBusAccess)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.ram.load_from(data);
+    }
 }
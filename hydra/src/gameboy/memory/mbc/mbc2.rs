@@ -4,6 +4,7 @@ use crate::common::util::BankedAddress;
 use crate::gameboy::memory::mbc;
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
+use crate::save_state_fields;
 
 pub struct MBC2 {
     rom: Rom<0x4000>,
@@ -37,6 +38,8 @@ impl MBC2 {
     }
 }
 
+save_state_fields!(MBC2 { ram, ram_enabled, rom_bank });
+
 impl mbc::MemoryBankController for MBC2 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -62,4 +65,17 @@ impl mbc::MemoryBankController for MBC2 {
             Err(HydraIOError::OpenBusAccess)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
 }
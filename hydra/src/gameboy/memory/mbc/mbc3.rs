@@ -1,11 +1,13 @@
-use std::time::{Duration, Instant};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::common::errors::HydraIOError;
+use crate::common::savestate::{SaveState, StateReader, StateWriter};
 use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
-use crate::{deserialize, serialize};
+use crate::gameboy::timer::MasterTimer;
+use crate::{deserialize, save_state_fields, serialize};
 
 pub struct MBC3 {
     rom: Rom<0x4000>,
@@ -50,6 +52,8 @@ impl MBC3 {
     }
 }
 
+save_state_fields!(MBC3 { ram, ram_area_enabled, rom_bank, ram_bank, rtc, rtc_latch });
+
 impl mbc::MemoryBankController for MBC3 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -62,16 +66,7 @@ impl mbc::MemoryBankController for MBC3 {
                     let BankedAddress { address, bank } = self.localize_ram_address(address);
                     Ok(self.ram.read_bank(address, bank))
                 }
-                (0x08, Some(rtc)) => Ok(rtc.latched_seconds),
-                (0x09, Some(rtc)) => Ok(rtc.latched_minutes),
-                (0x0A, Some(rtc)) => Ok(rtc.latched_hours),
-                (0x0B, Some(rtc)) => Ok((rtc.latched_days & 0xFF) as u8),
-                (0x0C, Some(rtc)) => Ok(serialize!(
-                    (rtc.day_carry as u8) =>> [7];
-                    (rtc.halted as u8) =>> [6];
-                    0b00111110;
-                    (((rtc.latched_days & 0b100000000) >> 8) as u8) =>> [0];
-                )),
+                (0x08..=0x0C, Some(rtc)) => Ok(rtc.read_latched(self.ram_bank - 0x08)),
                 _ => Err(HydraIOError::OpenBusAccess)
             }
         } else {
@@ -105,62 +100,314 @@ impl mbc::MemoryBankController for MBC3 {
                     let BankedAddress { address, bank } = self.localize_ram_address(address);
                     Ok(self.ram.write_bank(value, address, bank))
                 }
-                (0x08, Some(rtc)) => Ok(rtc.latched_seconds = value),
-                (0x09, Some(rtc)) => Ok(rtc.latched_minutes = value),
-                (0x0A, Some(rtc)) => Ok(rtc.latched_hours = value),
-                (0x0B, Some(rtc)) => Ok(rtc.latched_days = (rtc.latched_days & 0x100) | value as u16),
-                (0x0C, Some(rtc)) => {
-                    deserialize!(value;
-                        [7] as bool =>> (rtc.day_carry);
-                        [6] as bool =>> (rtc.halted);
-                        [0] =>> days_hi;
-                    );
-                    rtc.latched_days = (rtc.latched_days & 0xFF) | ((days_hi as u16) << 8);
-                    Ok(())
-                },
+                (0x08..=0x0C, Some(rtc)) => Ok(rtc.write_register(self.ram_bank - 0x08, value)),
                 _ => Err(HydraIOError::OpenBusAccess)
             }
         } else {
             Err(HydraIOError::OpenBusAccess)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn frame(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.frame();
+        }
+    }
+
+    fn set_emulated_clock(&mut self, start: Option<SystemTime>) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.set_emulated_clock(start);
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        let mut data = self.ram.to_vec();
+        if let Some(rtc) = &self.rtc {
+            data.extend_from_slice(&rtc.to_footer());
+        }
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram_size = self.ram.get_bank_count() * self.ram.bank_size();
+        self.ram.load_from(data);
+        if let Some(rtc) = &mut self.rtc && let Some(footer) = data.get(ram_size..) {
+            rtc.load_footer(footer);
+        }
+    }
 }
 
+/// The MBC3's real-time clock.
+///
+/// The live registers are only brought up to date when accessed, by advancing them with the wall-clock time elapsed since `synced_at`.
+/// While `emulated` is set, the wall clock is replaced by one counting emulated frames, so that movies replay identically.
 pub struct RealTimeClock {
-    pub(self) day_carry: bool,
-    pub(self) halted: bool,
-
-    timestamp: Instant,
-    pub(self) latched_seconds: u8,
-    pub(self) latched_minutes: u8,
-    pub(self) latched_hours: u8,
-    pub(self) latched_days: u16,
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    day_carry: bool,
+    halted: bool,
+    synced_at: SystemTime,
+
+    latched: [u8; 5],
+
+    emulated: Option<EmulatedTime>,
+}
+
+/// A stand-in for the host's clock, which only moves forward as frames are emulated.
+struct EmulatedTime {
+    start: SystemTime,
+    dots: u64,
+}
+
+impl EmulatedTime {
+    const DOTS_PER_SECOND: u64 = 4194304;
+
+    fn now(&self) -> SystemTime {
+        let nanos = (self.dots % Self::DOTS_PER_SECOND) * 1_000_000_000 / Self::DOTS_PER_SECOND;
+        self.start + Duration::from_secs(self.dots / Self::DOTS_PER_SECOND) + Duration::from_nanos(nanos)
+    }
 }
 
 impl RealTimeClock {
+    /// Size of the footer appended to `.sav` files: live registers, latched registers, then a UNIX timestamp.
+    const FOOTER_SIZE: usize = 48;
+    /// Older footer variant, with a 32-bit timestamp.
+    const FOOTER_SIZE_SHORT: usize = 44;
+
     pub fn new() -> Self {
         RealTimeClock { 
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
             day_carry: false,
             halted: true,
+            synced_at: SystemTime::now(),
 
-            timestamp: Instant::now(),
-            latched_seconds: 0,
-            latched_minutes: 0,
-            latched_hours: 0,
-            latched_days: 0,
+            latched: [0; 5],
+
+            emulated: None,
         }
     }
 
+    /// Returns the current time, as told by the host or by emulated frames.
+    fn now(&self) -> SystemTime {
+        self.emulated.as_ref().map_or_else(SystemTime::now, EmulatedTime::now)
+    }
+
+    /// Moves emulated time forward by one frame. Has no effect while the clock follows the host.
+    pub fn frame(&mut self) {
+        if let Some(emulated) = &mut self.emulated {
+            emulated.dots += MasterTimer::PPU_DOTS_PER_FRAME as u64;
+        }
+    }
+
+    /// Drives the clock from emulated frames, counting up from `start`, or hands it back to the host's clock if `None`.
+    /// Either way, the registers carry on from where they are now.
+    pub fn set_emulated_clock(&mut self, start: Option<SystemTime>) {
+        self.sync();
+        self.emulated = start.map(|start| EmulatedTime { start, dots: 0 });
+        self.synced_at = self.now();
+    }
+
+    /// Advances the live registers by however many whole seconds have passed since they were last updated.
+    fn sync(&mut self) {
+        let now = self.now();
+        if self.halted {
+            self.synced_at = now;
+            return;
+        }
+
+        let elapsed = now.duration_since(self.synced_at).unwrap_or(Duration::ZERO).as_secs();
+        self.synced_at += Duration::from_secs(elapsed);
+
+        let seconds = self.seconds as u64 + elapsed;
+        self.seconds = (seconds % 60) as u8;
+        let minutes = self.minutes as u64 + seconds / 60;
+        self.minutes = (minutes % 60) as u8;
+        let hours = self.hours as u64 + minutes / 60;
+        self.hours = (hours % 24) as u8;
+        let days = self.days as u64 + hours / 24;
+        self.days = (days % 512) as u16;
+        self.day_carry |= days >= 512;
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            (self.days & 0xFF) as u8,
+            serialize!(
+                (self.day_carry as u8) =>> [7];
+                (self.halted as u8) =>> [6];
+                0b00111110;
+                ((self.days >> 8) as u8) =>> [0];
+            ),
+        ]
+    }
+
     pub fn latch(&mut self) {
-        let latched = if self.halted {
-            Duration::ZERO
-        } else {
-            self.timestamp.elapsed()
+        self.sync();
+        self.latched = self.registers();
+    }
+
+    /// Reads one of the latched registers, where 0 is seconds and 4 is the upper day counter/control register.
+    pub fn read_latched(&self, index: u8) -> u8 {
+        self.latched[index as usize]
+    }
+
+    /// Writes one of the clock's registers, where 0 is seconds and 4 is the upper day counter/control register.
+    pub fn write_register(&mut self, index: u8, value: u8) {
+        self.sync();
+        match index {
+            0 => {
+                self.seconds = value & 0b111111;
+                // Writing seconds also resets the sub-second counter
+                self.synced_at = self.now();
+            }
+            1 => self.minutes = value & 0b111111,
+            2 => self.hours = value & 0b11111,
+            3 => self.days = (self.days & 0x100) | value as u16,
+            4 => {
+                deserialize!(value;
+                    [7] as bool =>> (self.day_carry);
+                    [6] as bool =>> (self.halted);
+                    [0] =>> days_hi;
+                );
+                self.days = (self.days & 0xFF) | ((days_hi as u16) << 8);
+            }
+            _ => panic!("Invalid RTC register {}", index),
+        }
+        // Writes are visible immediately, without needing to latch again
+        self.latched[index as usize] = self.registers()[index as usize];
+    }
+
+    /// Encodes this clock in the footer format shared by most emulators.
+    pub fn to_footer(&self) -> [u8; Self::FOOTER_SIZE] {
+        let timestamp = self.synced_at.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+        let mut footer = [0; Self::FOOTER_SIZE];
+        for (index, register) in self.registers().into_iter().chain(self.latched).enumerate() {
+            footer[index * 4..index * 4 + 4].copy_from_slice(&(register as u32).to_le_bytes());
+        }
+        footer[40..48].copy_from_slice(&timestamp.to_le_bytes());
+        footer
+    }
+
+    /// Restores this clock from a `.sav` footer, then advances it by the time that has passed since it was saved.
+    pub fn load_footer(&mut self, footer: &[u8]) {
+        let timestamp = match footer.len() {
+            Self::FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            Self::FOOTER_SIZE_SHORT => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            _ => return, // No (or an unrecognized) footer, so leave the clock as-is
         };
+        let register = |index: usize| footer[index * 4];
+
+        self.seconds = register(0);
+        self.minutes = register(1);
+        self.hours = register(2);
+        self.days = register(3) as u16;
+        let control = register(4);
+        deserialize!(control;
+            [7] as bool =>> (self.day_carry);
+            [6] as bool =>> (self.halted);
+            [0] =>> days_hi;
+        );
+        self.days |= (days_hi as u16) << 8;
+        self.latched = [register(5), register(6), register(7), register(8), register(9)];
 
-        self.latched_seconds = latched.as_secs() as u8 % 60;
-        self.latched_minutes = (latched.as_secs() / 60) as u8 % 60;
-        self.latched_hours = (latched.as_secs() / 3600) as u8 % 24;
-        self.latched_days = (latched.as_secs() / 86400) as u16 % 512;
+        self.synced_at = UNIX_EPOCH + Duration::from_secs(timestamp);
+        self.sync();
     }
-}
\ No newline at end of file
+}
+
+impl Default for RealTimeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Saved as the registers stand, along with how long ago they were last advanced, so loading puts the clock back exactly as it was.
+/// Unlike the `.sav` footer, it doesn't catch up on time spent between saving and loading, which would make every rewind jump it forward.
+impl SaveState for RealTimeClock {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.seconds.save_state(state);
+        self.minutes.save_state(state);
+        self.hours.save_state(state);
+        self.days.save_state(state);
+        self.day_carry.save_state(state);
+        self.halted.save_state(state);
+        self.latched.save_state(state);
+        let pending = self.now().duration_since(self.synced_at).unwrap_or(Duration::ZERO);
+        (pending.as_nanos() as u64).save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.seconds.load_state(state)?;
+        self.minutes.load_state(state)?;
+        self.hours.load_state(state)?;
+        self.days.load_state(state)?;
+        self.day_carry.load_state(state)?;
+        self.halted.load_state(state)?;
+        self.latched.load_state(state)?;
+        let mut pending = 0u64;
+        pending.load_state(state)?;
+        let now = self.now();
+        self.synced_at = now.checked_sub(Duration::from_nanos(pending)).unwrap_or(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save(rtc: &RealTimeClock) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        rtc.save_state(&mut state);
+        state.into_inner()
+    }
+
+    fn run_frames(rtc: &mut RealTimeClock, frames: u32) {
+        (0..frames).for_each(|_| rtc.frame());
+    }
+
+    #[test]
+    fn save_state_restores_clock_exactly() {
+        let mut rtc = RealTimeClock::new();
+        rtc.set_emulated_clock(Some(UNIX_EPOCH));
+        rtc.write_register(4, 0); // Start the clock
+        run_frames(&mut rtc, 90); // About 1.5 seconds
+        let saved = save(&rtc);
+
+        run_frames(&mut rtc, 600);
+        rtc.load_state(&mut StateReader::new(&saved)).unwrap();
+        assert_eq!(save(&rtc), saved, "loading shouldn't catch up on the time spent since saving");
+        rtc.latch();
+        assert_eq!(rtc.read_latched(0), 1);
+
+        // Half a second was left over when the state was made, so another half second finishes the next second
+        run_frames(&mut rtc, 30);
+        rtc.latch();
+        assert_eq!(rtc.read_latched(0), 2);
+    }
+
+    #[test]
+    fn footer_catches_up() {
+        let mut rtc = RealTimeClock::new();
+        rtc.set_emulated_clock(Some(UNIX_EPOCH));
+        rtc.write_register(4, 0);
+        let footer = rtc.to_footer();
+
+        run_frames(&mut rtc, 600); // About 10 seconds
+        rtc.load_footer(&footer);
+        rtc.latch();
+        assert_eq!(rtc.read_latched(0), 10);
+    }
+}
@@ -1,17 +1,18 @@
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
 use crate::common::bit::BitVec;
 use crate::common::errors::HydraIOError;
 use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
+use crate::gameboy::joypad::ControllerPort;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
-use crate::input::{ControllerMessage, ControllerState};
+use crate::save_state_fields;
 
 pub struct MBC5 {
     rom: Rom<0x4000>,
     ram: Sram<0x2000>,
-    controllers: Arc<RwLock<ControllerState>>,
+    controllers: Arc<dyn ControllerPort>,
 
     ram_enabled: bool,
     rom_bank: u16,
@@ -22,7 +23,7 @@ pub struct MBC5 {
 }
 
 impl MBC5 {
-    pub fn from_header(header: RomHeader, controllers: Arc<RwLock<ControllerState>>) -> Result<Self, HydraIOError> {
+    pub fn from_header(header: RomHeader, controllers: Arc<dyn ControllerPort>) -> Result<Self, HydraIOError> {
         Ok(MBC5 {
             ram: Sram::from_header(&header)?,
             rumble: header.get_rumble(),
@@ -53,6 +54,8 @@ impl MBC5 {
     }
 }
 
+save_state_fields!(MBC5 { ram, ram_enabled, rom_bank, ram_bank, rumble, rumble_aggregate });
+
 impl mbc::MemoryBankController for MBC5 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -92,12 +95,24 @@ impl mbc::MemoryBankController for MBC5 {
         }
     }
 
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
     fn tick(&mut self) {
         self.rumble.inspect(|rumbling| self.rumble_aggregate = self.rumble_aggregate.wrapping_add(*rumbling as u16));
     }
 
     fn frame(&mut self) {
-        self.controllers.read().unwrap().channel().send(ControllerMessage::Rumble(self.rumble_aggregate));
+        self.controllers.rumble(self.rumble_aggregate);
         self.rumble_aggregate = 0;
     }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.ram.load_from(data);
+    }
 }
@@ -3,6 +3,7 @@ use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
+use crate::{save_state_enum, save_state_fields};
 
 pub struct MBC6 {
     rom: Rom<0x2000>,
@@ -52,6 +53,8 @@ impl MBC6 {
     }
 }
 
+save_state_fields!(MBC6 { ram, rom_banks, rom_bank_select, flash_read_enabled, flash_write_enabled, ram_banks, ram_enabled });
+
 impl mbc::MemoryBankController for MBC6 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -100,10 +103,24 @@ impl mbc::MemoryBankController for MBC6 {
             Err(HydraIOError::OpenBusAccess)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.ram.load_from(data);
+    }
 }
 
 #[derive(Copy, Clone)]
 enum RomBankMapping {
     Rom = 0,
     Flash = 1
-}
\ No newline at end of file
+}
+
+save_state_enum!(RomBankMapping { Rom, Flash });
\ No newline at end of file
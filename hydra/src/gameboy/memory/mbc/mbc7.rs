@@ -1,19 +1,18 @@
-use std::sync::{Arc, RwLock};
-
-use sdl3::sensor::SensorType;
+use std::sync::Arc;
 
 use crate::common::bit::BitVec;
 use crate::common::errors::HydraIOError;
+use crate::common::savestate::{SaveState, StateReader, StateWriter};
 use crate::common::util::BankedAddress;
-use crate::input::ControllerState;
-use crate::{deserialize, serialize};
+use crate::{deserialize, save_state_fields, serialize};
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
+use crate::gameboy::joypad::ControllerPort;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
 
 pub struct MBC7 {
     rom: Rom<0x4000>,
-    controllers: Arc<RwLock<ControllerState>>,
+    controllers: Arc<dyn ControllerPort>,
 
     ram_enables: [u8; 2],
     rom_bank: u8,
@@ -28,7 +27,7 @@ pub struct MBC7 {
 impl MBC7 {
     const ACCELEROMETER_RESET: u16 = 0x8000;
     const ACCELEROMETER_CENTER: u16 = 0x81D0;
-    pub fn from_header(header: RomHeader, controllers: Arc<RwLock<ControllerState>>) -> Result<Self, HydraIOError> {
+    pub fn from_header(header: RomHeader, controllers: Arc<dyn ControllerPort>) -> Result<Self, HydraIOError> {
         Ok(MBC7 {
             rom: header.into_rom(),
             controllers,
@@ -57,6 +56,8 @@ impl MBC7 {
     }
 }
 
+save_state_fields!(MBC7 { ram_enables, rom_bank, latch_ready, accel_x, accel_y, eeprom });
+
 impl mbc::MemoryBankController for MBC7 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -97,8 +98,7 @@ impl mbc::MemoryBankController for MBC7 {
                     self.latch_ready = true;
                 }
                 0xA010 => if value == 0xAA && self.latch_ready {
-                    let controller = self.controllers.read().unwrap();
-                    let [accel_x_f32, accel_y_f32, accel_z_f32] = controller.poll_sensor(SensorType::Accelerometer);
+                    let [accel_x_f32, accel_y_f32, accel_z_f32] = self.controllers.poll_accelerometer();
                     self.accel_x = Self::ACCELEROMETER_CENTER.saturating_add_signed((accel_x_f32 * 11.0) as i16);
                     self.accel_y = Self::ACCELEROMETER_CENTER.saturating_add_signed((accel_z_f32 * 11.0) as i16);
                     println!("{:#06X}, {:#06X}", self.accel_x, self.accel_y);
@@ -112,6 +112,20 @@ impl mbc::MemoryBankController for MBC7 {
             Err(HydraIOError::OpenBusAccess)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.eeprom.memory.iter().flat_map(|word| word.to_le_bytes()).collect())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        for (word, bytes) in self.eeprom.memory.iter_mut().zip(data.chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+    }
 }
 
 struct Eeprom93LC56 {
@@ -130,6 +144,19 @@ struct Eeprom93LC56 {
     memory: [u16; 0x80]
 }
 
+save_state_fields!(Eeprom93LC56 {
+    chip_select,
+    clock,
+    data_in,
+    data_out,
+    state,
+    op_buffer,
+    address_buffer,
+    data_buffer,
+    write_enabled,
+    memory,
+});
+
 impl Eeprom93LC56 {
     pub fn new() -> Self {
         Eeprom93LC56 { 
@@ -248,4 +275,31 @@ enum EepromState {
     Address(u8),
     DataIn(u8),
     DataOut(u8),
+}
+
+impl SaveState for EepromState {
+    fn save_state(&self, state: &mut StateWriter) {
+        let (index, bits) = match *self {
+            EepromState::Standby => (0u8, 0u8),
+            EepromState::Opcode(bits) => (1, bits),
+            EepromState::Address(bits) => (2, bits),
+            EepromState::DataIn(bits) => (3, bits),
+            EepromState::DataOut(bits) => (4, bits),
+        };
+        index.save_state(state);
+        bits.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let [index, bits] = state.read_array()?;
+        *self = match index {
+            0 => EepromState::Standby,
+            1 => EepromState::Opcode(bits),
+            2 => EepromState::Address(bits),
+            3 => EepromState::DataIn(bits),
+            4 => EepromState::DataOut(bits),
+            _ => return Err(HydraIOError::MalformedSaveState("Invalid EEPROM state")),
+        };
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -7,7 +7,7 @@ use image::imageops::FilterType;
 use nokhwa::Camera;
 use nokhwa::pixel_format::{LumaFormat, RgbAFormat, RgbFormat};
 use nokhwa::utils::Resolution;
-use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, PipelineCompilationOptions, PipelineLayoutDescriptor, PollType, Queue, ShaderModuleDescriptor, ShaderSource, ShaderStages};
+use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, DeviceDescriptor, Instance, InstanceDescriptor, PipelineCompilationOptions, PipelineLayoutDescriptor, PollType, Queue, RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource, ShaderStages};
 
 use crate::common::bit::BitVec;
 use crate::common::errors::HydraIOError;
@@ -15,7 +15,7 @@ use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
-use crate::{deserialize, input, serialize};
+use crate::{deserialize, input, save_state_fields, serialize};
 
 const SENSOR_WIDTH_TILES: usize = 16;
 const SENSOR_WIDTH: usize = SENSOR_WIDTH_TILES * TILE_SIZE;
@@ -65,7 +65,11 @@ pub struct PocketCamera {
 }
 
 impl PocketCamera {
-    pub fn from_header(header: RomHeader, device: Arc<Device>, queue: Arc<Queue>) -> Result<Self, HydraIOError> {
+    pub fn from_header(header: RomHeader) -> Result<Self, HydraIOError> {
+        // The dithering shader runs on a dedicated compute device, so the cartridge doesn't depend on a window existing
+        let (device, queue) = futures::executor::block_on(Self::request_device())?;
+        let (device, queue) = (Arc::new(device), Arc::new(queue));
+
         let dithering_thresholds = device.create_buffer(&BufferDescriptor {
             label: Some("POCKETCAMERA Dithering Thresholds Matrix"),
             mapped_at_creation: false,
@@ -199,6 +203,12 @@ impl PocketCamera {
         })
     }
 
+    async fn request_device() -> Result<(Device, Queue), HydraIOError> {
+        let instance = Instance::new(&InstanceDescriptor::from_env_or_default());
+        let adapter = instance.request_adapter(&RequestAdapterOptions::default()).await.map_err(|_| HydraIOError::NoGraphicsAdapter)?;
+        adapter.request_device(&DeviceDescriptor::default()).await.map_err(|_| HydraIOError::NoGraphicsAdapter)
+    }
+
     fn localize_rom_address(&self, address: u16) -> BankedAddress<u16, usize> {
         match address {
             0x0000..=0x3FFF => BankedAddress {address: address, bank: 0},
@@ -215,6 +225,24 @@ impl PocketCamera {
     }
 }
 
+// The last captured image and dithering matrix live on the GPU, so they aren't included
+save_state_fields!(PocketCamera {
+    ram,
+    ram_write_enabled,
+    rom_bank,
+    ram_bank,
+    cam_selected,
+    gain,
+    add_six,
+    exposure_time,
+    h_enhance,
+    v_enhance,
+    enhance_ratio,
+    invert,
+    voltage,
+    rtc_latch,
+});
+
 impl mbc::MemoryBankController for PocketCamera {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -335,4 +363,16 @@ impl mbc::MemoryBankController for PocketCamera {
             Err(HydraIOError::OpenBusAccess)
         }
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.ram.load_from(data);
+    }
 }
\ No newline at end of file
@@ -1,17 +1,18 @@
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
 use crate::common::bit::BitVec;
 use crate::common::errors::HydraIOError;
 use crate::common::util::BankedAddress;
 use crate::gameboy::memory::{mbc, sram};
 use crate::gameboy::memory::sram::Sram;
+use crate::gameboy::joypad::ControllerPort;
 use crate::gameboy::memory::rom::{Rom, RomHeader};
-use crate::input::{ControllerMessage, ControllerState};
+use crate::save_state_fields;
 
 pub struct TAMA5 {
     rom: Rom<0x4000>,
     ram: [u8; 32],
-    controllers: Arc<RwLock<ControllerState>>,
+    controllers: Arc<dyn ControllerPort>,
 
     rom_bank: u8,
     ram_bank: u8,
@@ -22,7 +23,7 @@ pub struct TAMA5 {
 }
 
 impl TAMA5 {
-    pub fn from_header(header: RomHeader, controllers: Arc<RwLock<ControllerState>>) -> Result<Self, HydraIOError> {
+    pub fn from_header(header: RomHeader, controllers: Arc<dyn ControllerPort>) -> Result<Self, HydraIOError> {
         Ok(TAMA5 {
             rom: header.into_rom(),
             ram: [0x00; 32],
@@ -46,6 +47,8 @@ impl TAMA5 {
     }
 }
 
+save_state_fields!(TAMA5 { ram, rom_bank, ram_bank, ram_area_mode, ram_area_addr, ram_area_data });
+
 impl mbc::MemoryBankController for TAMA5 {
     fn read_rom_u8(&self, address: u16) -> Result<u8, HydraIOError> {
         let BankedAddress { address, bank } = self.localize_rom_address(address);
@@ -96,4 +99,17 @@ impl mbc::MemoryBankController for TAMA5 {
             _ => unimplemented!("Attempted to write {:#04X} to invalid SRAM address {:#06X}", value, address)
         })
     }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        self.localize_rom_address(address).bank
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.ram.to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
 }
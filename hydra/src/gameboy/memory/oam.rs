@@ -1,17 +1,17 @@
-use std::rc::Rc;
+use std::{cell::Cell, rc::Rc};
 
-use crate::{common::errors::HydraIOError, gameboy::{GbMode, Model, ppu::{attributes::TileAttributes, state::ObjectHeight}}};
+use crate::{common::errors::HydraIOError, gameboy::{GbMode, Model, ppu::{attributes::TileAttributes, state::ObjectHeight}}, save_state_fields};
 
 pub struct Oam {
     inner: [u8; 0x100],
-    mode: Rc<GbMode>,
+    mode: Rc<Cell<GbMode>>,
     dma_value: Option<u8>
 }
 
 pub const ADDRESS_OFFSET: u16 = 0xFE00;
 
 impl Oam {
-    pub fn new(mode: Rc<GbMode>) -> Self {
+    pub fn new(mode: Rc<Cell<GbMode>>) -> Self {
         Oam { 
             inner: [0; 0x100],
             mode,
@@ -27,8 +27,13 @@ impl Oam {
         ObjectOamMetadata { address, y: self.inner[Self::localize_address(address)], x: self.inner[Self::localize_address(address + 1)] }
     }
 
+    /// Returns the object's position in OAM, from 0 to 39.
+    pub fn get_index(&self, oam_meta: &ObjectOamMetadata) -> u8 {
+        (Self::localize_address(oam_meta.address) / 4) as u8
+    }
+
     pub fn resolve_oam_meta(&self, oam_meta: &ObjectOamMetadata) -> ObjectRenderMetadata {
-        ObjectRenderMetadata { data_index: self.inner[Self::localize_address(oam_meta.address + 2)], attributes: TileAttributes::from_u8(self.inner[Self::localize_address(oam_meta.address + 3)], &self.mode) }
+        ObjectRenderMetadata { data_index: self.inner[Self::localize_address(oam_meta.address + 2)], attributes: TileAttributes::from_u8(self.inner[Self::localize_address(oam_meta.address + 3)], self.mode.get()) }
     }
 
     pub fn write(&mut self, address: u16, value: u8) -> Result<(), HydraIOError> {
@@ -75,12 +80,17 @@ impl Oam {
     }
 }
 
+save_state_fields!(Oam { inner, dma_value });
+
+#[derive(Default)]
 pub struct ObjectOamMetadata {
     pub address: u16,
     pub y: u8,
     pub x: u8,
 }
 
+save_state_fields!(ObjectOamMetadata { address, y, x });
+
 impl ObjectOamMetadata {
     pub fn occupies_x(&self, x: u8) -> bool {
         ((self.x.saturating_sub(8))..(self.x)).contains(&x)
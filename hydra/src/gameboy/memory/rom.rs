@@ -1,30 +1,42 @@
-use std::{ops::RangeInclusive, sync::{Arc, RwLock}};
+use std::{ops::RangeInclusive, sync::Arc};
 
-use wgpu::{Device, Queue};
-
-use crate::{common::{bit::BitVec, errors::HydraIOError}, gameboy::memory::{mbc::{MemoryBankController, huc1::HuC1, huc3::HuC3, mbc0::MBC0, mbc1::MBC1, mbc2::MBC2, mbc3::{MBC3, RealTimeClock}, mbc5::MBC5, mbc6::MBC6, mbc7::MBC7, pocketcamera::PocketCamera, tama5::TAMA5}, sram::Sram}, input::ControllerState};
+#[cfg(feature = "frontend")]
+use crate::gameboy::memory::mbc::pocketcamera::PocketCamera;
+use crate::{common::{bit::BitVec, errors::HydraIOError}, gameboy::{joypad::ControllerPort, memory::{header::CartridgeHeader, mbc::{MemoryBankController, huc1::HuC1, huc3::HuC3, mbc0::MBC0, mbc1::MBC1, mbc2::MBC2, mbc3::{MBC3, RealTimeClock}, mbc5::MBC5, mbc6::MBC6, mbc7::MBC7, tama5::TAMA5}, sram::Sram}}};
 
 // Header Registers
 pub const TITLE_ADDRESS: RangeInclusive<usize> = 0x0134..=0x0143;
 pub const CGB_FLAG_ADDRESS: usize = 0x0143;
 pub const NEW_LICENSEE_CODE_ADDRESS: usize = 0x0144;
+pub const SGB_FLAG_ADDRESS: usize = 0x0146;
 pub const HARDWARE_ADDRESS: usize = 0x0147;
 pub const ROM_SIZE_ADDRESS: usize = 0x0148;
 pub const RAM_SIZE_ADDRESS: usize = 0x0149;
 pub const OLD_LICENSEE_CODE_ADDRESS: usize = 0x014B;
 pub const HEADER_CHECKSUM_ADDRESS: usize = 0x014D;
 
+#[derive(Clone)]
 pub struct RomHeader(Vec<u8>);
 
 impl RomHeader {
     /// Resizes and wraps a byte vector for use as cartridge ROM.
-    pub fn from_vec(mut romvec: Vec<u8>) -> Result<Self, HydraIOError> {
-        romvec.resize(RomHeader::rom_size_from_vec(&romvec)?, 0x00);
+    /// If the header is too broken to tell how large the ROM is, everything wrong with it is reported instead.
+    pub fn from_vec(romvec: Vec<u8>) -> Result<Self, HydraIOError> {
+        let header = CartridgeHeader::parse(&romvec)?;
+        Self::from_parsed(romvec, &header)
+    }
+
+    /// Like `from_vec`, for a ROM whose header has already been parsed.
+    pub fn from_parsed(mut romvec: Vec<u8>, header: &CartridgeHeader) -> Result<Self, HydraIOError> {
+        let Some(rom_size) = header.get_rom_size() else {
+            return Err(HydraIOError::InvalidHeader(header.get_problems()));
+        };
+        romvec.resize(rom_size, 0x00);
         Ok(RomHeader(romvec))
     }
 
     /// Consumes this ROM, wrapping it in a new memory bank controller
-    pub fn into_mbc(self, controllers: Arc<RwLock<ControllerState>>, device: Arc<Device>, queue: Arc<Queue>) -> Result<Box<dyn MemoryBankController>, HydraIOError> {
+    pub fn into_mbc(self, controllers: Arc<dyn ControllerPort>) -> Result<Box<dyn MemoryBankController>, HydraIOError> {
         match self.0[HARDWARE_ADDRESS] {
             0x00 | 0x08..=0x09 => Ok(Box::new(MBC0::from_header(self)?)),
             0x01..=0x03 => Ok(Box::new(MBC1::from_header(self)?)),
@@ -34,11 +46,14 @@ impl RomHeader {
             0x19..=0x1E => Ok(Box::new(MBC5::from_header(self, controllers)?)),
             0x20 => Ok(Box::new(MBC6::from_header(self)?)),
             0x22 => Ok(Box::new(MBC7::from_header(self, controllers)?)),
-            0xFC => Ok(Box::new(PocketCamera::from_header(self, device, queue)?)),
+            #[cfg(feature = "frontend")]
+            0xFC => Ok(Box::new(PocketCamera::from_header(self)?)),
+            #[cfg(not(feature = "frontend"))]
+            0xFC => Err(HydraIOError::NoCamera),
             0xFD => Ok(Box::new(TAMA5::from_header(self, controllers)?)),
             0xFE => Ok(Box::new(HuC3::from_header(self)?)),
             0xFF => Ok(Box::new(HuC1::from_header(self)?)),
-            _ => Err(HydraIOError::MalformedROM("Undefined cartridge hardware identifier").into()),
+            _ => Err(HydraIOError::InvalidHeader(CartridgeHeader::parse(&self.0)?.get_problems())),
         }
     }
 
@@ -58,22 +73,22 @@ impl RomHeader {
         }
     }
 
-    /// Reads the ROM size (in bytes) from a byte vector, treating it as a ROM cartridge header.
-    fn rom_size_from_vec(romvec: &Vec<u8>) -> Result<usize, HydraIOError> {
-        match romvec.get(ROM_SIZE_ADDRESS) {
-            Some(0x00) => Ok(0x8000), // 32 KiB
-            Some(0x01) => Ok(0x10000), // 64 KiB
-            Some(0x02) => Ok(0x20000), // 128 KiB
-            Some(0x03) => Ok(0x40000), // 256 KiB
-            Some(0x04) => Ok(0x80000), // 512 KiB
-            Some(0x05) => Ok(0x100000), // 1 MiB
-            Some(0x06) => Ok(0x200000), // 2 MiB
-            Some(0x07) => Ok(0x400000), // 4 MiB
-            Some(0x08) => Ok(0x800000), // 8 MiB
-            Some(0x52) => Ok(0x120000), // 1.1 MiB
-            Some(0x53) => Ok(0x140000), // 1.2 MiB
-            Some(0x54) => Ok(0x180000), // 1.5 MiB
-            _ => Err(HydraIOError::MalformedROM("Undefined ROM size identifier").into()),
+    /// Converts a ROM size identifier from a cartridge header into a size in bytes.
+    pub fn rom_size_from_code(code: u8) -> Option<usize> {
+        match code {
+            0x00 => Some(0x8000), // 32 KiB
+            0x01 => Some(0x10000), // 64 KiB
+            0x02 => Some(0x20000), // 128 KiB
+            0x03 => Some(0x40000), // 256 KiB
+            0x04 => Some(0x80000), // 512 KiB
+            0x05 => Some(0x100000), // 1 MiB
+            0x06 => Some(0x200000), // 2 MiB
+            0x07 => Some(0x400000), // 4 MiB
+            0x08 => Some(0x800000), // 8 MiB
+            0x52 => Some(0x120000), // 1.1 MiB
+            0x53 => Some(0x140000), // 1.2 MiB
+            0x54 => Some(0x180000), // 1.5 MiB
+            _ => None,
         }
     }
 
@@ -90,6 +105,12 @@ impl RomHeader {
         }
     }
 
+    /// Returns the contents of one of this ROM's banks, as they'd be switched in by its memory bank controller.
+    pub fn get_bank(&self, bank: usize) -> Option<&[u8]> {
+        let bank_size = Self::bank_size_from_vec(&self.0).ok()?;
+        self.0.chunks(bank_size).nth(bank)
+    }
+
     /// Reads the cartridge's title from this ROM's header.
     pub fn get_title(&self) -> &[u8] {
         &self.0[TITLE_ADDRESS]
@@ -100,6 +121,16 @@ impl RomHeader {
         self.0[CGB_FLAG_ADDRESS].test_bit(7)
     }
 
+    /// Returns whether this ROM supports SGB features. The SGB ignores every packet sent by cartridges that don't.
+    pub fn supports_sgb(&self) -> bool {
+        self.0[SGB_FLAG_ADDRESS] == 0x03 && self.0[OLD_LICENSEE_CODE_ADDRESS] == 0x33
+    }
+
+    /// Returns true if the cartridge's RAM is kept alive by a battery, and so should persist between sessions.
+    pub fn has_battery(&self) -> bool {
+        matches!(self.0[HARDWARE_ADDRESS], 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC..=0xFF)
+    }
+
     /// Constructs a new `RealTimeClock` if the provided ROM indicates a need for one.
     pub fn get_rtc(&self) -> Option<RealTimeClock> {
         match self.0[HARDWARE_ADDRESS] {
@@ -116,6 +147,16 @@ impl RomHeader {
         }
     }
 
+    /// Computes the CRC-32 of the entire ROM, for telling apart dumps that share a header (e.g. ROM hacks).
+    pub fn get_crc32(&self) -> u32 {
+        !self.0.iter().fold(!0u32, |crc, &byte| {
+            (0..8).fold(crc ^ byte as u32, |crc, _| match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB88320,
+                _ => crc >> 1,
+            })
+        })
+    }
+
     /// Reads the cartridge's header checksum.
     pub fn get_header_checksum(&self) -> u8 {
         self.0[HEADER_CHECKSUM_ADDRESS]
@@ -124,7 +165,10 @@ impl RomHeader {
     /// Returns true if the cartridge has a licensee ID of 0x01;
     /// i.e., was published by R&D1.
     pub fn has_publisher_rnd1(&self) -> bool {
-        self.0[OLD_LICENSEE_CODE_ADDRESS] == 0x01 || self.0[OLD_LICENSEE_CODE_ADDRESS] == 0x33 && self.0[NEW_LICENSEE_CODE_ADDRESS] == 0x01
+        match self.0[OLD_LICENSEE_CODE_ADDRESS] {
+            0x33 => &self.0[NEW_LICENSEE_CODE_ADDRESS..=NEW_LICENSEE_CODE_ADDRESS + 1] == b"01",
+            code => code == 0x01,
+        }
     }
 }
 
@@ -1,4 +1,4 @@
-use crate::{common::errors::HydraIOError, gameboy::memory::rom::{Rom, RomHeader}};
+use crate::{common::{errors::HydraIOError, savestate::{SaveState, StateReader, StateWriter}}, gameboy::memory::rom::{Rom, RomHeader}};
 
 pub const ADDRESS_OFFSET: usize = 0xA000;
 
@@ -29,4 +29,26 @@ impl<const BYTES_PER_BANK: usize> Sram<BYTES_PER_BANK> {
     pub const fn bank_size(&self) -> usize {
         BYTES_PER_BANK
     }
+
+    /// Returns this SRAM's contents as one contiguous byte vector, with banks in order.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.as_flattened().to_vec()
+    }
+
+    /// Overwrites this SRAM's contents from a contiguous byte slice, ignoring any bytes beyond its size.
+    pub fn load_from(&mut self, data: &[u8]) {
+        let flattened = self.0.as_flattened_mut();
+        let len = data.len().min(flattened.len());
+        flattened[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+impl<const BYTES_PER_BANK: usize> SaveState for Sram<BYTES_PER_BANK> {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.0.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        self.0.load_state(state)
+    }
 }
\ No newline at end of file
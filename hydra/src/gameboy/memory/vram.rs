@@ -1,24 +1,24 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use crate::common::errors::HydraIOError;
 use crate::gameboy::{GbMode, Model};
 use crate::gameboy::ppu::PpuMode;
 use crate::gameboy::ppu::attributes::TileAttributes;
-use crate::{deserialize, serialize};
+use crate::{deserialize, save_state_fields, serialize};
 
 pub const ADDRESS_OFFSET: u16 = 0x8000;
 
 pub struct Vram {
     model: Rc<Model>,
-    mode: Rc<GbMode>,
+    mode: Rc<Cell<GbMode>>,
     vram: Box<[[u8; 0x2000]]>,
     vbk: u8,
 }
 
 impl Vram {
-    pub fn new(model: Rc<Model>, mode: Rc<GbMode>) -> Self {
-        let bank_count = match *mode {
+    pub fn new(model: Rc<Model>, mode: Rc<Cell<GbMode>>) -> Self {
+        let bank_count = match mode.get() {
             GbMode::DMG => 1,
             GbMode::CGB => 2
         };
@@ -43,16 +43,20 @@ impl Vram {
         self.vram[bank as usize][Vram::localize_address(address)]
     }
 
+    pub fn bank_count(&self) -> u8 {
+        self.vram.len() as u8
+    }
+
     pub fn read_tile_map(&self, address: u16) -> (u8, TileAttributes) {
         let address = Vram::localize_address(address);
-        (self.vram[0][address], match *self.mode {
+        (self.vram[0][address], match self.mode.get() {
             GbMode::DMG => TileAttributes::default(),
-            GbMode::CGB => TileAttributes::from_u8(self.vram[1][address], &self.mode)
+            GbMode::CGB => TileAttributes::from_u8(self.vram[1][address], self.mode.get())
         }) 
     }
 
-    fn get_bank_id(&self) -> u8 {
-        if matches!(*self.mode, GbMode::DMG) {0} else {self.vbk}
+    pub(super) fn get_bank_id(&self) -> u8 {
+        if matches!(self.mode.get(), GbMode::DMG) {0} else {self.vbk}
     }
 
     const fn localize_address(address: u16) -> usize {
@@ -72,10 +76,12 @@ impl Vram {
 
     pub fn write_vbk(&mut self, val: u8) -> Result<(), HydraIOError> {
         // Disallow writes in DMG mode
-        if matches!(*self.mode, GbMode::DMG) {return Err(HydraIOError::OpenBusAccess)}
+        if matches!(self.mode.get(), GbMode::DMG) {return Err(HydraIOError::OpenBusAccess)}
         deserialize!(val;
             [0] =>> (self.vbk);
         );
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+save_state_fields!(Vram { vram, vbk });
\ No newline at end of file
@@ -1,18 +1,18 @@
-use std::rc::Rc;
+use std::{cell::Cell, rc::Rc};
 
-use crate::{deserialize, gameboy::{GbMode, Model}, serialize};
+use crate::{deserialize, gameboy::{GbMode, Model}, save_state_fields, serialize};
 
 pub const ADDRESS_OFFSET: u16 = 0xC000;
 
 pub struct Wram {
-    mode: Rc<GbMode>,
+    mode: Rc<Cell<GbMode>>,
     wram: Box<[[u8; 0x1000]]>,
     wbk: u8,
 }
 
 impl Wram {
-    pub fn new(mode: Rc<GbMode>) -> Self {
-        let bank_count = match *mode {
+    pub fn new(mode: Rc<Cell<GbMode>>) -> Self {
+        let bank_count = match mode.get() {
             GbMode::DMG => 2,
             GbMode::CGB => 8
         };
@@ -36,10 +36,15 @@ impl Wram {
         self.wram[self.get_bank_id(local_address) as usize][local_address % 0x1000] = value
     }
 
+    /// Returns the bank mapped to the given address in `0xC000..=0xDFFF`.
+    pub(super) fn get_bank(&self, address: u16) -> u8 {
+        self.get_bank_id(Wram::localize_address(address))
+    }
+
     fn get_bank_id(&self, address: usize) -> u8 {
         match address {
             0..0x1000 => 0,
-            _ => if matches!(*self.mode, GbMode::DMG) {1} else {self.wbk.max(1)}
+            _ => if matches!(self.mode.get(), GbMode::DMG) {1} else {self.wbk.max(1)}
         }
     }
 
@@ -61,4 +66,6 @@ impl Wram {
             [2..=0] =>> (self.wbk);
         );
     }
-}
\ No newline at end of file
+}
+
+save_state_fields!(Wram { wram, wbk });
\ No newline at end of file
@@ -0,0 +1,141 @@
+use crate::{common::{errors::HydraIOError, savestate::{SaveState, StateReader, StateWriter}}, gameboy::{interrupt::InterruptFlags, joypad::{InputVectors, Joypad}}};
+
+/// The point a movie starts playing from.
+pub enum MovieStart {
+    /// A freshly powered-on console, whose indeterminate power-on values are drawn from `seed`.
+    /// Cartridge RAM is stored alongside it, since games read it while booting.
+    PowerOn { seed: u64, cartridge_ram: Vec<u8> },
+    /// A save state, as made by `GameBoy::save_state`.
+    SaveState(Vec<u8>),
+}
+
+/// A recording of the joypad, one entry per frame, along with everything needed to replay it.
+pub struct Movie {
+    pub(super) model: String,
+    pub(super) rom_crc: u32,
+    pub(super) start: MovieStart,
+    /// When the movie began, in seconds since the UNIX epoch. Real-time clocks count up from here during playback, rather than following the host.
+    pub(super) start_time: u64,
+    frames: Vec<InputVectors>,
+}
+
+impl Movie {
+    const MAGIC: &[u8; 8] = b"HYDRA-GM";
+    const VERSION: u32 = 2;
+
+    pub(super) fn new(model: &str, rom_crc: u32, start: MovieStart, start_time: u64) -> Self {
+        Movie { model: model.to_string(), rom_crc, start, start_time, frames: Vec::new() }
+    }
+
+    /// Returns the number of frames of input held by this movie.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bytes(Self::MAGIC);
+        Self::VERSION.save_state(&mut state);
+        self.model.as_bytes().save_state(&mut state);
+        self.rom_crc.save_state(&mut state);
+        self.start_time.save_state(&mut state);
+        match &self.start {
+            MovieStart::PowerOn { seed, cartridge_ram } => {
+                0u8.save_state(&mut state);
+                seed.save_state(&mut state);
+                cartridge_ram.save_state(&mut state);
+            }
+            MovieStart::SaveState(data) => {
+                1u8.save_state(&mut state);
+                data.save_state(&mut state);
+            }
+        }
+        self.frames.save_state(&mut state);
+        state.into_inner()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, HydraIOError> {
+        Self::read(data).map_err(|e| match e {
+            // Movies share the save state format, but errors should still point at the right file
+            HydraIOError::MalformedSaveState(details) => HydraIOError::MalformedMovie(details),
+            e => e,
+        })
+    }
+
+    fn read(data: &[u8]) -> Result<Self, HydraIOError> {
+        let mut state = StateReader::new(data);
+        if state.read_bytes(Self::MAGIC.len()).ok() != Some(Self::MAGIC) {
+            return Err(HydraIOError::MalformedMovie("Not a Game Boy movie"));
+        }
+        let mut version = 0u32;
+        version.load_state(&mut state)?;
+        if version != Self::VERSION {
+            return Err(HydraIOError::IncompatibleMovie("Made by a different version of Hydra"));
+        }
+
+        let mut model = Vec::<u8>::new();
+        model.load_state(&mut state)?;
+        let model = String::from_utf8(model).map_err(|_| HydraIOError::MalformedMovie("Invalid model name"))?;
+        let mut rom_crc = 0u32;
+        rom_crc.load_state(&mut state)?;
+        let mut start_time = 0u64;
+        start_time.load_state(&mut state)?;
+
+        let mut start_kind = 0u8;
+        start_kind.load_state(&mut state)?;
+        let start = match start_kind {
+            0 => {
+                let (mut seed, mut cartridge_ram) = (0u64, Vec::new());
+                seed.load_state(&mut state)?;
+                cartridge_ram.load_state(&mut state)?;
+                MovieStart::PowerOn { seed, cartridge_ram }
+            }
+            1 => {
+                let mut data = Vec::new();
+                data.load_state(&mut state)?;
+                MovieStart::SaveState(data)
+            }
+            _ => return Err(HydraIOError::MalformedMovie("Invalid starting point")),
+        };
+
+        let mut frames = Vec::new();
+        frames.load_state(&mut state)?;
+        match state.is_empty() {
+            true => Ok(Movie { model, rom_crc, start, start_time, frames }),
+            false => Err(HydraIOError::MalformedMovie("Unexpected data after end of movie")),
+        }
+    }
+}
+
+/// A movie currently being recorded or played back.
+pub enum MovieSession {
+    Recording(Movie),
+    Playing(Movie, usize),
+}
+
+impl MovieSession {
+    /// Latches the inputs for the next frame, either from the host (recording them) or from the movie.
+    /// Called once as the movie starts, then once per frame. Returns false once playback has run out of inputs.
+    pub fn frame(&mut self, joypad: &mut Joypad, interrupt_flags: &mut InterruptFlags) -> bool {
+        let inputs = match self {
+            MovieSession::Recording(movie) => {
+                let inputs = joypad.get_host_inputs();
+                movie.frames.push(inputs);
+                inputs
+            }
+            MovieSession::Playing(movie, index) => {
+                let Some(&inputs) = movie.frames.get(*index) else {return false};
+                *index += 1;
+                inputs
+            }
+        };
+        joypad.override_inputs(Some(inputs), interrupt_flags);
+        true
+    }
+
+    pub fn into_movie(self) -> Movie {
+        match self {
+            MovieSession::Recording(movie) | MovieSession::Playing(movie, _) => movie,
+        }
+    }
+}
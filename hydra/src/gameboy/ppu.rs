@@ -1,22 +1,22 @@
 pub mod attributes;
 pub mod colormap;
+pub mod compatibility;
 pub mod fifo;
 pub mod state;
+pub mod viewer;
 
 use std::{
     cell::{Cell, RefCell}, collections::VecDeque, rc::Rc, sync::{Arc, RwLock}, thread, time::{Duration, Instant}
 };
 
-use winit::event_loop::EventLoopProxy;
-
 use crate::{
-    gameboy::{
+    common::{errors::HydraIOError, savestate::{SaveState, StateReader, StateWriter}}, gameboy::{
         GbMode, Model, memory::{MemoryMap, oam::{Oam, ObjectOamMetadata}, vram::Vram}, ppu::{attributes::TileAttributes, colormap::{Color, ColorMap}, fifo::FifoFetcher, state::{ObjectHeight, PpuState}}, timer::MasterTimer
-    }, graphics::Graphics, window::UserEvent
+    }, save_state_fields
 };
 
 pub struct Ppu {
-    model: Rc<Model>,
+    mode: Rc<Cell<GbMode>>,
     fifo: FifoFetcher,
 }
 
@@ -52,6 +52,34 @@ impl PpuMode {
     }
 }
 
+impl SaveState for PpuMode {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.as_u2().save_state(state);
+        if let PpuMode::OAMScan { current_address, obj_meta } = self {
+            current_address.save_state(state);
+            obj_meta.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), HydraIOError> {
+        let mut mode = 0u8;
+        mode.load_state(state)?;
+        *self = match mode {
+            0b00 => PpuMode::HBlank,
+            0b01 => PpuMode::VBlank,
+            0b10 => {
+                let (mut current_address, mut obj_meta) = (0, None);
+                current_address.load_state(state)?;
+                obj_meta.load_state(state)?;
+                PpuMode::OAMScan { current_address, obj_meta }
+            }
+            0b11 => PpuMode::Render,
+            _ => return Err(HydraIOError::MalformedSaveState("Invalid PPU mode")),
+        };
+        Ok(())
+    }
+}
+
 const SCANLINES: u32 = 154;
 pub(super) const SCREEN_WIDTH: u8 = 160;
 pub(super) const SCREEN_HEIGHT: u8 = 144;
@@ -60,9 +88,9 @@ const MAP_HEIGHT: u8 = 32;
 const BUFFER_SIZE: usize = SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 4;
 
 impl Ppu {
-    pub fn new(model: Rc<Model>) -> Self {
+    pub fn new(mode: Rc<Cell<GbMode>>) -> Self {
         Ppu {
-            model,
+            mode,
             fifo: FifoFetcher::new(),
         }
     }
@@ -80,15 +108,18 @@ impl Ppu {
                 if ly == SCREEN_HEIGHT {
                     memory.ppu_state.set_mode(PpuMode::VBlank, &mut memory.interrupt_flags);
                     memory.ppu_state.push_to_viewport();
+                    if let Some(sgb) = &mut memory.sgb {
+                        sgb.end_frame();
+                    }
                 } else if lx == 0 {
                     memory.ppu_state.set_mode(PpuMode::default_oam(), &mut memory.interrupt_flags);
-                    self.fifo.scanline_objects.clear();
+                    self.fifo.start_oam_scan(&memory.ppu_state);
                 }
             }
             PpuMode::VBlank => {
                 if ly == 0 {
                     memory.ppu_state.set_mode(PpuMode::default_oam(), &mut memory.interrupt_flags);
-                    self.fifo.scanline_objects.clear();
+                    self.fifo.start_oam_scan(&memory.ppu_state);
                 }
             }
             PpuMode::OAMScan{ref mut current_address, ref mut obj_meta} => {
@@ -107,27 +138,23 @@ impl Ppu {
 
                 // Update mode when complete
                 if *current_address > 0xFE9F {
-                    // No need to sort for CGB, because objects will already be in OAM order
-                    if self.model.is_monochrome() {
+                    // DMG mode fetches overlapping objects from left to right, so the leftmost wins.
+                    // CGB mode mixes them by OAM index instead, so they're left in OAM order
+                    if matches!(self.mode.get(), GbMode::DMG) {
                         self.fifo.scanline_objects.sort_by(|obj1, obj2| obj1.x.cmp(&obj2.x));
                     }
                     memory.ppu_state.set_mode(PpuMode::Render, &mut memory.interrupt_flags);
+                    self.fifo.start_scanline(&memory.ppu_state);
                 }
             }
             PpuMode::Render => {
-                // Screen texture generation
-
-                let (screen_x, screen_y) = (self.fifo.screen_x, ly);
-                let color = self.fifo.resolve_color(memory);
-
-                let buffer_address = (screen_x as usize + (screen_y as usize * SCREEN_WIDTH as usize)) * 4;
-                memory.ppu_state.screen_buffer[buffer_address..buffer_address + 4].copy_from_slice(&color);
-
-                // Return to HBlank upon completion of the scanline
-                if self.fifo.screen_x == 0 {
+                // Return to HBlank once the last pixel of the scanline is drawn, which takes longer the more the fetcher is held up
+                if self.fifo.tick(memory) {
                     memory.ppu_state.set_mode(PpuMode::HBlank, &mut memory.interrupt_flags);
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+save_state_fields!(Ppu { fifo });
\ No newline at end of file
@@ -1,6 +1,4 @@
-use std::rc::Rc;
-
-use crate::{deserialize, gameboy::{GbMode, Model}};
+use crate::{deserialize, gameboy::{GbMode, Model}, save_state_fields};
 
 pub struct TileAttributes {
     pub(super) bg_priority: bool,
@@ -10,9 +8,11 @@ pub struct TileAttributes {
     pub(super) palette: u8,
 }
 
+save_state_fields!(TileAttributes { bg_priority, y_flip, x_flip, bank_index, palette });
+
 impl TileAttributes {
-    pub fn from_u8(val: u8, mode: &Rc<GbMode>) -> Self {
-        match **mode {
+    pub fn from_u8(val: u8, mode: GbMode) -> Self {
+        match mode {
             GbMode::DMG => {
                 deserialize!(val;
                     [7] as bool =>> bg_priority;
@@ -1,12 +1,10 @@
 use std::{cell::RefCell, rc::Rc, time::Duration};
 
-use crate::{common::errors::HydraIOError, deserialize, gameboy::{GbMode, memory::MemoryMapped}, serialize};
+use crate::{common::{errors::HydraIOError, savestate::SaveState}, deserialize, gameboy::{GameBoy, GbMode, memory::MemoryMapped, ppu::compatibility::CompatibilityPalettes}, save_state_fields, serialize};
 
 pub type Color = [u8; 4];
 type DmgPaletteIndices = [u8; 4];
 
-pub const LCD_OFF_COLOR: Color = [255, 255, 255, 255];
-
 const R: usize = 0;
 const G: usize = 1;
 const B: usize = 2;
@@ -15,37 +13,128 @@ const A: usize = 3;
 const WHITE: [u8; 4] = [0xF8, 0xF8, 0xF8, 0xFF];
 const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
 
-pub trait ColorMap: MemoryMapped {
+pub trait ColorMap: MemoryMapped + SaveState {
     fn get_tile_color(&self, palette_index: u8, color_index: u8) -> Color;
     fn get_object_color(&self, palette_index: u8, color_index: u8) -> Color;
+
+    /// Returns the shade (0-3) BGP gives a color, which is all an SGB sees of the screen. Meaningless for CGB palettes.
+    fn get_tile_shade(&self, color_index: u8) -> u8;
+    /// Returns the shade (0-3) OBP0 or OBP1 gives a color. Meaningless for CGB palettes.
+    fn get_object_shade(&self, palette_index: u8, color_index: u8) -> u8;
+
+    /// Lists every palette, background palettes first.
+    fn get_palettes(&self) -> Vec<PaletteView>;
+    /// Replaces a color in one of the palettes listed by `get_palettes`, given as a shade (0-3) on DMG, or as RGB555 on CGB.
+    fn set_palette_color(&mut self, palette: usize, color_index: usize, value: u16);
+
+    /// Changes the colors DMG shades are displayed with. Has no effect where colors come from palette RAM instead.
+    fn set_shade_palette(&mut self, _shades: ShadePalette) {}
+    /// Chooses whether colors are looked up through BGP/OBP0/OBP1 first, as in DMG compatibility mode. Only meaningful on CGB.
+    fn set_compatibility(&mut self, _compatibility: bool) {}
+}
+
+/// The colors each of the four DMG shades are displayed with, given separately for the background and both object palettes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadePalette {
+    pub background: [Color; 4],
+    pub objects: [[Color; 4]; 2],
+}
+
+impl ShadePalette {
+    pub const GRAYSCALE: ShadePalette = ShadePalette::uniform([[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]]);
+    pub const DMG: ShadePalette = ShadePalette::uniform([[0x9B, 0xBC, 0x0F], [0x8B, 0xAC, 0x0F], [0x30, 0x62, 0x30], [0x0F, 0x38, 0x0F]]);
+    pub const POCKET: ShadePalette = ShadePalette::uniform([[0xE3, 0xE6, 0xC9], [0xC3, 0xC4, 0xA5], [0x8E, 0x8B, 0x61], [0x6C, 0x6C, 0x4E]]);
+    pub const LIGHT: ShadePalette = ShadePalette::uniform([[0x00, 0xB5, 0x81], [0x00, 0x9A, 0x71], [0x00, 0x69, 0x4A], [0x00, 0x51, 0x38]]);
+    pub const HIGH_CONTRAST: ShadePalette = ShadePalette::uniform([[255, 255, 255], [192, 192, 192], [64, 64, 64], [0, 0, 0]]);
+
+    /// Built-in palettes, by the name they're given in `config.toml` and the name they're shown with.
+    pub const PRESETS: [(&'static str, &'static str, ShadePalette); 5] = [
+        ("grayscale", "Grayscale", Self::GRAYSCALE),
+        ("dmg", "Original (DMG)", Self::DMG),
+        ("pocket", "Pocket (MGB)", Self::POCKET),
+        ("light", "Light (Backlit)", Self::LIGHT),
+        ("high_contrast", "High Contrast", Self::HIGH_CONTRAST),
+    ];
+
+    /// Builds a palette which colors every layer the same way, from lightest shade to darkest.
+    pub const fn uniform(shades: [[u8; 3]; 4]) -> Self {
+        let colors = Self::opaque(shades);
+        ShadePalette { background: colors, objects: [colors; 2] }
+    }
+
+    /// Builds a palette from separate background, OBJ0 and OBJ1 shades, each listed from lightest to darkest.
+    pub const fn layered(background: [[u8; 3]; 4], object0: [[u8; 3]; 4], object1: [[u8; 3]; 4]) -> Self {
+        ShadePalette { background: Self::opaque(background), objects: [Self::opaque(object0), Self::opaque(object1)] }
+    }
+
+    const fn opaque(shades: [[u8; 3]; 4]) -> [Color; 4] {
+        let mut colors = [[0, 0, 0, 0xFF]; 4];
+        let mut index = 0;
+        while index < 4 {
+            colors[index] = [shades[index][R], shades[index][G], shades[index][B], 0xFF];
+            index += 1;
+        }
+        colors
+    }
+}
+
+/// A palette as seen by the palette viewer, with its colors both as displayed and as stored.
+pub struct PaletteView {
+    pub name: String,
+    pub colors: [Color; 4],
+    /// Shades (0-3) on DMG, or RGB555 values on CGB.
+    pub values: [u16; 4],
+}
+
+const fn to_rgb555(color: Color) -> u16 {
+    (color[R] >> 3) as u16 | ((color[G] >> 3) as u16) << 5 | ((color[B] >> 3) as u16) << 10
+}
+
+pub const fn from_rgb555(value: u16) -> Color {
+    [(value as u8 & 0x1F) << 3, ((value >> 5) as u8 & 0x1F) << 3, ((value >> 10) as u8 & 0x1F) << 3, 0xFF]
+}
+
+impl GameBoy {
+    /// Changes the colors DMG shades are displayed with, keeping them across power cycles.
+    pub fn set_shade_palette(&mut self, shades: ShadePalette) {
+        self.shade_palette = shades;
+        self.memory.color_map.set_shade_palette(shades);
+    }
 }
 
 pub fn from_mode(mode: &GbMode) -> Box<dyn ColorMap> {
     match mode {
         GbMode::DMG => Box::new(DmgColorMap::new()),
-        GbMode::CGB => Box::new(CgbColorMap::new()),
+        GbMode::CGB => Box::new(CgbColorMap::new(false)),
     }
 }
 
+/// Builds the color map of a CGB running a DMG game, where BGP, OBP0 and OBP1 pick colors from the first few CGB palettes.
+/// Those palettes are either given here, or left for the boot ROM to fill in.
+pub fn for_compatibility(palettes: Option<&CompatibilityPalettes>) -> Box<dyn ColorMap> {
+    let mut color_map = CgbColorMap::new(true);
+    if let Some(palettes) = palettes {
+        color_map.background.palettes[0] = palettes.background.map(from_rgb555);
+        color_map.objects.palettes[0] = palettes.objects[0].map(from_rgb555);
+        color_map.objects.palettes[1] = palettes.objects[1].map(from_rgb555);
+    }
+    Box::new(color_map)
+}
+
 pub struct DmgColorMap {
     bg_palette: DmgPaletteIndices,
     ob_palettes: [DmgPaletteIndices; 2],
+    /// Configured by the user rather than the game, so it isn't saved.
+    shades: ShadePalette,
 }
 
 
 impl DmgColorMap {
-    // TODO: allow colors to be configured by user
-    const COLOR_MAP: [Color; 4] = [
-        [255, 255, 255, 255],
-        [170, 170, 170, 255],
-        [85, 85, 85, 255],
-        [0, 0, 0, 255],
-    ];
-
     fn new() -> Self {
         DmgColorMap {
             bg_palette: [0b00, 0b11, 0b11, 0b11],
             ob_palettes: [[0b11, 0b11, 0b11, 0b11]; 2],
+            shades: ShadePalette::GRAYSCALE,
         }
     }
 
@@ -88,11 +177,41 @@ impl DmgColorMap {
 
 impl ColorMap for DmgColorMap {
     fn get_tile_color(&self, _palette_index: u8, color_index: u8) -> Color {
-        Self::COLOR_MAP[self.bg_palette[color_index as usize] as usize]
+        self.shades.background[self.get_tile_shade(color_index) as usize]
     }
 
     fn get_object_color(&self, palette_index: u8, color_index: u8) -> Color {
-        Self::COLOR_MAP[self.ob_palettes[palette_index as usize][color_index as usize] as usize]
+        self.shades.objects[palette_index as usize][self.get_object_shade(palette_index, color_index) as usize]
+    }
+
+    fn get_tile_shade(&self, color_index: u8) -> u8 {
+        self.bg_palette[color_index as usize]
+    }
+
+    fn get_object_shade(&self, palette_index: u8, color_index: u8) -> u8 {
+        self.ob_palettes[palette_index as usize][color_index as usize]
+    }
+
+    fn get_palettes(&self) -> Vec<PaletteView> {
+        [("BGP", &self.bg_palette, &self.shades.background), ("OBP0", &self.ob_palettes[0], &self.shades.objects[0]), ("OBP1", &self.ob_palettes[1], &self.shades.objects[1])].into_iter()
+            .map(|(name, palette, shades)| PaletteView {
+                name: name.to_string(),
+                colors: palette.map(|shade| shades[shade as usize]),
+                values: palette.map(u16::from),
+            })
+            .collect()
+    }
+
+    fn set_palette_color(&mut self, palette: usize, color_index: usize, value: u16) {
+        let palette = match palette {
+            0 => &mut self.bg_palette,
+            _ => &mut self.ob_palettes[palette - 1],
+        };
+        palette[color_index] = value as u8 & 0b11;
+    }
+
+    fn set_shade_palette(&mut self, shades: ShadePalette) {
+        self.shades = shades;
     }
 }
 
@@ -116,16 +235,21 @@ impl MemoryMapped for DmgColorMap {
     }
 }
 
+save_state_fields!(DmgColorMap { bg_palette, ob_palettes });
+
 pub struct CgbColorMap {
     dmg: DmgColorMap,
     background: CgbPaletteBank,
     objects: CgbPaletteBank,
+    /// Set when running a DMG game, in which case colors are looked up through the DMG palettes first.
+    compatibility: bool,
 }
 
 impl CgbColorMap {
-    fn new() -> Self {
+    fn new(compatibility: bool) -> Self {
         CgbColorMap { 
             dmg: DmgColorMap::new(),
+            compatibility,
             background: CgbPaletteBank {
                 palettes: [[WHITE; 4]; 8], 
                 palette_index: 0b001000, 
@@ -142,11 +266,59 @@ impl CgbColorMap {
 
 impl ColorMap for CgbColorMap {
     fn get_tile_color(&self, palette_index: u8, color_index: u8) -> Color {
-        self.background.get_color(palette_index, color_index)
+        match self.compatibility {
+            true => self.background.get_color(0, self.dmg.bg_palette[color_index as usize]),
+            false => self.background.get_color(palette_index, color_index),
+        }
     }
 
     fn get_object_color(&self, palette_index: u8, color_index: u8) -> Color {
-        self.objects.get_color(palette_index, color_index)
+        match self.compatibility {
+            true => self.objects.get_color(palette_index, self.dmg.ob_palettes[palette_index as usize][color_index as usize]),
+            false => self.objects.get_color(palette_index, color_index),
+        }
+    }
+
+    fn get_tile_shade(&self, color_index: u8) -> u8 {
+        self.dmg.get_tile_shade(color_index)
+    }
+
+    fn get_object_shade(&self, palette_index: u8, color_index: u8) -> u8 {
+        self.dmg.get_object_shade(palette_index & 0b1, color_index)
+    }
+
+    fn get_palettes(&self) -> Vec<PaletteView> {
+        if self.compatibility {
+            // Listed (and edited) as DMG palettes, since that's what the game sees
+            let mut palettes = self.dmg.get_palettes();
+            palettes[0].colors = [0, 1, 2, 3].map(|color_index| self.get_tile_color(0, color_index));
+            for (index, palette) in palettes.iter_mut().enumerate().skip(1) {
+                palette.colors = [0, 1, 2, 3].map(|color_index| self.get_object_color(index as u8 - 1, color_index));
+            }
+            return palettes;
+        }
+        [("BG", &self.background), ("OBJ", &self.objects)].into_iter()
+            .flat_map(|(prefix, bank)| bank.palettes.iter().enumerate().map(move |(index, palette)| PaletteView {
+                name: format!("{}{}", prefix, index),
+                colors: *palette,
+                values: palette.map(to_rgb555),
+            }))
+            .collect()
+    }
+
+    fn set_compatibility(&mut self, compatibility: bool) {
+        self.compatibility = compatibility;
+    }
+
+    fn set_palette_color(&mut self, palette: usize, color_index: usize, value: u16) {
+        if self.compatibility {
+            return self.dmg.set_palette_color(palette, color_index, value);
+        }
+        let bank = match palette < 8 {
+            true => &mut self.background,
+            false => &mut self.objects,
+        };
+        bank.palettes[palette % 8][color_index] = from_rgb555(value);
     }
 }
 
@@ -178,6 +350,8 @@ impl MemoryMapped for CgbColorMap {
     }
 }
 
+save_state_fields!(CgbColorMap { dmg, background, objects });
+
 struct CgbPaletteBank {
     palettes: [[Color; 4]; 8],
 
@@ -185,6 +359,8 @@ struct CgbPaletteBank {
     index_auto_increment: bool,
 }
 
+save_state_fields!(CgbPaletteBank { palettes, palette_index, index_auto_increment });
+
 impl CgbPaletteBank {
     fn get_color(&self, palette_index: u8, color_index: u8) -> Color {
         self.palettes[palette_index as usize][color_index as usize]
@@ -0,0 +1,128 @@
+use crate::gameboy::{JoypButton, JoypDpad, joypad::InputVectors, memory::rom::RomHeader};
+
+/// The colors the CGB boot ROM gives a DMG game, as RGB555.
+pub struct CompatibilityPalettes {
+    pub background: [u16; 4],
+    pub objects: [[u16; 4]; 2],
+}
+
+impl CompatibilityPalettes {
+    /// Picks palettes the same way the CGB boot ROM does: from a held D-pad direction (optionally with A or B) if there is one,
+    /// or else by looking up the checksum of the cartridge's title, if it was published by Nintendo.
+    pub fn select(header: &RomHeader, inputs: &InputVectors) -> Self {
+        let combination = match Self::combination_from_inputs(inputs) {
+            Some(combination) => combination,
+            None => Self::combination_from_title(header),
+        };
+        let [object0, object1, background] = PALETTE_COMBINATIONS[combination];
+        let colors = |offset: usize| [0, 1, 2, 3].map(|color_index| PALETTE_COLORS[offset + color_index]);
+        CompatibilityPalettes {
+            background: colors(background),
+            objects: [colors(object0), colors(object1)],
+        }
+    }
+
+    fn combination_from_inputs(inputs: &InputVectors) -> Option<usize> {
+        let a = inputs.is_button_pressed(JoypButton::A);
+        let b = inputs.is_button_pressed(JoypButton::B);
+        let direction = [JoypDpad::Right, JoypDpad::Left, JoypDpad::Up, JoypDpad::Down].into_iter().position(|dpad| inputs.is_dpad_pressed(dpad))?;
+        // Rows are Right, Left, Up and Down, and columns are alone, with A and with B
+        const COMBINATIONS: [[usize; 3]; 4] = [
+            [1, 0, 6],
+            [48, 40, 7],
+            [5, 43, 28],
+            [8, 3, 49],
+        ];
+        Some(COMBINATIONS[direction][match (a, b) {
+            (true, _) => 1,
+            (false, true) => 2,
+            (false, false) => 0,
+        }])
+    }
+
+    fn combination_from_title(header: &RomHeader) -> usize {
+        if !header.has_publisher_rnd1() {
+            return 0;
+        }
+        let title = header.get_title();
+        let checksum = title.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        // Some checksums are shared by several games, which are told apart by the 4th letter of their titles
+        let index = TITLE_CHECKSUMS.iter().enumerate().position(|(index, &entry)| {
+            entry == checksum && index.checked_sub(UNIQUE_CHECKSUMS).is_none_or(|letter_index| FOURTH_LETTERS[letter_index] == title[3])
+        });
+        index.map_or(0, |index| PALETTE_PER_CHECKSUM[index] as usize)
+    }
+}
+
+/// Title checksums recognized by the boot ROM. Those from `UNIQUE_CHECKSUMS` onwards are only matched along with `FOURTH_LETTERS`.
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B,
+    0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4,
+    0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4,
+    0xB3,
+];
+const UNIQUE_CHECKSUMS: usize = 65;
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// The palette combination (an index into `PALETTE_COMBINATIONS`) used by each entry in `TITLE_CHECKSUMS`.
+const PALETTE_PER_CHECKSUM: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44,
+    21, 32, 31, 20, 5, 33, 13, 14, 5, 29, 5, 18, 9, 3, 2, 26,
+    25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34,
+    5, 42, 6, 5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0,
+    39,
+    36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39, 24, 31, 50,
+    17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18,
+    29,
+];
+
+/// Offsets into `PALETTE_COLORS` for OBJ0, OBJ1 and BG, in that order. A few start partway through a palette.
+const PALETTE_COMBINATIONS: [[usize; 3]; 51] = [
+    [16, 16, 116], [72, 72, 72], [80, 80, 80], [96, 96, 96], [36, 36, 36], [0, 0, 0],
+    [108, 108, 108], [20, 20, 20], [48, 48, 48], [104, 104, 104], [64, 32, 32], [16, 112, 112],
+    [16, 8, 8], [12, 16, 16], [16, 116, 116], [112, 16, 112], [8, 68, 8], [64, 64, 32],
+    [16, 16, 28], [16, 16, 72], [16, 16, 80], [76, 76, 36], [15, 15, 44], [68, 68, 8],
+    [16, 16, 8], [16, 16, 12], [112, 112, 0], [12, 12, 0], [0, 0, 4], [72, 88, 72],
+    [80, 88, 80], [96, 88, 96], [64, 88, 32], [68, 16, 52], [111, 0, 56], [111, 16, 60],
+    [76, 88, 36], [64, 112, 40], [16, 92, 112], [68, 88, 8], [16, 0, 8], [16, 112, 12],
+    [112, 12, 0], [12, 112, 16], [84, 112, 16], [12, 112, 0], [100, 12, 112], [0, 112, 32],
+    [16, 12, 112], [112, 12, 24], [16, 112, 116],
+];
+
+/// Every color used by the compatibility palettes, as RGB555, in groups of four.
+const PALETTE_COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000,
+    0x639F, 0x4279, 0x15B0, 0x04CB,
+    0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000,
+    0x7FFF, 0x421F, 0x1CF2, 0x0000,
+    0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000,
+    0x7FFF, 0x03EF, 0x01D6, 0x0000,
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000,
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B,
+    0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000,
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0,
+    0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF,
+    0x7FFF, 0x01DF, 0x0112, 0x0000,
+    0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000,
+    0x299F, 0x001A, 0x000C, 0x0000,
+    0x7FFF, 0x027F, 0x001F, 0x0000,
+    0x7FFF, 0x03E0, 0x0206, 0x0120,
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00,
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000,
+    0x03FF, 0x001F, 0x000C, 0x0000,
+    0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF,
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000,
+    0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
@@ -1,129 +1,570 @@
-use crate::gameboy::{memory::{MemoryMap, oam::ObjectOamMetadata}, ppu::{self, SCREEN_WIDTH, attributes::TileAttributes, colormap::{self, Color}, state::{ObjectHeight, PpuState}}};
+use std::collections::VecDeque;
+
+use crate::{gameboy::{GbMode, memory::{MemoryMap, oam::ObjectOamMetadata}, ppu::{self, SCREEN_WIDTH, attributes::TileAttributes, colormap::Color, state::{ObjectHeight, PpuState}}}, save_state_fields};
 
 pub struct FifoFetcher {
-    // bg_fifo: [Color; 16],
-    // ob_fifo: [Color; 16],
+    bg_fifo: VecDeque<BgPixel>,
+    ob_fifo: VecDeque<ObjPixel>,
 
     pub(super) scanline_objects: Vec<ObjectOamMetadata>,
 
     pub(super) screen_x: u8,
     pub(super) screen_y: u8,
+
+    /// Dots left before the fetcher starts, standing in for the tile fetch the PPU throws away at the start of each line.
+    startup_dots: u8,
+    /// Pixels still to be dropped from the front of the background FIFO, for SCX's fine scroll.
+    discard: u8,
+
+    fetcher_dot: u8,
+    fetcher_x: u8,
+    fetching_window: bool,
+    /// Set once LY has matched WY at the start of a line, and held until the next frame. The window can't be drawn until then.
+    wy_latched: bool,
+    /// The row of the window to draw next, which only advances on lines where the window was actually drawn.
+    window_line: u8,
+    /// Set once the window has been reached on this line, even if it's since been turned off.
+    window_drawn: bool,
+    /// With WX at 166, the window starts on the line's very last pixel, and carries on through the whole of the next line.
+    window_spill: bool,
+    tile_index: u8,
+    tile_attributes: TileAttributes,
+    tile_data: [u8; 2],
+
+    object_fetch: Option<ObjectOamMetadata>,
+    object_fetch_dots: u8,
 }
 
+save_state_fields!(FifoFetcher {
+    bg_fifo,
+    ob_fifo,
+    scanline_objects,
+    screen_x,
+    screen_y,
+    startup_dots,
+    discard,
+    fetcher_dot,
+    fetcher_x,
+    fetching_window,
+    wy_latched,
+    window_line,
+    window_drawn,
+    window_spill,
+    tile_index,
+    tile_attributes,
+    tile_data,
+    object_fetch,
+    object_fetch_dots,
+});
+
 impl FifoFetcher {
+    const STARTUP_DOTS: u8 = 6;
+    /// The fetcher reads the tile index, then the low and high bytes of its data, spending two dots on each,
+    /// then pushes the row into the background FIFO as soon as it's empty.
+    const TILE_INDEX_DOT: u8 = 1;
+    const DATA_LOW_DOT: u8 = 3;
+    const DATA_HIGH_DOT: u8 = 5;
+    const PUSH_DOT: u8 = 6;
+    const OBJECT_FETCH_DOTS: u8 = 6;
+
     pub fn new() -> Self {
-        FifoFetcher { 
-            // bg_fifo: [], 
-            // ob_fifo: (), 
+        FifoFetcher {
+            bg_fifo: VecDeque::with_capacity(8),
+            ob_fifo: VecDeque::with_capacity(8),
+
+            scanline_objects: Vec::with_capacity(10),
 
-            scanline_objects: Vec::with_capacity(10), 
+            screen_x: 0,
+            screen_y: 0,
 
-            screen_x: 0, 
-            screen_y: 0 
+            startup_dots: Self::STARTUP_DOTS,
+            discard: 0,
+
+            fetcher_dot: 0,
+            fetcher_x: 0,
+            fetching_window: false,
+            wy_latched: false,
+            window_line: 0,
+            window_drawn: false,
+            window_spill: false,
+            tile_index: 0,
+            tile_attributes: TileAttributes::default(),
+            tile_data: [0; 2],
+
+            object_fetch: None,
+            object_fetch_dots: 0,
         }
     }
-    
-    pub fn resolve_color(&mut self, memory: &mut MemoryMap) -> Color {
-        self.screen_y = memory.ppu_state.read_ly();
-        let color = self.resolve_color_inner(memory);
-        self.screen_x = (self.screen_x + 1) % SCREEN_WIDTH;
 
-        color
+    /// Forgets the last line's objects before OAM is scanned for the next, and checks whether LY has reached WY.
+    pub fn start_oam_scan(&mut self, ppu_state: &PpuState) {
+        self.scanline_objects.clear();
+        if ppu_state.read_ly() == 0 {
+            self.wy_latched = false;
+            self.window_line = 0;
+            self.window_spill = false;
+        }
+        self.wy_latched |= ppu_state.read_ly() == ppu_state.wy;
     }
 
-    fn resolve_color_inner(&mut self, memory: &mut MemoryMap) -> Color {
-        if !memory.ppu_state.lcd_enabled {
-            return colormap::LCD_OFF_COLOR;
+    /// Resets the fetcher and both FIFOs for the line about to be drawn.
+    pub fn start_scanline(&mut self, ppu_state: &PpuState) {
+        self.bg_fifo.clear();
+        self.ob_fifo.clear();
+        self.screen_x = 0;
+        self.screen_y = ppu_state.read_ly();
+        self.startup_dots = Self::STARTUP_DOTS;
+        self.discard = ppu_state.scx % 8;
+        self.fetcher_dot = 0;
+        self.fetcher_x = 0;
+        self.fetching_window = false;
+        self.window_drawn = false;
+        self.object_fetch = None;
+
+        if self.window_spill && ppu_state.window_enabled {
+            self.fetching_window = true;
+            self.window_drawn = true;
+            self.discard = 0;
         }
+        self.window_spill = false;
+    }
 
-        // Check BG/window color first
-        let (bg_palette_index, bg_color_index, bg_priority) = if memory.ppu_state.tilemaps_enabled { // TODO: use tilemaps_enabled for priority in CGB mode
-            let is_window = memory.ppu_state.window_enabled && self.screen_x >= memory.ppu_state.wx.saturating_sub(7) && self.screen_y >= memory.ppu_state.wy;
+    /// Advances the fetchers and FIFOs by one dot of mode 3, drawing at most one pixel.
+    /// Returns true once the last pixel of the line has been drawn.
+    pub fn tick(&mut self, memory: &mut MemoryMap) -> bool {
+        if self.startup_dots > 0 {
+            self.startup_dots -= 1;
+            return false;
+        }
 
-            let data_low_address = memory.ppu_state.tilemaps_data_area as u16;
-            let (map_address, map_x, map_y) = match is_window {
-                false => (
-                    memory.ppu_state.bg_map_area as u16,
-                    self.screen_x.wrapping_add(memory.ppu_state.scx),
-                    self.screen_y.wrapping_add(memory.ppu_state.scy),
-                ),
-                true => (
-                    memory.ppu_state.win_map_area as u16,
-                    self.screen_x - memory.ppu_state.wx + 7,
-                    self.screen_y - memory.ppu_state.wy,
-                ),
+        // An object whose left edge has been reached stalls everything until it's fetched,
+        // though the background fetcher gets to finish reading its current tile (and fill the FIFO, at the start of a line) first.
+        // Objects hanging off the left edge are reached straight away, but the rest wait for SCX's fine scroll to be discarded
+        if self.object_fetch.is_none() && memory.ppu_state.objects_enabled
+        && let Some(index) = self.scanline_objects.iter().position(|obj| obj.x <= self.screen_x + 8 && (obj.x < 8 || self.discard == 0)) {
+            if self.fetcher_dot < Self::DATA_HIGH_DOT || self.bg_fifo.is_empty() {
+                self.step_fetcher(memory);
+                return false;
+            }
+            self.object_fetch = Some(self.scanline_objects.remove(index));
+            self.object_fetch_dots = 0;
+        }
+        if self.object_fetch.is_some() {
+            self.object_fetch_dots += 1;
+            if self.object_fetch_dots == Self::OBJECT_FETCH_DOTS && let Some(obj) = self.object_fetch.take() {
+                self.fetch_object(&obj, memory);
+            }
+            return false;
+        }
+
+        // Reaching the window throws away the background pixels fetched so far, and starts fetching from the window's map instead
+        let state = &memory.ppu_state;
+        if !self.fetching_window && self.wy_latched && state.window_enabled && self.screen_x + 7 >= state.wx {
+            self.fetching_window = true;
+            self.window_drawn = true;
+            self.bg_fifo.clear();
+            self.fetcher_dot = 0;
+            self.fetcher_x = 0;
+            // Below 7, the window starts off the left edge of the screen, so its first few pixels are dropped.
+            // At 0, it's triggered partway through SCX's fine scroll instead, so it shifts around along with SCX
+            self.discard = match state.wx {
+                0 => 7 - state.scx % 8,
+                wx => 7u8.saturating_sub(wx),
             };
+        }
+        // Turning the window off partway through goes back to the background, picking up from the tile under the next pixel to be fetched.
+        // Whatever's already in the FIFO is still drawn
+        if self.fetching_window && !state.window_enabled {
+            self.fetching_window = false;
+            self.fetcher_dot = 0;
+            self.fetcher_x = (self.screen_x + self.bg_fifo.len() as u8 + state.scx % 8) / 8;
+        }
 
-            let map_index_x = (map_x / 8) as u16;
-            let map_index_y = (map_y / 8) as u16;
-            let data_index_address = map_address + map_index_x + (map_index_y * ppu::MAP_WIDTH as u16);
+        let line_complete = self.shift_pixel(memory);
+        self.step_fetcher(memory);
+        line_complete
+    }
 
-            let (data_index, tile_attributes) = memory.vram.read_tile_map(data_index_address);
-            let data_address = if data_index < 0x80 {
-                data_low_address + (data_index as u16 * 16)
-            } else {
-                0x8800 + ((data_index - 0x80) as u16 * 16)
-            };
+    fn step_fetcher(&mut self, memory: &MemoryMap) {
+        match self.fetcher_dot {
+            Self::TILE_INDEX_DOT => {
+                let state = &memory.ppu_state;
+                let (map_area, map_x) = match self.fetching_window {
+                    false => (state.bg_map_area as u16, (state.scx / 8).wrapping_add(self.fetcher_x) % ppu::MAP_WIDTH),
+                    true => (state.win_map_area as u16, self.fetcher_x % ppu::MAP_WIDTH),
+                };
+                let map_y = self.map_y(state) / 8;
+                (self.tile_index, self.tile_attributes) = memory.vram.read_tile_map(map_area + map_x as u16 + map_y as u16 * ppu::MAP_WIDTH as u16);
+            }
+            Self::DATA_LOW_DOT => self.tile_data[0] = memory.vram.read_tile_data(self.tile_row_address(&memory.ppu_state), self.tile_attributes.bank_index),
+            Self::DATA_HIGH_DOT => self.tile_data[1] = memory.vram.read_tile_data(self.tile_row_address(&memory.ppu_state) + 1, self.tile_attributes.bank_index),
+            _ => {}
+        }
+        if self.fetcher_dot < Self::PUSH_DOT {
+            self.fetcher_dot += 1;
+        }
 
-            let tile_y = map_y % 8;
-            let tile_x = map_x % 8;
-            
-            (tile_attributes.palette, self.resolve_color_index(tile_x, tile_y, data_address, &tile_attributes, false, memory), tile_attributes.bg_priority)
+        if self.fetcher_dot == Self::PUSH_DOT && self.bg_fifo.is_empty() {
+            for color_index in Self::decode_row(self.tile_data, self.tile_attributes.x_flip) {
+                self.bg_fifo.push_back(BgPixel { color_index, palette: self.tile_attributes.palette, priority: self.tile_attributes.bg_priority });
+            }
+            self.fetcher_x = self.fetcher_x.wrapping_add(1);
+            self.fetcher_dot = 0;
+        }
+    }
+
+    /// The row of the background or window map the current line falls on, in pixels.
+    fn map_y(&self, state: &PpuState) -> u8 {
+        match self.fetching_window {
+            false => self.screen_y.wrapping_add(state.scy),
+            true => self.window_line,
+        }
+    }
+
+    fn tile_row_address(&self, state: &PpuState) -> u16 {
+        let tile_address = if self.tile_index < 0x80 {
+            state.tilemaps_data_area as u16 + (self.tile_index as u16 * 16)
         } else {
-            (0, 0, false)
+            0x8800 + ((self.tile_index - 0x80) as u16 * 16)
         };
+        let tile_y = self.map_y(state) % 8;
+        let tile_y = if self.tile_attributes.y_flip {7 - tile_y} else {tile_y};
+        tile_address + (tile_y as u16 * 2)
+    }
 
-        // Return early if BG color has priority over any potential objects
-        let bg_color = memory.color_map.get_tile_color(bg_palette_index, bg_color_index);
-        let bg_can_override = bg_color_index != 0;
-        if bg_priority && bg_can_override {
-            return bg_color;
-        }
-
-        // If background does not have inherent priority, check for opaque object pixels
-        let valid_objects = self.scanline_objects.iter().filter(|obj| obj.occupies_x(self.screen_x));
-        if memory.ppu_state.objects_enabled { 
-            for oam_meta in valid_objects {
-                let mut render_meta = memory.oam.resolve_oam_meta(oam_meta);
-                // Ignore LSB of tile index if objects are tall
-                if matches!(memory.ppu_state.object_size, ObjectHeight::Tall) {
-                    render_meta.data_index &= 0b11111110; 
-                }
-
-                let data_address = 0x8000 + (render_meta.data_index as u16 * 16);
+    /// Reads an object's row for this line, and mixes it into the object FIFO beneath any opaque pixels already there.
+    fn fetch_object(&mut self, obj: &ObjectOamMetadata, memory: &MemoryMap) {
+        let mut render_meta = memory.oam.resolve_oam_meta(obj);
+        let height = memory.ppu_state.object_size;
+        // Ignore LSB of tile index if objects are tall
+        if matches!(height, ObjectHeight::Tall) {
+            render_meta.data_index &= 0b11111110;
+        }
+        let attributes = render_meta.attributes;
 
-                let tile_y = self.screen_y + 16 - oam_meta.y;
-                let tile_x = self.screen_x - oam_meta.x;
-                let obj_color_index = self.resolve_color_index(tile_x, tile_y, data_address, &render_meta.attributes, true, memory);
+        // Objects that were tall during OAM scan but have been shortened since wrap around to the top of their tile
+        let tile_y = (self.screen_y + 16 - obj.y) & (height as u8 - 1);
+        let tile_y = if attributes.y_flip {height as u8 - 1 - tile_y} else {tile_y};
+        let row_address = 0x8000 + (render_meta.data_index as u16 * 16) + (tile_y as u16 * 2);
+        let data = [memory.vram.read_tile_data(row_address, attributes.bank_index), memory.vram.read_tile_data(row_address + 1, attributes.bank_index)];
 
-                if obj_color_index != 0 {
-                    return match render_meta.attributes.bg_priority && bg_can_override {
-                        true => bg_color,
-                        false => memory.color_map.get_object_color(render_meta.attributes.palette, obj_color_index)
-                    }
-                }
+        while self.ob_fifo.len() < 8 {
+            self.ob_fifo.push_back(ObjPixel::default());
+        }
+        // Where objects overlap, the one fetched first (the leftmost) stays on top in DMG mode, while in CGB mode the one earliest in OAM does
+        let oam_priority = matches!(memory.mode.get(), GbMode::CGB);
+        let oam_index = memory.oam.get_index(obj);
+        // Objects partially off the left edge of the screen lose their leftmost columns
+        let hidden_columns = 8u8.saturating_sub(obj.x) as usize;
+        for (slot, color_index) in self.ob_fifo.iter_mut().zip(Self::decode_row(data, attributes.x_flip).into_iter().skip(hidden_columns)) {
+            if color_index != 0 && (slot.color_index == 0 || (oam_priority && oam_index < slot.oam_index)) {
+                *slot = ObjPixel { color_index, palette: attributes.palette, bg_priority: attributes.bg_priority, oam_index };
             }
         }
+    }
+
+    /// Splits a row of tile data into its eight color indices, from left to right.
+    fn decode_row(data: [u8; 2], x_flip: bool) -> [u8; 8] {
+        std::array::from_fn(|x| {
+            let bit = if x_flip {x} else {7 - x};
+            ((data[1] >> bit) & 1) << 1 | ((data[0] >> bit) & 1)
+        })
+    }
+
+    /// Shifts a pixel out of the background FIFO (along with one from the object FIFO, if there is one) and draws it.
+    fn shift_pixel(&mut self, memory: &mut MemoryMap) -> bool {
+        let Some(bg) = self.bg_fifo.pop_front() else {
+            return false;
+        };
+        if self.discard > 0 {
+            self.discard -= 1;
+            return false;
+        }
+        let obj = self.ob_fifo.pop_front();
+
+        let color = self.resolve_color(self.mix_pixels(bg, obj, memory), memory);
+        let buffer_address = (self.screen_x as usize + (self.screen_y as usize * SCREEN_WIDTH as usize)) * 4;
+        memory.ppu_state.screen_buffer[buffer_address..buffer_address + 4].copy_from_slice(&color);
 
-        // Return background color if no opaque object pixels are found
-        return bg_color
+        self.screen_x += 1;
+        if self.screen_x == SCREEN_WIDTH {
+            self.screen_x = 0;
+            if self.window_drawn {
+                self.window_line = self.window_line.wrapping_add(1);
+            }
+            self.window_spill = self.fetching_window && memory.ppu_state.wx == 166;
+            return true;
+        }
+        false
     }
 
-    fn resolve_color_index(&self, tile_x: u8, tile_y: u8, tile_address: u16, attributes: &TileAttributes, is_object: bool, memory: &mut MemoryMap) -> u8 {
-        let tile_x = match attributes.x_flip {
-            true => 7 - tile_x,
-            false => tile_x
+    fn mix_pixels(&self, bg: BgPixel, obj: Option<ObjPixel>, memory: &MemoryMap) -> Pixel {
+        let state = &memory.ppu_state;
+        // In DMG mode, clearing LCDC.0 blanks the background and window.
+        // In CGB mode, they're still drawn, but lose any priority they had over objects
+        let bg = match memory.mode.get() {
+            GbMode::DMG if !state.tilemaps_enabled => BgPixel::default(),
+            _ => bg,
         };
-        let tile_y = match attributes.y_flip {
-            true => (if !is_object || matches!(memory.ppu_state.object_size, ObjectHeight::Standard) {7} else {15}) - tile_y,
-            false => tile_y
+        let bg_pixel = Pixel::Background { palette: bg.palette, color_index: bg.color_index };
+        match obj {
+            Some(obj) if obj.color_index != 0 && state.objects_enabled => match state.tilemaps_enabled && (obj.bg_priority || bg.priority) && bg.color_index != 0 {
+                true => bg_pixel,
+                false => Pixel::Object { palette: obj.palette, color_index: obj.color_index },
+            },
+            _ => bg_pixel,
+        }
+    }
+
+    fn resolve_color(&self, pixel: Pixel, memory: &mut MemoryMap) -> Color {
+        let color_map = &memory.color_map;
+        let (color, shade) = match pixel {
+            Pixel::Background { palette, color_index } => (color_map.get_tile_color(palette, color_index), color_map.get_tile_shade(color_index)),
+            Pixel::Object { palette, color_index } => (color_map.get_object_color(palette, color_index), color_map.get_object_shade(palette, color_index)),
         };
+        if let Some(sgb) = &mut memory.sgb {
+            sgb.capture_pixel(self.screen_x, self.screen_y, shade);
+        }
+        color
+    }
+}
+
+/// A background or window pixel, waiting in the FIFO to be drawn.
+#[derive(Copy, Clone, Default)]
+struct BgPixel {
+    color_index: u8,
+    palette: u8,
+    priority: bool,
+}
+
+save_state_fields!(BgPixel { color_index, palette, priority });
+
+/// An object pixel, waiting in the FIFO to be mixed with the background.
+#[derive(Copy, Clone, Default)]
+struct ObjPixel {
+    color_index: u8,
+    palette: u8,
+    bg_priority: bool,
+    oam_index: u8,
+}
 
-        let byte_address = tile_address + (tile_y as u16 * 2);
-        let data = [memory.vram.read_tile_data(byte_address, attributes.bank_index),
-            memory.vram.read_tile_data(byte_address + 1, attributes.bank_index)];
+save_state_fields!(ObjPixel { color_index, palette, bg_priority, oam_index });
 
-        let color_index_bits = data.map(|byte| (byte >> (7 - tile_x)) & 1);
-        color_index_bits[1] << 1 | color_index_bits[0]
+/// Where a pixel's color comes from, before it's looked up in a palette.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Pixel {
+    Background { palette: u8, color_index: u8 },
+    Object { palette: u8, color_index: u8 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{AGBRevision, CGBRevision, GBRevision, GameBoy, Model, SGBRevision, ppu::state::TileMapArea};
+
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+    const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+    /// A console at the start of line 0, running a cartridge with the given CGB flag, with a blank background map.
+    /// Tile 1 is filled with color 3 and tile 2 with color 1, and every palette shows those colors differently.
+    fn console(model: Model, cgb_flag: u8) -> GameBoy {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0143] = cgb_flag;
+        let mut gb = GameBoy::headless(rom, model).unwrap();
+        while gb.memory.ppu_state.read_ly() != 0 {
+            gb.step_instruction();
+        }
+        let memory = &mut gb.memory;
+        for address in 0x8000..0x8010 {
+            memory.debug_write_u8(0x00, address);
+            memory.debug_write_u8(0xFF, address + 0x10);
+            memory.debug_write_u8(if address % 2 == 0 { 0xFF } else { 0x00 }, address + 0x20);
+        }
+        for address in 0x9800..0xA000 {
+            memory.debug_write_u8(0x00, address);
+        }
+        for register in [0xFF47, 0xFF48, 0xFF49] {
+            memory.debug_write_u8(0b11100100, register);
+        }
+        for (index_register, data_register) in [(0xFF68, 0xFF69), (0xFF6A, 0xFF6B)] {
+            memory.debug_write_u8(0x80, index_register);
+            for _ in 0..8 {
+                for color in [0x7FFF, 0x001F, 0x03E0, 0x7C00u16] {
+                    memory.debug_write_u8(color as u8, data_register);
+                    memory.debug_write_u8((color >> 8) as u8, data_register);
+                }
+            }
+        }
+        memory.ppu_state.objects_enabled = true;
+        gb
     }
-}
\ No newline at end of file
+
+    fn dmg() -> GameBoy {
+        console(Model::GameBoy(GBRevision::DMG), 0x00)
+    }
+
+    /// Draws a whole line with objects at the given OAM X positions, using the given tiles, and returns how many dots of mode 3 it took.
+    /// Objects are left in OAM order in CGB mode, and sorted by X in DMG mode, as OAM scan leaves them.
+    fn draw_line(fifo: &mut FifoFetcher, memory: &mut MemoryMap, objects: &[(u8, u8)]) -> u32 {
+        fifo.start_oam_scan(&memory.ppu_state);
+        for (index, &(x, tile)) in objects.iter().enumerate() {
+            let address = 0xFE00 + index as u16 * 4;
+            memory.oam.write(address + 2, tile).unwrap();
+            memory.oam.write(address + 3, 0).unwrap();
+            fifo.scanline_objects.push(ObjectOamMetadata { address, y: memory.ppu_state.read_ly() + 16, x });
+        }
+        if memory.mode.get() == GbMode::DMG {
+            fifo.scanline_objects.sort_by_key(|obj| obj.x);
+        }
+        fifo.start_scanline(&memory.ppu_state);
+        let mut dots = 1;
+        while !fifo.tick(memory) {
+            dots += 1;
+        }
+        dots
+    }
+
+    /// The time objects hold up mode 3, per Pan Docs: 6 dots to fetch each, plus however long the background fetcher
+    /// still needs to finish the tile its left edge falls on, which only the first object on that tile waits for.
+    /// One at X 0 always waits the longest. Objects partly off the left edge aren't covered.
+    fn object_penalties(scx: u8, objects: &[u8]) -> u32 {
+        let mut sorted = objects.to_vec();
+        sorted.sort();
+        let mut waited_tiles = Vec::new();
+        sorted.into_iter().map(|x| {
+            let tile = (x as u32 + scx as u32 % 8) / 8;
+            let wait = match x {
+                0 => 5,
+                _ if waited_tiles.contains(&tile) => 0,
+                _ => 5u32.saturating_sub((x as u32 + scx as u32) % 8),
+            };
+            waited_tiles.push(tile);
+            6 + wait
+        }).sum()
+    }
+
+    #[test]
+    fn mode_3_length() {
+        let cases: &[&[u8]] = &[&[], &[0], &[8], &[9], &[12], &[13], &[15], &[40, 41], &[20, 80, 120, 167], &[60; 10]];
+        for scx in 0..8 {
+            for objects in cases {
+                let mut gb = dmg();
+                gb.memory.ppu_state.scx = scx;
+                let mut fifo = FifoFetcher::new();
+                let expected = 172 + scx as u32 % 8 + object_penalties(scx, objects);
+                let objects: Vec<_> = objects.iter().map(|&x| (x, 1)).collect();
+                assert_eq!(draw_line(&mut fifo, &mut gb.memory, &objects), expected, "SCX {} with objects at {:?}", scx, objects);
+            }
+        }
+    }
+
+    #[test]
+    fn window_disabled_mid_line() {
+        let mut gb = dmg();
+        let memory = &mut gb.memory;
+        for address in 0x9C00..0xA000 {
+            memory.debug_write_u8(0x01, address);
+        }
+        let state = &mut memory.ppu_state;
+        state.win_map_area = TileMapArea::Map1;
+        state.window_enabled = true;
+        state.wx = 7;
+        state.wy = state.read_ly();
+
+        let mut fifo = FifoFetcher::new();
+        fifo.start_oam_scan(&memory.ppu_state);
+        fifo.start_scanline(&memory.ppu_state);
+        while fifo.screen_x < 80 {
+            fifo.tick(memory);
+        }
+        memory.ppu_state.window_enabled = false;
+        while !fifo.tick(memory) {}
+
+        let pixel = |x: usize| &memory.ppu_state.screen_buffer[x * 4..x * 4 + 4];
+        assert_eq!(pixel(40), BLACK, "window should be drawn until it's disabled");
+        assert_eq!(pixel(120), WHITE, "background should be drawn once the window is disabled");
+        assert_eq!(fifo.window_line, 1, "line the window was partly drawn on should still count");
+    }
+
+    #[test]
+    fn object_order() {
+        // Object 0 has color 3, and object 1 (which has color 1) overlaps its left half, from screen X 8 to 15.
+        // The leftmost object wins in DMG mode, including DMG compatibility mode, but the first in OAM wins in CGB mode
+        let cases = [
+            (Model::GameBoy(GBRevision::DMG), 0x00, GbMode::DMG, 1),
+            (Model::GameBoyColor(CGBRevision::CGB), 0x80, GbMode::CGB, 0),
+            (Model::GameBoyColor(CGBRevision::CGB), 0x00, GbMode::DMG, 1),
+        ];
+        for (model, cgb_flag, mode, winner) in cases {
+            let mut gb = console(model, cgb_flag);
+            assert_eq!(gb.memory.mode.get(), mode);
+            let mut fifo = FifoFetcher::new();
+            draw_line(&mut fifo, &mut gb.memory, &[(20, 1), (16, 2)]);
+
+            let y = gb.memory.ppu_state.read_ly() as usize * SCREEN_WIDTH as usize;
+            let pixel = |x: usize| &gb.memory.ppu_state.screen_buffer[(y + x) * 4..(y + x) * 4 + 4];
+            let only = [pixel(17), pixel(9)];
+            assert_ne!(only[0], only[1], "{} should draw each object's color differently", model.as_str());
+            assert_eq!(pixel(13), only[winner], "{} drew the wrong object on top", model.as_str());
+        }
+    }
+
+    #[test]
+    fn background_priority() {
+        const BG: Pixel = Pixel::Background { palette: 2, color_index: 1 };
+        const OBJ: Pixel = Pixel::Object { palette: 1, color_index: 3 };
+
+        // Mode, LCDC.0, background color, background's priority attribute, object's priority attribute, and the pixel drawn.
+        // LCDC.0 blanks the background in DMG mode, but in CGB mode only takes away any priority it has over objects
+        let cases = [
+            (GbMode::DMG, true, 1, false, false, OBJ),
+            (GbMode::DMG, true, 1, false, true, BG),
+            (GbMode::DMG, true, 0, false, true, OBJ),
+            (GbMode::DMG, false, 1, false, true, OBJ),
+            (GbMode::CGB, true, 1, false, false, OBJ),
+            (GbMode::CGB, true, 1, true, false, BG),
+            (GbMode::CGB, true, 1, false, true, BG),
+            (GbMode::CGB, true, 0, true, true, OBJ),
+            (GbMode::CGB, false, 1, true, true, OBJ),
+        ];
+        for (mode, lcdc_0, bg_color, bg_priority, obj_priority, expected) in cases {
+            let mut gb = match mode {
+                GbMode::DMG => dmg(),
+                GbMode::CGB => console(Model::GameBoyColor(CGBRevision::CGB), 0x80),
+            };
+            gb.memory.ppu_state.tilemaps_enabled = lcdc_0;
+            let fifo = FifoFetcher::new();
+            let bg = BgPixel { color_index: bg_color, palette: 2, priority: bg_priority };
+            let obj = ObjPixel { color_index: 3, palette: 1, bg_priority: obj_priority, oam_index: 0 };
+            assert_eq!(fifo.mix_pixels(bg, Some(obj), &gb.memory), expected, "{:?} with LCDC.0 {}, BG color {}, BG priority {}, object priority {}", mode, lcdc_0, bg_color, bg_priority, obj_priority);
+        }
+
+        let mut gb = dmg();
+        gb.memory.ppu_state.tilemaps_enabled = false;
+        let bg = BgPixel { color_index: 1, palette: 0, priority: false };
+        assert_eq!(FifoFetcher::new().mix_pixels(bg, None, &gb.memory), Pixel::Background { palette: 0, color_index: 0 }, "DMG mode should blank the background without LCDC.0");
+    }
+
+    /// Consoles which skip the boot ROM can start on any line, including those below the screen.
+    #[test]
+    fn every_starting_line() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+        let models = [
+            Model::GameBoy(GBRevision::DMG0),
+            Model::SuperGameBoy(SGBRevision::SGB),
+            Model::GameBoyColor(CGBRevision::CGB),
+            Model::GameBoyAdvance(AGBRevision::AGB),
+        ];
+        for model in models {
+            let mut gb = GameBoy::headless(rom.clone(), model).unwrap();
+            for seed in 0..100 {
+                gb.power_on(seed).unwrap();
+                let ly = gb.memory.ppu_state.read_ly();
+                assert!(ly < 154, "{:?} started on line {}", model, ly);
+                assert_eq!(gb.memory.ppu_state.get_mode().as_u2() == 1, ly >= 144, "{:?} started on line {} in the wrong mode", model, ly);
+                gb.run_cycles(12 * 456); // Through VBlank and back to line 0, from any line below the screen
+            }
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use std::{cell::RefCell, rc::Rc, sync::{Arc, RwLock}, time::Duration};
+use std::{cell::RefCell, rc::Rc, time::Duration};
 
-use winit::event_loop::EventLoopProxy;
+use rand::{Rng, rngs::StdRng};
 
-use crate::{common::{bit::BitVec, errors::HydraIOError}, deserialize, gameboy::{GBRevision, Model, interrupt::{Interrupt, InterruptFlags}, memory::{MemoryMap, MemoryMapped}, ppu::{self, Ppu, PpuMode}}, graphics::Graphics, serialize, window::UserEvent};
+use crate::{common::{bit::BitVec, errors::HydraIOError, output::VideoOutput}, deserialize, gameboy::{GBRevision, Model, interrupt::{Interrupt, InterruptFlags}, memory::{MemoryMap, MemoryMapped}, ppu::{self, Ppu, PpuMode}}, save_state_enum, save_state_fields, serialize};
 
 pub struct PpuState {
     pub(super) ppu_mode: PpuMode,
@@ -30,36 +30,39 @@ pub struct PpuState {
     stat_interrupt_select: u8,
 
     pub(super) screen_buffer: Box<[u8]>,
-    graphics: Arc<RwLock<Graphics>>,
-    proxy: EventLoopProxy<UserEvent>
+    frame_buffer: Box<[u8]>,
+    video_output: Option<Box<dyn VideoOutput>>,
 }
 
 impl PpuState {
-    pub fn new(model: &Rc<Model>, graphics: Arc<RwLock<Graphics>>, proxy: EventLoopProxy<UserEvent>) -> Self {
+    /// Builds the PPU's state as the boot ROM leaves it, or with the LCD still off if a boot ROM is going to run.
+    pub fn new(model: &Rc<Model>, boot_rom: bool, rng: &mut StdRng) -> Self {
         let screen_buffer = vec![0; ppu::BUFFER_SIZE].into_boxed_slice();
+        let frame_buffer = screen_buffer.clone();
 
-        // Start at beginning of OAM scan for selected ly
+        // Start at the beginning of the selected line: OAM scan if it's visible, otherwise VBlank
         let ly = match **model {
+            _ if boot_rom => 0x00,
             Model::GameBoy(GBRevision::DMG0) => 0x91,
             Model::GameBoy(_) => 0x00,
-            Model::SuperGameBoy(_) | Model::GameBoyColor(_) | Model::GameBoyAdvance(_) => rand::random(), // TODO: Number is supposed to be based on boot rom cycles
+            Model::SuperGameBoy(_) | Model::GameBoyColor(_) | Model::GameBoyAdvance(_) => rng.random_range(0..154), // TODO: Number is supposed to be based on boot rom cycles
         };
         
         PpuState { 
-            ppu_mode: PpuMode::default_oam(),
+            ppu_mode: if ly < ppu::SCREEN_HEIGHT {PpuMode::default_oam()} else {PpuMode::VBlank},
 
             dots: ly as u32 * Self::DOTS_PER_SCANLINE,
             ly,
             lyc: 0,
 
-            lcd_enabled: true,
+            lcd_enabled: !boot_rom,
             window_enabled: false,
             objects_enabled: false,
-            tilemaps_enabled: true,
+            tilemaps_enabled: !boot_rom,
 
             bg_map_area: TileMapArea::Map0,
             win_map_area: TileMapArea::Map0,
-            tilemaps_data_area: TileLowDataArea::Data1,
+            tilemaps_data_area: TileLowDataArea::from_bool(!boot_rom),
 
             object_size: ObjectHeight::Standard,
 
@@ -71,8 +74,8 @@ impl PpuState {
             stat_interrupt_select: 0,
 
             screen_buffer,
-            graphics,
-            proxy
+            frame_buffer,
+            video_output: None,
         }
     }
 
@@ -137,10 +140,33 @@ impl PpuState {
     }
 
     pub(super) fn push_to_viewport(&mut self) {
-        // Send redraw request through event loop proxy
-        let graphics = self.graphics.read().unwrap();
-        graphics.update_screen_texture(&self.screen_buffer);
-        self.proxy.send_event(UserEvent::RedrawRequest).expect("Unable to render Game Boy graphics: Main event loop closed unexpectedly");
+        // Keep a copy of the finished frame, since rendering the next one overwrites the screen buffer
+        self.frame_buffer.copy_from_slice(&self.screen_buffer);
+        if let Some(output) = &mut self.video_output {
+            output.present(&self.frame_buffer);
+        }
+    }
+
+    /// Sends every completed frame to the given output (such as a window), in addition to the internal frame buffer.
+    pub fn attach_output(&mut self, output: Box<dyn VideoOutput>) {
+        self.video_output = Some(output);
+    }
+
+    /// Detaches and returns the current output, if any.
+    pub fn take_output(&mut self) -> Option<Box<dyn VideoOutput>> {
+        self.video_output.take()
+    }
+
+    /// Sends the most recently completed frame to the attached output again, e.g. after it was replaced by loading a save state.
+    pub fn resend_frame(&mut self) {
+        if let Some(output) = &mut self.video_output {
+            output.present(&self.frame_buffer);
+        }
+    }
+
+    /// Returns the most recently completed frame as tightly packed RGBA8 pixels.
+    pub fn get_frame(&self) -> &[u8] {
+        &self.frame_buffer
     }
 }
 
@@ -248,6 +274,28 @@ impl PpuState {
     }
 }
 
+save_state_fields!(PpuState {
+    ppu_mode,
+    dots,
+    ly,
+    lyc,
+    lcd_enabled,
+    window_enabled,
+    objects_enabled,
+    tilemaps_enabled,
+    bg_map_area,
+    win_map_area,
+    tilemaps_data_area,
+    object_size,
+    scy,
+    scx,
+    wy,
+    wx,
+    stat_interrupt_select,
+    screen_buffer,
+    frame_buffer,
+});
+
 #[derive(Copy, Clone)]
 #[repr(u16)]
 pub enum TileMapArea {
@@ -260,6 +308,8 @@ impl TileMapArea {
     }
 }
 
+save_state_enum!(TileMapArea { Map0, Map1 });
+
 #[derive(Copy, Clone)]
 #[repr(u16)]
 pub enum TileLowDataArea {
@@ -272,6 +322,8 @@ impl TileLowDataArea {
     }
 }
 
+save_state_enum!(TileLowDataArea { Data0, Data1 });
+
 #[derive(Copy, Clone)]
 pub enum ObjectHeight {
     Standard = 8,
@@ -281,4 +333,6 @@ impl ObjectHeight {
     pub fn from_bool(cond: bool) -> Self {
         if cond {ObjectHeight::Tall} else {ObjectHeight::Standard}
     }
-}
\ No newline at end of file
+}
+
+save_state_enum!(ObjectHeight { Standard, Tall });
\ No newline at end of file
@@ -0,0 +1,413 @@
+use crate::gameboy::{GameBoy, GbMode, memory::vram::Vram, ppu::{MAP_HEIGHT, MAP_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH, attributes::TileAttributes, colormap::{Color, PaletteView}, state::{ObjectHeight, TileLowDataArea, TileMapArea}}};
+
+/// The debug views of VRAM and OAM that can be rendered.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Viewer {
+    /// All tile data, in every bank.
+    Tiles,
+    /// Both 32x32 tile maps, with the background's viewport overlaid.
+    Tilemaps,
+    /// All 40 objects, along with their attributes.
+    Objects,
+    /// Every palette's colors, which can be edited by scrolling over them.
+    Palettes,
+}
+
+/// The colors tile data is shown with in the tile viewer, since tiles don't have colors of their own.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TilePalette {
+    Grayscale,
+    Background(u8),
+    Object(u8),
+}
+
+/// A rendered debug view, as tightly packed RGBA8 pixels.
+#[derive(Debug)]
+pub struct ViewerImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// A destination for rendered debug views, such as a set of windows.
+pub trait ViewerOutput {
+    fn present(&mut self, viewer: Viewer, image: ViewerImage);
+}
+
+/// The debug views currently open, which are redrawn once per frame.
+pub struct Viewers {
+    open: Vec<Viewer>,
+    tile_palette: TilePalette,
+    output: Option<Box<dyn ViewerOutput>>,
+}
+
+impl Viewers {
+    pub fn new() -> Self {
+        Viewers { open: Vec::new(), tile_palette: TilePalette::Background(0), output: None }
+    }
+}
+
+const BACKGROUND: Color = [0x20, 0x20, 0x20, 0xFF];
+const TEXT: Color = [0xE0, 0xE0, 0xE0, 0xFF];
+const VIEWPORT: Color = [0xFF, 0x30, 0x30, 0xFF];
+const TRANSPARENT: Color = [0x60, 0x60, 0x60, 0xFF];
+const GRAYSCALE: [Color; 4] = [
+    [255, 255, 255, 255],
+    [170, 170, 170, 255],
+    [85, 85, 85, 255],
+    [0, 0, 0, 255],
+];
+
+const GAP: u32 = 4;
+const HEADER_HEIGHT: u32 = 8;
+const TILES_PER_ROW: u32 = 16;
+const TILES_PER_BANK: u32 = 384;
+const OBJECT_COUNT: u32 = 40;
+const OBJECT_ROWS: u32 = 20;
+const OBJECT_ROW_HEIGHT: u32 = 18;
+const OBJECT_COLUMN_WIDTH: u32 = 120;
+const PALETTES_PER_COLUMN: u32 = 8;
+const PALETTE_NAME_WIDTH: u32 = 20;
+const PALETTE_COLUMN_WIDTH: u32 = PALETTE_NAME_WIDTH + 4 * (SWATCH_WIDTH + 2) + GAP;
+const PALETTE_ROW_HEIGHT: u32 = SWATCH_HEIGHT + 10;
+const SWATCH_WIDTH: u32 = 18;
+const SWATCH_HEIGHT: u32 = 12;
+
+impl ViewerImage {
+    fn new(width: u32, height: u32) -> Self {
+        ViewerImage { width, height, pixels: BACKGROUND.repeat((width * height) as usize) }
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
+        if x < self.width && y < self.height {
+            let index = ((x + y * self.width) * 4) as usize;
+            self.pixels[index..index + 4].copy_from_slice(&color);
+        }
+    }
+
+    /// Draws a line of text in a 3x5 font, with one pixel between characters.
+    fn draw_text(&mut self, x: u32, y: u32, text: &str) {
+        for (offset, character) in text.chars().enumerate() {
+            let Some((_, glyph)) = GLYPHS.iter().find(|(glyph_char, _)| *glyph_char == character.to_ascii_uppercase()) else {continue};
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.set_pixel(x + offset as u32 * 4 + column, y + row as u32, TEXT);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reads the color index of a single pixel from a tile, without any flipping.
+fn tile_pixel(vram: &Vram, tile_address: u16, bank: u8, x: u8, y: u8) -> u8 {
+    let byte_address = tile_address + y as u16 * 2;
+    let (low, high) = (vram.read_tile_data(byte_address, bank), vram.read_tile_data(byte_address + 1, bank));
+    ((high >> (7 - x)) & 1) << 1 | ((low >> (7 - x)) & 1)
+}
+
+/// Reads the color index of a single pixel from a tile, applying its attributes' flips. `height` is 8, or 16 for tall objects.
+fn attributed_pixel(vram: &Vram, tile_address: u16, attributes: &TileAttributes, height: u8, x: u8, y: u8) -> u8 {
+    let x = if attributes.x_flip {7 - x} else {x};
+    let y = if attributes.y_flip {height - 1 - y} else {y};
+    tile_pixel(vram, tile_address, attributes.bank_index, x, y)
+}
+
+impl GameBoy {
+    pub fn open_viewer(&mut self, viewer: Viewer) {
+        if !self.viewers.open.contains(&viewer) {
+            self.viewers.open.push(viewer);
+        }
+    }
+
+    pub fn close_viewer(&mut self, viewer: Viewer) {
+        self.viewers.open.retain(|open| *open != viewer);
+    }
+
+    pub fn set_tile_palette(&mut self, palette: TilePalette) {
+        self.viewers.tile_palette = palette;
+    }
+
+    /// Sends the open debug views to the given output (such as a set of windows) after every frame.
+    pub fn attach_viewer_output(&mut self, output: Box<dyn ViewerOutput>) {
+        self.viewers.output = Some(output);
+    }
+
+    /// Redraws every open debug view, and sends them to the attached output.
+    pub fn present_viewers(&mut self) {
+        if self.viewers.output.is_none() {
+            return;
+        }
+        for viewer in self.viewers.open.clone() {
+            let image = self.render_viewer(viewer);
+            if let Some(output) = &mut self.viewers.output {
+                output.present(viewer, image);
+            }
+        }
+    }
+
+    pub fn render_viewer(&self, viewer: Viewer) -> ViewerImage {
+        match viewer {
+            Viewer::Tiles => self.render_tiles(self.viewers.tile_palette),
+            Viewer::Tilemaps => self.render_tilemaps(),
+            Viewer::Objects => self.render_objects(),
+            Viewer::Palettes => self.render_palettes(),
+        }
+    }
+
+    /// Handles the mouse wheel being scrolled `delta` notches over the given pixel of a debug view.
+    /// Over a palette's color, this nudges its shade on DMG, or whichever of its red, green or blue thirds is under the cursor on CGB.
+    pub fn scroll_viewer(&mut self, viewer: Viewer, x: u32, y: u32, delta: i32) {
+        if viewer != Viewer::Palettes {
+            return;
+        }
+        let palettes = self.get_palettes();
+        let Some((palette, color_index, third)) = palette_color_at(palettes.len(), x, y) else {return};
+        let value = palettes[palette].values[color_index] as i32;
+        let value = match self.memory.mode.get() {
+            GbMode::DMG => (value + delta).clamp(0, 3),
+            GbMode::CGB => {
+                let shift = third * 5;
+                let channel = ((value >> shift) & 0x1F) + delta;
+                value & !(0x1F << shift) | channel.clamp(0, 0x1F) << shift
+            }
+        };
+        self.set_palette_color(palette, color_index, value as u16);
+    }
+
+    /// Lists every palette, background palettes first: BGP, OBP0 and OBP1 on DMG, or BG0-7 and OBJ0-7 on CGB.
+    pub fn get_palettes(&self) -> Vec<PaletteView> {
+        self.memory.color_map.get_palettes()
+    }
+
+    /// Replaces a color in one of the palettes listed by `get_palettes`, given as a shade (0-3) on DMG, or as RGB555 on CGB.
+    pub fn set_palette_color(&mut self, palette: usize, color_index: usize, value: u16) {
+        self.memory.color_map.set_palette_color(palette, color_index, value);
+    }
+
+    /// Renders all 384 tiles of every VRAM bank, 16 tiles per row, with each bank side by side.
+    pub fn render_tiles(&self, palette: TilePalette) -> ViewerImage {
+        let vram = &self.memory.vram;
+        let color_map = &self.memory.color_map;
+        let palette_mask = match self.memory.mode.get() {
+            GbMode::DMG => 0b001,
+            GbMode::CGB => 0b111,
+        };
+        let colors: [Color; 4] = std::array::from_fn(|index| match palette {
+            TilePalette::Grayscale => GRAYSCALE[index],
+            TilePalette::Background(palette) => color_map.get_tile_color(palette & 0b111, index as u8),
+            TilePalette::Object(palette) => color_map.get_object_color(palette & palette_mask, index as u8),
+        });
+
+        let bank_width = TILES_PER_ROW * 8;
+        let bank_height = TILES_PER_BANK / TILES_PER_ROW * 8;
+        let banks = vram.bank_count() as u32;
+        let mut image = ViewerImage::new(banks * (bank_width + GAP) - GAP, HEADER_HEIGHT + bank_height);
+        for bank in 0..banks {
+            let left = bank * (bank_width + GAP);
+            image.draw_text(left, 1, &format!("BANK {}", bank));
+            for tile in 0..TILES_PER_BANK {
+                let tile_address = 0x8000 + tile as u16 * 16;
+                let (tile_left, tile_top) = (left + tile % TILES_PER_ROW * 8, HEADER_HEIGHT + tile / TILES_PER_ROW * 8);
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let color_index = tile_pixel(vram, tile_address, bank as u8, x, y);
+                        image.set_pixel(tile_left + x as u32, tile_top + y as u32, colors[color_index as usize]);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    /// Renders both tile maps side by side, addressing tile data as LCDC currently selects.
+    /// The screen's viewport is outlined on whichever map the background is using.
+    pub fn render_tilemaps(&self) -> ViewerImage {
+        let vram = &self.memory.vram;
+        let ppu_state = &self.memory.ppu_state;
+        let map_width = MAP_WIDTH as u32 * 8;
+        let map_height = MAP_HEIGHT as u32 * 8;
+        let mut image = ViewerImage::new(map_width * 2 + GAP, HEADER_HEIGHT + map_height);
+
+        for (index, area) in [TileMapArea::Map0, TileMapArea::Map1].into_iter().enumerate() {
+            let left = index as u32 * (map_width + GAP);
+            let is_background = ppu_state.bg_map_area as u16 == area as u16;
+            let is_window = ppu_state.win_map_area as u16 == area as u16;
+            let usage = match (is_background, is_window) {
+                (true, true) => " BG+WIN",
+                (true, false) => " BG",
+                (false, true) => " WIN",
+                (false, false) => "",
+            };
+            image.draw_text(left, 1, &format!("{:04X}{}", area as u16, usage));
+
+            for map_y in 0..MAP_HEIGHT as u16 {
+                for map_x in 0..MAP_WIDTH as u16 {
+                    let (data_index, attributes) = vram.read_tile_map(area as u16 + map_x + map_y * MAP_WIDTH as u16);
+                    let tile_address = match (data_index < 0x80, ppu_state.tilemaps_data_area) {
+                        (true, TileLowDataArea::Data0) => 0x9000 + data_index as u16 * 16,
+                        (true, TileLowDataArea::Data1) => 0x8000 + data_index as u16 * 16,
+                        (false, _) => 0x8800 + (data_index - 0x80) as u16 * 16,
+                    };
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            let color_index = attributed_pixel(vram, tile_address, &attributes, 8, x, y);
+                            let color = self.memory.color_map.get_tile_color(attributes.palette, color_index);
+                            image.set_pixel(left + map_x as u32 * 8 + x as u32, HEADER_HEIGHT + map_y as u32 * 8 + y as u32, color);
+                        }
+                    }
+                }
+            }
+
+            if is_background {
+                // The viewport wraps around the edges of the map, just as the background does
+                let (scx, scy) = (ppu_state.scx as u32, ppu_state.scy as u32);
+                let (width, height) = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+                let mut outline = |x: u32, y: u32| image.set_pixel(left + (scx + x) % map_width, HEADER_HEIGHT + (scy + y) % map_height, VIEWPORT);
+                for x in 0..width {
+                    outline(x, 0);
+                    outline(x, height - 1);
+                }
+                for y in 0..height {
+                    outline(0, y);
+                    outline(width - 1, y);
+                }
+            }
+        }
+        image
+    }
+
+    /// Renders a preview of each object in OAM order, in two columns, each followed by its position, tile and attributes.
+    pub fn render_objects(&self) -> ViewerImage {
+        let vram = &self.memory.vram;
+        let oam = &self.memory.oam;
+        let height = self.memory.ppu_state.object_size as u8;
+        let mut image = ViewerImage::new(OBJECT_COLUMN_WIDTH * 2, OBJECT_ROWS * OBJECT_ROW_HEIGHT);
+
+        for index in 0..OBJECT_COUNT {
+            let (left, top) = (index / OBJECT_ROWS * OBJECT_COLUMN_WIDTH, index % OBJECT_ROWS * OBJECT_ROW_HEIGHT);
+            let oam_meta = oam.get_oam_meta(0xFE00 + index as u16 * 4);
+            let render_meta = oam.resolve_oam_meta(&oam_meta);
+            let attributes = &render_meta.attributes;
+
+            // Tall objects ignore the lowest bit of their tile index, just as when they're rendered
+            let data_index = match self.memory.ppu_state.object_size {
+                ObjectHeight::Standard => render_meta.data_index,
+                ObjectHeight::Tall => render_meta.data_index & 0b11111110,
+            };
+            let tile_address = 0x8000 + data_index as u16 * 16;
+            for y in 0..height {
+                for x in 0..8 {
+                    let color = match attributed_pixel(vram, tile_address, attributes, height, x, y) {
+                        0 => TRANSPARENT,
+                        color_index => self.memory.color_map.get_object_color(attributes.palette, color_index),
+                    };
+                    image.set_pixel(left + 2 + x as u32, top + 1 + y as u32, color);
+                }
+            }
+
+            let palette = match self.memory.mode.get() {
+                GbMode::DMG => format!("OBP{}", attributes.palette),
+                GbMode::CGB => format!("PAL{} VRAM{}", attributes.palette, attributes.bank_index),
+            };
+            let flags: String = [(attributes.x_flip, " XFLIP"), (attributes.y_flip, " YFLIP"), (attributes.bg_priority, " BGPRI")].iter()
+                .filter(|(is_set, _)| *is_set)
+                .map(|(_, flag)| *flag)
+                .collect();
+            image.draw_text(left + 14, top + 2, &format!("{:02} X:{:02X} Y:{:02X} T:{:02X}", index, oam_meta.x, oam_meta.y, render_meta.data_index));
+            image.draw_text(left + 14, top + 9, &format!("{}{}", palette, flags));
+        }
+        image
+    }
+
+    /// Renders every palette as a row of swatches, each labelled with its raw value. Background palettes make up the first column.
+    pub fn render_palettes(&self) -> ViewerImage {
+        let palettes = self.get_palettes();
+        let columns = (palettes.len() as u32).div_ceil(PALETTES_PER_COLUMN);
+        let rows = (palettes.len() as u32).min(PALETTES_PER_COLUMN);
+        let mut image = ViewerImage::new(columns * PALETTE_COLUMN_WIDTH - GAP, HEADER_HEIGHT + rows * PALETTE_ROW_HEIGHT);
+        image.draw_text(0, 1, "SCROLL OVER A COLOR TO EDIT IT");
+
+        for (index, palette) in palettes.iter().enumerate() {
+            let (left, top) = palette_position(index as u32);
+            image.draw_text(left, top + 4, &palette.name);
+            for (color_index, (color, value)) in palette.colors.iter().zip(palette.values).enumerate() {
+                let swatch_left = left + PALETTE_NAME_WIDTH + color_index as u32 * (SWATCH_WIDTH + 2);
+                for y in 0..SWATCH_HEIGHT {
+                    for x in 0..SWATCH_WIDTH {
+                        image.set_pixel(swatch_left + x, top + y, *color);
+                    }
+                }
+                let text = match self.memory.mode.get() {
+                    GbMode::DMG => value.to_string(),
+                    GbMode::CGB => format!("{:04X}", value),
+                };
+                image.draw_text(swatch_left + 1, top + SWATCH_HEIGHT + 2, &text);
+            }
+        }
+        image
+    }
+}
+
+/// Returns the top-left corner of a palette's row in the palette viewer.
+const fn palette_position(index: u32) -> (u32, u32) {
+    (index / PALETTES_PER_COLUMN * PALETTE_COLUMN_WIDTH, HEADER_HEIGHT + index % PALETTES_PER_COLUMN * PALETTE_ROW_HEIGHT)
+}
+
+/// Finds the palette color drawn at the given pixel of the palette viewer, along with which third of its swatch the pixel is in.
+fn palette_color_at(palette_count: usize, x: u32, y: u32) -> Option<(usize, usize, u32)> {
+    (0..palette_count).find_map(|palette| {
+        let (left, top) = palette_position(palette as u32);
+        let x = x.checked_sub(left + PALETTE_NAME_WIDTH)?;
+        let y = y.checked_sub(top)?;
+        let color_index = x / (SWATCH_WIDTH + 2);
+        let swatch_x = x % (SWATCH_WIDTH + 2);
+        (color_index < 4 && swatch_x < SWATCH_WIDTH && y < SWATCH_HEIGHT).then_some((palette, color_index as usize, swatch_x * 3 / SWATCH_WIDTH))
+    })
+}
+
+/// A 3x5 font covering everything the viewers label themselves with. Each row's three pixels are given left to right.
+const GLYPHS: [(char, [u8; 5]); 41] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+];
@@ -1,23 +1,25 @@
-use std::rc::Rc;
+use std::{cell::Cell, collections::VecDeque, rc::Rc};
 
-use crate::{common::{errors::HydraIOError, timing::ModuloCounter}, deserialize, gameboy::{GbMode, Model, interrupt::{Interrupt, InterruptFlags}, memory::MemoryMapped}, serialize};
+use crate::{common::{errors::HydraIOError, timing::ModuloCounter}, deserialize, gameboy::{GbMode, Model, interrupt::{Interrupt, InterruptFlags}, memory::MemoryMapped}, save_state_fields, serialize};
 
 pub struct SerialConnection {
-    mode: Rc<GbMode>,
+    mode: Rc<Cell<GbMode>>,
 
     m_cycle_counter: ModuloCounter<u8>,
     transfer_cycles_remaining: u8,
     local_clock: bool,
 
     data: u8,
+    sent_bytes: VecDeque<u8>,
 }
 
 impl SerialConnection {
     const DMG_LOW_SPEED: u8 = 128;
     const CGB_HIGH_SPEED: u8 = 4;
+    const SENT_BYTES_CAPACITY: usize = 0x10000;
 
-    pub fn new(mode: Rc<GbMode>) -> Self {
-        let is_cgb = matches!(*mode, GbMode::CGB);
+    pub fn new(mode: Rc<Cell<GbMode>>) -> Self {
+        let is_cgb = matches!(mode.get(), GbMode::CGB);
         SerialConnection { 
             mode, 
 
@@ -26,6 +28,7 @@ impl SerialConnection {
             local_clock: is_cgb,
 
             data: 0x00,
+            sent_bytes: VecDeque::new(),
         }
     }
 
@@ -54,7 +57,7 @@ impl SerialConnection {
         serialize!(
             ((self.transfer_cycles_remaining != 0) as u8) =>> [7];
             0b01111100;
-            ((matches!(*self.mode, GbMode::DMG) || self.m_cycle_counter.modulus == Self::CGB_HIGH_SPEED) as u8) =>> [1];
+            ((matches!(self.mode.get(), GbMode::DMG) || self.m_cycle_counter.modulus == Self::CGB_HIGH_SPEED) as u8) =>> [1];
             (self.local_clock as u8) =>> [0];
         )
     }
@@ -68,13 +71,30 @@ impl SerialConnection {
         if self.transfer_cycles_remaining == 0 {
             if transfer_enabled {
                 self.transfer_cycles_remaining = 8;
+                if self.local_clock {
+                    self.record_sent_byte();
+                }
             }
-            self.m_cycle_counter.modulus = if matches!(*self.mode, GbMode::CGB) && high_speed {Self::CGB_HIGH_SPEED} else {Self::DMG_LOW_SPEED};
+            self.m_cycle_counter.modulus = if matches!(self.mode.get(), GbMode::CGB) && high_speed {Self::CGB_HIGH_SPEED} else {Self::DMG_LOW_SPEED};
             self.m_cycle_counter.reset();
         }
     }
 }
 
+impl SerialConnection {
+    fn record_sent_byte(&mut self) {
+        if self.sent_bytes.len() == Self::SENT_BYTES_CAPACITY {
+            self.sent_bytes.pop_front();
+        }
+        self.sent_bytes.push_back(self.data);
+    }
+
+    /// Drains every byte shifted out under the internal clock since the last call, oldest first.
+    pub fn take_sent_bytes(&mut self) -> Vec<u8> {
+        self.sent_bytes.drain(..).collect()
+    }
+}
+
 impl MemoryMapped for SerialConnection {
     fn read(&self, address: u16) -> Result<u8, HydraIOError> {
         match address {
@@ -91,4 +111,6 @@ impl MemoryMapped for SerialConnection {
             _ => Err(HydraIOError::OpenBusAccess),
         }
     }
-}
\ No newline at end of file
+}
+
+save_state_fields!(SerialConnection { m_cycle_counter, transfer_cycles_remaining, local_clock, data });
\ No newline at end of file
@@ -0,0 +1,458 @@
+pub mod packet;
+
+use std::cmp::Ordering;
+
+use crate::{
+    common::output::VideoOutput, gameboy::{interrupt::InterruptFlags, joypad::Joypad, ppu::{self, colormap::{self, Color}}, sgb::packet::Packet}, save_state_enum, save_state_fields
+};
+
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 224;
+const FRAME_SIZE: usize = FRAME_WIDTH * FRAME_HEIGHT * 4;
+
+const SCREEN_WIDTH: usize = ppu::SCREEN_WIDTH as usize;
+const SCREEN_HEIGHT: usize = ppu::SCREEN_HEIGHT as usize;
+/// Where the Game Boy's screen sits within the border.
+const SCREEN_X: usize = (FRAME_WIDTH - SCREEN_WIDTH) / 2;
+const SCREEN_Y: usize = (FRAME_HEIGHT - SCREEN_HEIGHT) / 2;
+
+/// Palettes are assigned to the screen in 8x8 cells.
+const CELLS_X: usize = SCREEN_WIDTH / 8;
+const CELLS_Y: usize = SCREEN_HEIGHT / 8;
+const CELL_COUNT: usize = CELLS_X * CELLS_Y;
+
+const SYSTEM_PALETTE_COUNT: usize = 512;
+const ATTRIBUTE_FILE_SIZE: usize = CELL_COUNT / 4;
+const ATTRIBUTE_FILE_COUNT: usize = 45;
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_MAP_WIDTH: usize = 32;
+/// VRAM transfers copy 4 KiB, read from the screen as 256 tiles laid out 20 to a row.
+const TRANSFER_SIZE: usize = 0x1000;
+
+/// The palette the SGB starts with (1-A), before a game picks its own.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+/// The SNES side of a Super Game Boy, which colorizes the Game Boy's screen and draws a border around it, as directed by packets sent over the joypad lines.
+/// Commands for the SNES's sound and CPU (e.g. SOUND, JUMP and DATA_SND) are ignored.
+pub struct Sgb {
+    /// Whether the cartridge declares SGB support in its header. If it doesn't, every packet is ignored.
+    enabled: bool,
+    /// The packets received so far of a command that spans more than one.
+    command: Vec<u8>,
+    packets_left: u8,
+
+    palettes: [[u16; 4]; 4],
+    system_palettes: Box<[[u16; 4]]>,
+    /// The palette used by each 8x8 cell of the screen.
+    attributes: Box<[u8]>,
+    attribute_files: Box<[u8]>,
+    mask: ScreenMask,
+    transfer: Option<VramTransfer>,
+    /// Set at the end of the frame a transfer was requested during, so that it's made from the next whole frame.
+    transfer_armed: bool,
+
+    /// Both banks of 4bpp SNES tiles, as sent with CHR_TRN.
+    border_tiles: Box<[u8]>,
+    /// 32x32 tile map entries, as sent with PCT_TRN. Only the first 28 rows are shown.
+    border_map: Box<[u16]>,
+    /// Palettes 4-7, used by the border.
+    border_palettes: [[u16; 16]; 4],
+
+    /// Shades captured from the Game Boy's LCD as it draws.
+    screen: Box<[u8]>,
+    /// Shades shown on the last frame, which are left alone while the screen is frozen.
+    shown_screen: Box<[u8]>,
+    frame_buffer: Box<[u8]>,
+    video_output: Option<Box<dyn VideoOutput>>,
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Sgb {
+            enabled: false,
+            command: Vec::new(),
+            packets_left: 0,
+
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![[0; 4]; SYSTEM_PALETTE_COUNT].into_boxed_slice(),
+            attributes: vec![0; CELL_COUNT].into_boxed_slice(),
+            attribute_files: vec![0; ATTRIBUTE_FILE_SIZE * ATTRIBUTE_FILE_COUNT].into_boxed_slice(),
+            mask: ScreenMask::None,
+            transfer: None,
+            transfer_armed: false,
+
+            border_tiles: vec![0; BORDER_TILE_SIZE * 256].into_boxed_slice(),
+            border_map: vec![0; BORDER_MAP_WIDTH * 32].into_boxed_slice(),
+            border_palettes: [[0; 16]; 4],
+
+            screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+            shown_screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+            frame_buffer: vec![0; FRAME_SIZE].into_boxed_slice(),
+            video_output: None,
+        }
+    }
+
+    /// Accepts or ignores packets from here on, depending on whether the inserted cartridge supports the SGB.
+    pub fn enable_commands(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Records the shade (0-3) the Game Boy has just drawn at a pixel of its screen.
+    pub fn capture_pixel(&mut self, x: u8, y: u8, shade: u8) {
+        self.screen[x as usize + y as usize * SCREEN_WIDTH] = shade;
+    }
+
+    /// Takes in a packet sent over the joypad lines, running its command once every packet of it has arrived.
+    pub fn receive_packet(&mut self, packet: Packet, joypad: &mut Joypad, interrupt_flags: &mut InterruptFlags) {
+        if !self.enabled {
+            return;
+        }
+        if self.command.is_empty() {
+            // The first packet gives the command's length, in packets
+            self.packets_left = packet[0] & 0b111;
+            if self.packets_left == 0 {
+                return;
+            }
+        }
+        self.command.extend_from_slice(&packet);
+        self.packets_left -= 1;
+        if self.packets_left == 0 {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command, joypad, interrupt_flags);
+        }
+    }
+
+    fn execute(&mut self, command: &[u8], joypad: &mut Joypad, interrupt_flags: &mut InterruptFlags) {
+        match command[0] >> 3 {
+            0x00 => self.set_palette_pair(0, 1, command),
+            0x01 => self.set_palette_pair(2, 3, command),
+            0x02 => self.set_palette_pair(0, 3, command),
+            0x03 => self.set_palette_pair(1, 2, command),
+            0x04 => self.attr_blk(command),
+            0x05 => self.attr_lin(command),
+            0x06 => self.attr_div(command),
+            0x07 => self.attr_chr(command),
+            0x0A => self.pal_set(command),
+            0x0B => self.request_transfer(VramTransfer::Palettes),
+            0x11 => joypad.set_sgb_player_count(match command[1] & 0b11 {
+                0b01 => 2,
+                0b11 => 4,
+                _ => 1,
+            }, interrupt_flags),
+            0x13 => self.request_transfer(match command[1] & 0b1 {
+                0 => VramTransfer::TilesLow,
+                _ => VramTransfer::TilesHigh,
+            }),
+            0x14 => self.request_transfer(VramTransfer::Border),
+            0x15 => self.request_transfer(VramTransfer::Attributes),
+            0x16 => self.attr_set(command),
+            0x17 => self.mask = match command[1] & 0b11 {
+                0 => ScreenMask::None,
+                1 => ScreenMask::Freeze,
+                2 => ScreenMask::Black,
+                _ => ScreenMask::Color0,
+            },
+            _ => {} // Commands for the SNES's sound and CPU don't affect the picture
+        }
+    }
+
+    /// PAL01, PAL23, PAL03 and PAL12: sets colors 1-3 of two palettes, along with the color 0 they all share.
+    fn set_palette_pair(&mut self, first: usize, second: usize, command: &[u8]) {
+        let color = |index: usize| u16::from_le_bytes([command[1 + index * 2], command[2 + index * 2]]);
+        self.set_shared_color(color(0));
+        for color_index in 1..4 {
+            self.palettes[first][color_index] = color(color_index);
+            self.palettes[second][color_index] = color(color_index + 3);
+        }
+    }
+
+    fn set_shared_color(&mut self, color: u16) {
+        for palette in &mut self.palettes {
+            palette[0] = color;
+        }
+    }
+
+    /// Assigns palettes to cells inside, on the edge of and outside of a number of rectangles.
+    fn attr_blk(&mut self, command: &[u8]) {
+        for data in command[2..].chunks_exact(6).take(command[1] as usize) {
+            let control = data[0] & 0b111;
+            let [inside, edge, outside] = [0, 2, 4].map(|shift| (data[1] >> shift) & 0b11);
+            // A rectangle's edge takes after whichever of its inside or outside is changed, if only one is
+            let (edge, change_edge) = match control {
+                0b001 => (inside, true),
+                0b100 => (outside, true),
+                _ => (edge, control & 0b010 != 0),
+            };
+            let [x1, y1, x2, y2] = [data[2], data[3], data[4], data[5]].map(|coord| (coord & 0x1F) as usize);
+            for (cell, palette) in self.attributes.iter_mut().enumerate() {
+                let (x, y) = (cell % CELLS_X, cell / CELLS_X);
+                let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                let on_edge = within && (x == x1 || x == x2 || y == y1 || y == y2);
+                match (within, on_edge) {
+                    (true, false) if control & 0b001 != 0 => *palette = inside,
+                    (true, true) if change_edge => *palette = edge,
+                    (false, _) if control & 0b100 != 0 => *palette = outside,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Assigns palettes to whole rows or columns of cells.
+    fn attr_lin(&mut self, command: &[u8]) {
+        for &line in command[2..].iter().take(command[1] as usize) {
+            let index = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0b11;
+            for (cell, cell_palette) in self.attributes.iter_mut().enumerate() {
+                let on_line = match line >> 7 {
+                    1 => cell / CELLS_X == index,
+                    _ => cell % CELLS_X == index,
+                };
+                if on_line {
+                    *cell_palette = palette;
+                }
+            }
+        }
+    }
+
+    /// Splits the screen in two along a row or column of cells, assigning palettes to either side and to the line itself.
+    fn attr_div(&mut self, command: &[u8]) {
+        let [after, before, on_line] = [0, 2, 4].map(|shift| (command[1] >> shift) & 0b11);
+        let horizontal = command[1] & 0b01000000 != 0;
+        let line = (command[2] & 0x1F) as usize;
+        for (cell, palette) in self.attributes.iter_mut().enumerate() {
+            let position = match horizontal {
+                true => cell / CELLS_X,
+                false => cell % CELLS_X,
+            };
+            *palette = match position.cmp(&line) {
+                Ordering::Less => before,
+                Ordering::Equal => on_line,
+                Ordering::Greater => after,
+            };
+        }
+    }
+
+    /// Assigns palettes to cells one by one, from a starting cell onwards.
+    fn attr_chr(&mut self, command: &[u8]) {
+        let (mut x, mut y) = ((command[1] as usize).min(CELLS_X - 1), (command[2] as usize).min(CELLS_Y - 1));
+        let count = (u16::from_le_bytes([command[3], command[4]]) as usize).min(CELL_COUNT);
+        let vertical = command[5] & 0b1 != 0;
+        for index in 0..count {
+            let Some(byte) = command.get(6 + index / 4) else {
+                break;
+            };
+            self.attributes[x + y * CELLS_X] = (byte >> (6 - (index % 4) * 2)) & 0b11;
+            if vertical {
+                y = (y + 1) % CELLS_Y;
+                x = (x + (y == 0) as usize) % CELLS_X;
+            } else {
+                x = (x + 1) % CELLS_X;
+                y = (y + (x == 0) as usize) % CELLS_Y;
+            }
+        }
+    }
+
+    /// Copies four of the palettes sent with PAL_TRN into palettes 0-3, optionally applying an attribute file as well.
+    fn pal_set(&mut self, command: &[u8]) {
+        for palette in 0..4 {
+            let index = u16::from_le_bytes([command[1 + palette * 2], command[2 + palette * 2]]) as usize % SYSTEM_PALETTE_COUNT;
+            self.palettes[palette] = self.system_palettes[index];
+        }
+        self.set_shared_color(self.palettes[0][0]);
+        if command[9] & 0b10000000 != 0 {
+            self.apply_attribute_file(command[9] & 0x3F);
+        }
+        if command[9] & 0b01000000 != 0 {
+            self.mask = ScreenMask::None;
+        }
+    }
+
+    /// Applies one of the attribute files sent with ATTR_TRN.
+    fn attr_set(&mut self, command: &[u8]) {
+        self.apply_attribute_file(command[1] & 0x3F);
+        if command[1] & 0b01000000 != 0 {
+            self.mask = ScreenMask::None;
+        }
+    }
+
+    fn apply_attribute_file(&mut self, file: u8) {
+        let Some(file) = self.attribute_files.chunks_exact(ATTRIBUTE_FILE_SIZE).nth(file as usize) else {
+            return;
+        };
+        for (cell, palette) in self.attributes.iter_mut().enumerate() {
+            *palette = (file[cell / 4] >> (6 - (cell % 4) * 2)) & 0b11;
+        }
+    }
+
+    fn request_transfer(&mut self, transfer: VramTransfer) {
+        self.transfer = Some(transfer);
+        self.transfer_armed = false;
+    }
+
+    /// Reads the 4 KiB a VRAM transfer copies from the screen. Each 8x8 area is read back as a 2bpp tile, left to right and then top to bottom.
+    fn read_transfer_data(&self) -> Vec<u8> {
+        (0..TRANSFER_SIZE / 16).flat_map(|tile| (0..8).flat_map(move |row| {
+            let y = (tile / CELLS_X) * 8 + row;
+            let x = (tile % CELLS_X) * 8;
+            let pixels = &self.screen[x + y * SCREEN_WIDTH..][..8];
+            [0, 1].map(|plane| pixels.iter().fold(0u8, |byte, shade| (byte << 1) | ((shade >> plane) & 1)))
+        })).collect()
+    }
+
+    fn finish_transfer(&mut self, transfer: VramTransfer) {
+        let data = self.read_transfer_data();
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        match transfer {
+            VramTransfer::Palettes => {
+                for (index, palette) in self.system_palettes.iter_mut().enumerate() {
+                    *palette = [0, 1, 2, 3].map(|color_index| read_u16((index * 4 + color_index) * 2));
+                }
+            }
+            VramTransfer::Attributes => {
+                let size = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..size]);
+            }
+            VramTransfer::TilesLow => self.border_tiles[..TRANSFER_SIZE].copy_from_slice(&data),
+            VramTransfer::TilesHigh => self.border_tiles[TRANSFER_SIZE..].copy_from_slice(&data),
+            VramTransfer::Border => {
+                for (index, entry) in self.border_map.iter_mut().enumerate() {
+                    *entry = read_u16(index * 2);
+                }
+                for (index, palette) in self.border_palettes.iter_mut().enumerate() {
+                    *palette = std::array::from_fn(|color_index| read_u16(0x800 + (index * 16 + color_index) * 2));
+                }
+            }
+        }
+    }
+
+    /// Finishes a frame once the Game Boy enters VBlank, carrying out any pending VRAM transfer, then draws and presents it.
+    pub fn end_frame(&mut self) {
+        match (self.transfer.take(), self.transfer_armed) {
+            (Some(transfer), true) => {
+                self.finish_transfer(transfer);
+                self.transfer_armed = false;
+            }
+            (transfer, _) => {
+                self.transfer_armed = transfer.is_some();
+                self.transfer = transfer;
+            }
+        }
+        if !matches!(self.mask, ScreenMask::Freeze) {
+            self.shown_screen.copy_from_slice(&self.screen);
+        }
+        self.draw_frame();
+        if let Some(output) = &mut self.video_output {
+            output.present(&self.frame_buffer);
+        }
+    }
+
+    fn draw_frame(&mut self) {
+        let backdrop = colormap::from_rgb555(self.palettes[0][0]);
+        let mut frame_buffer = std::mem::take(&mut self.frame_buffer);
+        for (index, pixel) in frame_buffer.chunks_exact_mut(4).enumerate() {
+            let (x, y) = (index % FRAME_WIDTH, index / FRAME_WIDTH);
+            let color = self.get_border_color(x, y)
+                .or_else(|| self.get_screen_color(x.wrapping_sub(SCREEN_X), y.wrapping_sub(SCREEN_Y)))
+                .unwrap_or(backdrop);
+            pixel.copy_from_slice(&color);
+        }
+        self.frame_buffer = frame_buffer;
+    }
+
+    /// Returns the color of the border at a pixel, or `None` if it's transparent there.
+    fn get_border_color(&self, x: usize, y: usize) -> Option<Color> {
+        let entry = self.border_map[x / 8 + (y / 8) * BORDER_MAP_WIDTH];
+        let tile = &self.border_tiles[(entry & 0xFF) as usize * BORDER_TILE_SIZE..][..BORDER_TILE_SIZE];
+        let (mut tile_x, mut tile_y) = (x % 8, y % 8);
+        if entry & 0x4000 != 0 {
+            tile_x = 7 - tile_x;
+        }
+        if entry & 0x8000 != 0 {
+            tile_y = 7 - tile_y;
+        }
+        // SNES tiles store bitplanes 0 and 1 interleaved by row, followed by bitplanes 2 and 3
+        let color_index = [tile[tile_y * 2], tile[tile_y * 2 + 1], tile[16 + tile_y * 2], tile[17 + tile_y * 2]].iter().enumerate()
+            .fold(0, |color_index, (plane, byte)| color_index | (((byte >> (7 - tile_x)) & 1) << plane));
+        let palette = ((entry >> 10) & 0b11) as usize;
+        (color_index != 0).then(|| colormap::from_rgb555(self.border_palettes[palette][color_index as usize]))
+    }
+
+    /// Returns the color of the Game Boy's screen at a pixel, or `None` if it's outside of the screen.
+    fn get_screen_color(&self, x: usize, y: usize) -> Option<Color> {
+        if x >= SCREEN_WIDTH || y >= SCREEN_HEIGHT {
+            return None;
+        }
+        let color = match self.mask {
+            ScreenMask::Black => 0x0000,
+            ScreenMask::Color0 => self.palettes[0][0],
+            ScreenMask::None | ScreenMask::Freeze => {
+                let palette = self.attributes[x / 8 + (y / 8) * CELLS_X] as usize;
+                self.palettes[palette][self.shown_screen[x + y * SCREEN_WIDTH] as usize]
+            }
+        };
+        Some(colormap::from_rgb555(color))
+    }
+
+    /// Sends every completed frame, border included, to the given output.
+    pub fn attach_output(&mut self, output: Box<dyn VideoOutput>) {
+        self.video_output = Some(output);
+    }
+
+    pub fn take_output(&mut self) -> Option<Box<dyn VideoOutput>> {
+        self.video_output.take()
+    }
+
+    /// Sends the most recently completed frame to the attached output again.
+    pub fn resend_frame(&mut self) {
+        if let Some(output) = &mut self.video_output {
+            output.present(&self.frame_buffer);
+        }
+    }
+
+    /// Returns the most recently completed frame as 256x224 tightly packed RGBA8 pixels.
+    pub fn get_frame(&self) -> &[u8] {
+        &self.frame_buffer
+    }
+}
+
+save_state_fields!(Sgb {
+    command,
+    packets_left,
+    palettes,
+    system_palettes,
+    attributes,
+    attribute_files,
+    mask,
+    transfer,
+    transfer_armed,
+    border_tiles,
+    border_map,
+    border_palettes,
+    screen,
+    shown_screen,
+    frame_buffer,
+});
+
+/// What MASK_EN shows in place of the Game Boy's screen, e.g. while garbage is on it during a VRAM transfer.
+enum ScreenMask {
+    None,
+    Freeze,
+    Black,
+    Color0,
+}
+
+save_state_enum!(ScreenMask { None, Freeze, Black, Color0 });
+
+/// Data copied from the screen on the next frame, as requested by one of the *_TRN commands.
+#[derive(Default)]
+enum VramTransfer {
+    #[default]
+    Palettes,
+    Attributes,
+    TilesLow,
+    TilesHigh,
+    Border,
+}
+
+save_state_enum!(VramTransfer { Palettes, Attributes, TilesLow, TilesHigh, Border });
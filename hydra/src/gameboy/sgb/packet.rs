@@ -0,0 +1,100 @@
+use crate::save_state_fields;
+
+pub type Packet = [u8; 16];
+
+/// The SGB's end of the joypad lines, which decodes the packets games send by pulsing P14 and P15,
+/// and picks which controller is read when more than one is enabled by MLT_REQ.
+pub struct SgbLink {
+    packet: Packet,
+    bit_index: usize,
+    receiving: bool,
+    /// Set once P14 and P15 have both gone high again, after which the next pulse is taken as a bit.
+    awaiting_pulse: bool,
+    completed: Option<Packet>,
+
+    player_count: u8,
+    player: u8,
+    /// Set once the next player has been selected, until P15 goes low to read their buttons.
+    player_locked: bool,
+}
+
+impl SgbLink {
+    const PACKET_BITS: usize = 128;
+
+    pub fn new() -> Self {
+        SgbLink {
+            packet: [0; 16],
+            bit_index: 0,
+            receiving: false,
+            awaiting_pulse: false,
+            completed: None,
+
+            player_count: 1,
+            player: 0,
+            player_locked: false,
+        }
+    }
+
+    /// Handles a write to P14 (bit 0) and P15 (bit 1), both active low.
+    pub fn write_lines(&mut self, lines: u8) {
+        match lines & 0b11 {
+            // Both low resets the transfer, and starts a new packet
+            0b00 => {
+                self.packet = [0; 16];
+                self.bit_index = 0;
+                self.receiving = true;
+                self.awaiting_pulse = false;
+            }
+            0b11 => {
+                self.awaiting_pulse = self.receiving;
+                if self.player_count > 1 && !self.player_locked {
+                    self.player = (self.player + 1) % self.player_count;
+                    self.player_locked = true;
+                }
+            }
+            // P15 low sends a 1, and P14 low sends a 0
+            pulse => {
+                let bit = pulse == 0b01;
+                if bit {
+                    self.player_locked = false;
+                }
+                if !self.awaiting_pulse {
+                    return;
+                }
+                self.awaiting_pulse = false;
+                if self.bit_index == Self::PACKET_BITS {
+                    // Packets end with a stop bit, which must be a 0
+                    self.receiving = false;
+                    if !bit {
+                        self.completed = Some(self.packet);
+                    }
+                } else {
+                    self.packet[self.bit_index / 8] |= (bit as u8) << (self.bit_index % 8);
+                    self.bit_index += 1;
+                }
+            }
+        }
+    }
+
+    pub fn take_packet(&mut self) -> Option<Packet> {
+        self.completed.take()
+    }
+
+    /// Returns the controller currently being read, counting from 0.
+    pub fn get_player(&self) -> u8 {
+        self.player
+    }
+
+    pub fn set_player_count(&mut self, player_count: u8) {
+        self.player_count = player_count;
+        self.player = 0;
+    }
+}
+
+impl Default for SgbLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+save_state_fields!(SgbLink { packet, bit_index, receiving, awaiting_pulse, completed, player_count, player, player_locked });
@@ -0,0 +1,170 @@
+use std::{collections::{BTreeMap, HashMap}, fs, path::Path};
+
+use crate::{common::errors::HydraIOError, gameboy::GameBoy};
+
+/// Labels loaded from a `.sym` file, as emitted by RGBDS (or no$gmb): one `bank:address label` pair per line.
+/// Banks are numbered the same way as `MemoryMap::get_bank`.
+#[derive(Default)]
+pub struct SymbolTable {
+    labels: BTreeMap<(usize, u16), String>,
+    addresses: HashMap<String, (usize, u16)>,
+}
+
+impl SymbolTable {
+    /// Parses the contents of a `.sym` file, skipping comments and any lines that aren't labels.
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = SymbolTable::default();
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let (Some(location), Some(name)) = (words.next(), words.next()) else {continue};
+            let Some((bank, address)) = location.split_once(':') else {continue};
+            let (Ok(bank), Ok(address)) = (usize::from_str_radix(bank, 16), u16::from_str_radix(address, 16)) else {continue};
+
+            // Keep the first label at each address, since it's usually the parent of any local labels after it
+            symbols.labels.entry((bank, address)).or_insert_with(|| name.to_string());
+            symbols.addresses.insert(name.to_string(), (bank, address));
+        }
+        symbols
+    }
+
+    pub fn load(path: &Path) -> Result<Self, HydraIOError> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Returns the label placed exactly at the given location, if any.
+    pub fn get_label(&self, bank: usize, address: u16) -> Option<&str> {
+        self.labels.get(&(bank, address)).map(String::as_str)
+    }
+
+    /// Returns the bank and address of a label.
+    pub fn find(&self, name: &str) -> Option<(usize, u16)> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Names a location after the closest label before it in the same bank and memory region, e.g. `Main` or `Main+$1A`.
+    pub fn locate(&self, bank: usize, address: u16) -> Option<String> {
+        let (&(label_bank, label_address), name) = self.labels.range(..=(bank, address)).next_back()?;
+        if label_bank != bank || region_of(label_address) != region_of(address) {
+            return None;
+        }
+        Some(match address - label_address {
+            0 => name.clone(),
+            offset => format!("{}+${:X}", name, offset),
+        })
+    }
+}
+
+/// Separates the address space into the regions RGBDS places sections in, so labels never extend past their own.
+const fn region_of(address: u16) -> u8 {
+    match address {
+        0x0000..=0x3FFF => 0, // ROM0
+        0x4000..=0x7FFF => 1, // ROMX
+        0x8000..=0x9FFF => 2, // VRAM
+        0xA000..=0xBFFF => 3, // SRAM
+        0xC000..=0xCFFF => 4, // WRAM0
+        0xD000..=0xDFFF => 5, // WRAMX
+        0xE000..=0xFDFF => 6, // Echo RAM
+        0xFE00..=0xFE9F => 7, // OAM
+        0xFEA0..=0xFF7F => 8, // IO
+        _ => 9, // HRAM and IE
+    }
+}
+
+impl GameBoy {
+    /// Replaces the labels used in debugging output.
+    pub fn load_symbols(&mut self, symbols: SymbolTable) {
+        self.memory.symbols = symbols;
+    }
+
+    pub fn get_symbols(&self) -> &SymbolTable {
+        &self.memory.symbols
+    }
+
+    /// Loads the `.sym` file next to the ROM, if there is one. Otherwise, any labels already loaded are cleared.
+    pub(super) fn load_symbols_alongside_rom(&mut self) {
+        let path = self.rom_path.as_ref().map(|path| path.with_extension("sym")).filter(|path| path.is_file());
+        self.memory.symbols = match path.map(|path| SymbolTable::load(&path)) {
+            Some(Ok(symbols)) => {
+                println!("Loaded {} symbols.", symbols.len());
+                symbols
+            }
+            Some(Err(e)) => {
+                println!("Unable to load symbols: {}", e);
+                SymbolTable::default()
+            }
+            None => SymbolTable::default(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMBOLS: &str = "\
+; File generated by rgblink
+00:0150 Main
+00:0150 Main.init ; A local label at the same address
+00:0158 Main.loop
+01:4000 Fade
+01:4020 Fade.step
+02:4000 Fade.step
+00:C000 wBuffer
+00:FF80 hDMA
+not a label
+00:zzzz Bad
+00:0100
+";
+
+    #[test]
+    fn parse() {
+        let symbols = SymbolTable::parse(SYMBOLS);
+        assert_eq!(symbols.len(), 7);
+        assert_eq!(symbols.find("Main"), Some((0, 0x0150)));
+        assert_eq!(symbols.find("Fade"), Some((1, 0x4000)));
+        assert_eq!(symbols.find("hDMA"), Some((0, 0xFF80)));
+        assert_eq!(symbols.find("Bad"), None);
+        assert_eq!(symbols.find("label"), None);
+
+        // Both labels at the same address can be found, but the first names it
+        assert_eq!(symbols.find("Main.init"), Some((0, 0x0150)));
+        assert_eq!(symbols.get_label(0, 0x0150), Some("Main"));
+
+        // A label defined twice refers to its last definition, but both locations keep their names
+        assert_eq!(symbols.find("Fade.step"), Some((2, 0x4000)));
+        assert_eq!(symbols.get_label(1, 0x4020), Some("Fade.step"));
+        assert_eq!(symbols.get_label(2, 0x4000), Some("Fade.step"));
+    }
+
+    #[test]
+    fn empty() {
+        let symbols = SymbolTable::parse("; Nothing but comments\n\n");
+        assert!(symbols.is_empty());
+        assert_eq!(symbols.locate(0, 0x0150), None);
+    }
+
+    #[test]
+    fn locate() {
+        let symbols = SymbolTable::parse(SYMBOLS);
+        assert_eq!(symbols.locate(0, 0x0150).as_deref(), Some("Main"));
+        assert_eq!(symbols.locate(0, 0x015A).as_deref(), Some("Main.loop+$2"));
+        assert_eq!(symbols.locate(1, 0x4031).as_deref(), Some("Fade.step+$11"));
+        assert_eq!(symbols.locate(0, 0xC123).as_deref(), Some("wBuffer+$123"));
+
+        // Nothing before the first label, in another bank, or in another region
+        assert_eq!(symbols.locate(0, 0x0100), None);
+        assert_eq!(symbols.locate(3, 0x4000), None);
+        assert_eq!(symbols.locate(0, 0x4000), None);
+        assert_eq!(symbols.locate(0, 0xD000), None);
+        assert_eq!(symbols.locate(0, 0xFF40), None);
+    }
+}
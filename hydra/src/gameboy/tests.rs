@@ -0,0 +1,77 @@
+mod golden;
+mod test_roms;
+
+use std::{env, fs, path::PathBuf};
+
+use crate::gameboy::{AGBRevision, CGBRevision, GBRevision, GameBoy, Model, SGBRevision, cpu::Register16};
+
+const DOTS_PER_SECOND: u64 = 4_194_304;
+const DOTS_PER_FRAME: u64 = 70224;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Expect {
+    Pass,
+    Fail,
+    /// The ROM targets different hardware, so it isn't run on this model at all.
+    Skip,
+    /// Nobody has checked this result yet, so it's reported but can't fail the run.
+    Unverified,
+}
+
+/// The models every test ROM is run on, in the order of each expectation table's columns.
+const MODELS: [Model; 5] = [
+    Model::GameBoy(GBRevision::DMG),
+    Model::GameBoy(GBRevision::MGB),
+    Model::SuperGameBoy(SGBRevision::SGB),
+    Model::GameBoyColor(CGBRevision::CGB),
+    Model::GameBoyAdvance(AGBRevision::AGB),
+];
+
+/// Test ROMs can't be redistributed, so they're looked up in `$HYDRA_TEST_ROMS`, or `hydra/test_roms` if that isn't set.
+fn test_rom_dir() -> PathBuf {
+    env::var_os("HYDRA_TEST_ROMS").map(PathBuf::from).unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_roms"))
+}
+
+/// Reads a ROM relative to the test ROM directory. Tests that need one are `#[ignore]`d, so a missing ROM fails the run
+/// rather than letting it pass without having checked anything.
+fn read_test_rom(path: &str) -> Vec<u8> {
+    let dir = test_rom_dir();
+    assert!(dir.is_dir(), "test ROM directory {} doesn't exist, extract the test ROMs there or set HYDRA_TEST_ROMS", dir.display());
+    let full_path = dir.join(path);
+    fs::read(&full_path).unwrap_or_else(|e| panic!("couldn't read test ROM {}: {}", full_path.display(), e))
+}
+
+/// Builds a 32 KiB ROM-only cartridge which jumps straight to `code` at $0150, for tests that don't need a real ROM.
+fn assemble(code: &[u8]) -> Vec<u8> {
+    const ENTRY: [u8; 4] = [0x00, 0xC3, 0x50, 0x01]; // NOP; JP $0150
+    const CODE_ADDRESS: usize = 0x0150;
+
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + ENTRY.len()].copy_from_slice(&ENTRY);
+    rom[CODE_ADDRESS..CODE_ADDRESS + code.len()].copy_from_slice(code);
+    rom
+}
+
+/// Boots a ROM headlessly, straight into the cartridge, with a fixed seed so runs are reproducible.
+fn boot(rom: Vec<u8>, model: Model) -> GameBoy {
+    let mut gb = GameBoy::headless(rom, model).expect("test ROM should have a valid header");
+    gb.power_on(0).expect("power cycle should succeed");
+    gb
+}
+
+/// Steps until an `LD B,B` (the software breakpoint test ROMs use to say they're done) has been executed.
+/// Returns false if it wasn't reached within `max_dots`.
+fn run_until_ld_b_b(gb: &mut GameBoy, max_dots: u64) -> bool {
+    const LD_B_B: u8 = 0x40;
+
+    let mut dots = 0;
+    while dots < max_dots {
+        let pc = gb.cpu.as_ref().unwrap().get_register16(Register16::PC);
+        let opcode = gb.memory.debug_read_u8(pc);
+        dots += gb.step_instruction();
+        if opcode == LD_B_B {
+            return true;
+        }
+    }
+    false
+}
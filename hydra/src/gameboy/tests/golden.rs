@@ -0,0 +1,315 @@
+//! Compares the screens left by visual test ROMs (acid2, mealybug-tearoom) against reference images in `golden/`.
+//! On a mismatch, the frame that was actually drawn is written to `target/<profile>/golden/<name>.actual.png`,
+//! along with `<name>.diff.png`, which dims every matching pixel and marks every mismatched one in red.
+//! Running with `HYDRA_BLESS` set writes the drawn frames as the new references instead.
+//! The test ROMs aren't included, so those frames are only checked by `cargo test -- --ignored`,
+//! but a hand-assembled scene covering the same features is always checked.
+
+use std::{env, fs::{self, File}, io::BufWriter, path::{Path, PathBuf}};
+
+use png::{ColorType, Transformations};
+
+use crate::gameboy::{CGBRevision, GBRevision, Model, ppu::{SCREEN_HEIGHT, SCREEN_WIDTH}, tests::{DOTS_PER_FRAME, DOTS_PER_SECOND, Expect::{self, *}, assemble, boot, read_test_rom, run_until_ld_b_b}};
+
+const DMG: Model = Model::GameBoy(GBRevision::DMG);
+const CGB: Model = Model::GameBoyColor(CGBRevision::CGB);
+
+/// How far to run a ROM before its screen is checked.
+enum Run {
+    Frames(u32),
+    /// Until the ROM executes `LD B,B`, giving up after this many seconds of emulated time.
+    UntilLdBB(u64),
+}
+
+enum Source {
+    /// A path relative to the test ROM directory.
+    TestRom(&'static str),
+    Assembled(fn() -> Vec<u8>),
+}
+
+struct GoldenFrame {
+    rom: Source,
+    model: Model,
+    run: Run,
+    /// The name of the reference image in `golden/`, without its extension.
+    reference: &'static str,
+    expect: Expect,
+}
+
+const GOLDEN_FRAMES: &[GoldenFrame] = &[
+    GoldenFrame { rom: Source::TestRom("dmg-acid2/dmg-acid2.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "dmg-acid2-dmg", expect: Pass },
+    GoldenFrame { rom: Source::TestRom("dmg-acid2/dmg-acid2.gb"), model: CGB, run: Run::UntilLdBB(10), reference: "dmg-acid2-cgb", expect: Pass },
+    GoldenFrame { rom: Source::TestRom("cgb-acid2/cgb-acid2.gbc"), model: CGB, run: Run::UntilLdBB(10), reference: "cgb-acid2", expect: Pass },
+    GoldenFrame { rom: Source::TestRom("which.gb/which.gb"), model: DMG, run: Run::Frames(60), reference: "which-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("which.gb/which.gb"), model: CGB, run: Run::Frames(60), reference: "which-cgb", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_bgp_change.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_bgp_change-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_bgp_change_sprites.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_bgp_change_sprites-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_lcdc_bg_en_change.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_lcdc_bg_en_change-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_lcdc_bg_map_change.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_lcdc_bg_map_change-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_lcdc_obj_en_change.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_lcdc_obj_en_change-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_lcdc_tile_sel_change.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_lcdc_tile_sel_change-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_obp0_change.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_obp0_change-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_scx_low_3_bits.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_scx_low_3_bits-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_scy_change.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_scy_change-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_window_timing.gb"), model: DMG, run: Run::UntilLdBB(10), reference: "m3_window_timing-dmg", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_bgp_change.gb"), model: CGB, run: Run::UntilLdBB(10), reference: "m3_bgp_change-cgb", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_lcdc_bg_map_change.gb"), model: CGB, run: Run::UntilLdBB(10), reference: "m3_lcdc_bg_map_change-cgb", expect: Fail },
+    GoldenFrame { rom: Source::TestRom("mealybug-tearoom-tests/ppu/m3_scx_low_3_bits.gb"), model: CGB, run: Run::UntilLdBB(10), reference: "m3_scx_low_3_bits-cgb", expect: Fail },
+];
+
+const ASSEMBLED_FRAMES: &[GoldenFrame] = &[
+    GoldenFrame { rom: Source::Assembled(scene), model: DMG, run: Run::Frames(10), reference: "scene-dmg", expect: Pass },
+    GoldenFrame { rom: Source::Assembled(scene), model: CGB, run: Run::Frames(10), reference: "scene-cgb", expect: Pass },
+];
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/gameboy/tests/golden")
+}
+
+/// Where frames that don't match are written, which is `target/<profile>/golden` beside the test binary's `deps`.
+fn output_dir() -> PathBuf {
+    let exe = env::current_exe().expect("test binary should have a path");
+    exe.parent().and_then(Path::parent).expect("test binary should be in target/<profile>/deps").join("golden")
+}
+
+/// Runs a ROM as described, then returns the last complete frame the PPU drew.
+fn capture(frame: &GoldenFrame, rom: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut gb = boot(rom, frame.model);
+    match frame.run {
+        Run::Frames(count) => {
+            for _ in 0..count {
+                gb.run_cycles(DOTS_PER_FRAME);
+            }
+        }
+        Run::UntilLdBB(timeout) => {
+            if !run_until_ld_b_b(&mut gb, timeout * DOTS_PER_SECOND) {
+                return Err("timed out before reaching LD B,B".to_string());
+            }
+        }
+    }
+    Ok(gb.memory.ppu_state.get_frame().to_vec())
+}
+
+/// Reads a PNG as RGBA, whatever format it was saved in.
+fn read_png(path: &Path) -> Option<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(File::open(path).ok()?));
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()?];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let pixels = &buf[..info.buffer_size()];
+    let rgba = match info.color_type {
+        ColorType::Rgba => pixels.to_vec(),
+        ColorType::Rgb => pixels.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF]).collect(),
+        ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]]).collect(),
+        ColorType::Grayscale => pixels.iter().flat_map(|&g| [g, g, g, 0xFF]).collect(),
+        ColorType::Indexed => return None,
+    };
+    Some((info.width, info.height, rgba))
+}
+
+fn write_png(path: &Path, rgba: &[u8]) {
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir).unwrap_or_else(|e| panic!("couldn't create {}: {}", dir.display(), e));
+    let file = File::create(path).unwrap_or_else(|e| panic!("couldn't create {}: {}", path.display(), e));
+    let mut encoder = png::Encoder::new(BufWriter::new(file), SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().and_then(|mut writer| writer.write_image_data(rgba)).unwrap_or_else(|e| panic!("couldn't write {}: {}", path.display(), e));
+}
+
+/// Returns the number of pixels whose color differs, and an image marking them in red over a dimmed copy of the reference.
+fn diff(actual: &[u8], reference: &[u8]) -> (usize, Vec<u8>) {
+    let mut mismatches = 0;
+    let image = actual.chunks_exact(4).zip(reference.chunks_exact(4)).flat_map(|(actual, reference)| {
+        if actual[..3] == reference[..3] {
+            let luma = ((reference[0] as u16 + reference[1] as u16 + reference[2] as u16) / 9) as u8;
+            [luma, luma, luma, 0xFF]
+        } else {
+            mismatches += 1;
+            [0xFF, 0x00, 0x00, 0xFF]
+        }
+    }).collect();
+    (mismatches, image)
+}
+
+/// Checks every frame in a table, and panics with a summary of every one that didn't match its expectation.
+fn check_frames(frames: &[GoldenFrame]) {
+    let bless = env::var_os("HYDRA_BLESS").is_some();
+    let mut mismatches = Vec::new();
+    for frame in frames {
+        let (rom, source) = match frame.rom {
+            Source::TestRom(path) => (read_test_rom(path), path),
+            Source::Assembled(build) => (build(), "assembled scene"),
+        };
+        let reference_path = golden_dir().join(format!("{}.png", frame.reference));
+        let name = format!("{} on {}", source, frame.model.as_str());
+        let actual = match capture(frame, rom) {
+            Ok(actual) => actual,
+            Err(reason) => {
+                if frame.expect == Pass {
+                    mismatches.push(format!("{}: {}", name, reason));
+                }
+                continue;
+            }
+        };
+
+        // The scene's references come from render_scene.py, so blessing would only replace them with the emulator's output
+        if bless && matches!(frame.rom, Source::TestRom(_)) {
+            write_png(&reference_path, &actual);
+            continue;
+        }
+        let Some((width, height, reference)) = read_png(&reference_path) else {
+            mismatches.push(format!("{}: no readable reference at {}", name, reference_path.display()));
+            continue;
+        };
+        if (width, height) != (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32) {
+            mismatches.push(format!("{}: reference is {}x{}, not {}x{}", name, width, height, SCREEN_WIDTH, SCREEN_HEIGHT));
+            continue;
+        }
+
+        let (different_pixels, diff_image) = diff(&actual, &reference);
+        if different_pixels > 0 {
+            write_png(&output_dir().join(format!("{}.actual.png", frame.reference)), &actual);
+            write_png(&output_dir().join(format!("{}.diff.png", frame.reference)), &diff_image);
+        }
+        match (frame.expect, different_pixels) {
+            (Pass, 1..) => mismatches.push(format!("{}: {} pixels differ, see {}", name, different_pixels, output_dir().join(format!("{}.diff.png", frame.reference)).display())),
+            (Fail, 0) => mismatches.push(format!("{}: now matches its reference, update its expectation", name)),
+            _ => {}
+        }
+    }
+    assert!(mismatches.is_empty(), "{} golden frame(s) didn't match expectations:\n{}", mismatches.len(), mismatches.join("\n"));
+}
+
+#[test]
+#[ignore = "needs the visual test ROMs in $HYDRA_TEST_ROMS or hydra/test_roms"]
+fn golden_frames() {
+    check_frames(GOLDEN_FRAMES);
+}
+
+#[test]
+fn assembled_frames() {
+    check_frames(ASSEMBLED_FRAMES);
+}
+
+/// Copies `len` bytes from `src` to `dest`.
+fn copy(code: &mut Vec<u8>, dest: u16, src: u16, len: u16) {
+    code.extend([0x21, dest as u8, (dest >> 8) as u8]); // LD HL,dest
+    code.extend([0x11, src as u8, (src >> 8) as u8]);   // LD DE,src
+    code.extend([0x01, len as u8, (len >> 8) as u8]);   // LD BC,len
+    code.extend([
+        0x1A,       // loop: LD A,(DE)
+        0x13,       // INC DE
+        0x22,       // LD (HL+),A
+        0x0B,       // DEC BC
+        0x78,       // LD A,B
+        0xB1,       // OR C
+        0x20, 0xF8, // JR NZ,loop
+    ]);
+}
+
+/// Writes `val` to an I/O register.
+fn poke(code: &mut Vec<u8>, register: u8, val: u8) {
+    code.extend([0x3E, val, 0xE0, register]); // LD A,val; LDH (register),A
+}
+
+/// A static screen exercising scrolling, the window, tile and object flips, palettes, the ten object per line limit,
+/// and both kinds of priority between objects and the background. The same ROM runs in DMG and CGB mode.
+/// Its references are drawn by `golden/render_scene.py`, which describes the same scene again and follows Pan Docs' rules
+/// instead of emulating the PPU, so any change here has to be made there too before rerunning it.
+fn scene() -> Vec<u8> {
+    const TILES: u16 = 0x4000;
+    const BG_MAP: u16 = 0x4100;
+    const BG_ATTRIBUTES: u16 = 0x4500;
+    const WINDOW_MAP: u16 = 0x4900;
+    const WINDOW_ATTRIBUTES: u16 = 0x4D00;
+    const OBJECTS: u16 = 0x5100;
+
+    // Tile 0 is blank, tile 1 has diagonal stripes in all four colors, tile 2 is the window's outlined square,
+    // and tile 3 is the objects' triangle, which is transparent below its diagonal.
+    let mut tiles = [0; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            let pixels = [0, (x + y) % 4, if x == 0 || y == 0 { 3 } else { 1 }, if x >= y { x % 3 + 1 } else { 0 }];
+            for (tile, color) in pixels.into_iter().enumerate() {
+                let row = tile * 16 + y * 2;
+                tiles[row] |= (color as u8 & 1) << (7 - x);
+                tiles[row + 1] |= (color as u8 >> 1) << (7 - x);
+            }
+        }
+    }
+    let bg_map: Vec<u8> = (0..1024).map(|i| ((i % 32 + i / 32) % 2 == 0) as u8).collect();
+    // On CGB, rows use palettes 0 to 3, and some columns are flipped. Row 7 has priority over objects.
+    let bg_attributes: Vec<u8> = (0..1024).map(|i| {
+        let (x, y) = (i % 32, i / 32);
+        (y % 4) as u8 | if x % 4 == 1 { 0x20 } else { 0 } | if x % 4 == 2 { 0x40 } else { 0 } | if y == 7 { 0x80 } else { 0 }
+    }).collect();
+
+    // Screen positions and attributes. Objects 2 and 3 overlap with 3 further left, so it's on top on DMG,
+    // but 2 is on top on CGB. Objects 7 to 17 share a line, so the last of them isn't drawn.
+    let mut objects = vec![(20, 30, 0x00), (24, 30, 0x00), (40, 30, 0x11), (36, 30, 0x20), (60, 50, 0x80), (80, 50, 0x40), (110, 100, 0x00)];
+    objects.extend((0..11).map(|i| (14 * i - 4, 120, 0x00)));
+    let mut oam = [0; 160];
+    for (i, (x, y, attributes)) in objects.into_iter().enumerate() {
+        oam[i * 4..i * 4 + 4].copy_from_slice(&[(y + 16) as u8, (x + 8) as u8, 3, attributes]);
+    }
+
+    let mut code = vec![
+        0xF3,             // DI
+        0xF0, 0x44,       // wait: LDH A,(LY)
+        0xFE, 0x90,       // CP 144
+        0x20, 0xFA,       // JR NZ,wait
+    ];
+    poke(&mut code, 0x40, 0x00); // LCD off
+    // The attributes go first, since on DMG they land in the maps and are overwritten.
+    poke(&mut code, 0x4F, 0x01);
+    copy(&mut code, 0x9800, BG_ATTRIBUTES, 0x400);
+    copy(&mut code, 0x9C00, WINDOW_ATTRIBUTES, 0x400);
+    poke(&mut code, 0x4F, 0x00);
+    copy(&mut code, 0x8000, TILES, tiles.len() as u16);
+    copy(&mut code, 0x9800, BG_MAP, 0x400);
+    copy(&mut code, 0x9C00, WINDOW_MAP, 0x400);
+    copy(&mut code, 0xFE00, OBJECTS, oam.len() as u16);
+    for (index_register, data_register, colors) in [(0x68, 0x69, background_color as fn(u16, u16) -> u16), (0x6A, 0x6B, object_color)] {
+        poke(&mut code, index_register, 0x80);
+        for palette in 0..8 {
+            for color in 0..4 {
+                let [lo, hi] = colors(palette, color).to_le_bytes();
+                poke(&mut code, data_register, lo);
+                poke(&mut code, data_register, hi);
+            }
+        }
+    }
+    poke(&mut code, 0x47, 0b11100100); // BGP
+    poke(&mut code, 0x48, 0b11100100); // OBP0
+    poke(&mut code, 0x49, 0b00011011); // OBP1
+    poke(&mut code, 0x43, 3);          // SCX
+    poke(&mut code, 0x42, 5);          // SCY
+    poke(&mut code, 0x4A, 96);         // WY
+    poke(&mut code, 0x4B, 104 + 7);    // WX
+    poke(&mut code, 0x40, 0b11110011); // LCD, window (map at $9C00), tiles at $8000, objects, background
+    code.extend([0x18, 0xFE]);         // JR -2
+
+    let mut rom = assemble(&code);
+    rom[0x0143] = 0x80; // Supports CGB
+    for (address, data) in [(TILES, &tiles[..]), (BG_MAP, &bg_map), (BG_ATTRIBUTES, &bg_attributes), (WINDOW_MAP, &[2; 0x400]), (WINDOW_ATTRIBUTES, &[3; 0x400]), (OBJECTS, &oam)] {
+        rom[address as usize..address as usize + data.len()].copy_from_slice(data);
+    }
+    rom
+}
+
+/// The scene's RGB555 background palettes.
+fn background_color(palette: u16, color: u16) -> u16 {
+    let (r, g, b) = (31 - color * 10, palette * 8 + color, (palette * 5 + color * 7) % 32);
+    r | g << 5 | b << 10
+}
+
+/// The scene's RGB555 object palettes.
+fn object_color(palette: u16, color: u16) -> u16 {
+    let (r, g, b) = match palette % 2 {
+        0 => (color * 10, 31 - color * 10, 16),
+        _ => (31, color * 10, 31 - color * 10),
+    };
+    r | g << 5 | b << 10
+}
@@ -0,0 +1,138 @@
+"""Draws the references for the hand-assembled scene in golden.rs (scene-dmg.png and scene-cgb.png).
+
+The scene is described a second time here, and drawn by following Pan Docs' rendering rules directly rather than by
+emulating the PPU, so the references don't depend on the emulator they check. Anything changed in `scene()` has to be
+changed here too. Only the standard library is needed:
+
+    python3 render_scene.py [output directory]
+
+The references are written next to this script unless another directory is given.
+"""
+import os
+import struct
+import sys
+import zlib
+
+SCREEN_WIDTH, SCREEN_HEIGHT = 160, 144
+
+
+def tile_color(tile, x, y):
+    """Tile 0 is blank, 1 has diagonal stripes, 2 is the window's outlined square and 3 is the objects' triangle."""
+    return [0, (x + y) % 4, 3 if x == 0 or y == 0 else 1, (x % 3 + 1) if x >= y else 0][tile]
+
+
+def bg_map(cx, cy):
+    return 1 if (cx + cy) % 2 == 0 else 0
+
+
+def bg_attributes(cx, cy):
+    attributes = cy % 4
+    if cx % 4 == 1:
+        attributes |= 0x20
+    if cx % 4 == 2:
+        attributes |= 0x40
+    if cy == 7:
+        attributes |= 0x80
+    return attributes
+
+
+WINDOW_TILE, WINDOW_ATTRIBUTES = 2, 3
+
+# (X, Y, attributes) on screen, in OAM order. Eleven objects share line 120, so the last is dropped by the ten object limit.
+OBJECTS = [(20, 30, 0x00), (24, 30, 0x00), (40, 30, 0x11), (36, 30, 0x20), (60, 50, 0x80), (80, 50, 0x40), (110, 100, 0x00)]
+OBJECTS += [(14 * i - 4, 120, 0x00) for i in range(11)]
+OBJECTS += [(-8, -16, 0x00)] * (40 - len(OBJECTS))
+
+
+def bg_rgb(palette, color):
+    r, g, b = 31 - color * 10, palette * 8 + color, (palette * 5 + color * 7) % 32
+    return (r << 3, g << 3, b << 3)
+
+
+def obj_rgb(palette, color):
+    if palette % 2 == 0:
+        r, g, b = color * 10, 31 - color * 10, 16
+    else:
+        r, g, b = 31, color * 10, 31 - color * 10
+    return (r << 3, g << 3, b << 3)
+
+
+BGP, OBP0, OBP1 = 0b11100100, 0b11100100, 0b00011011
+SCX, SCY, WY, WX = 3, 5, 96, 104 + 7
+GRAYS = [255, 170, 85, 0]
+
+
+def shade(palette, color):
+    return (palette >> (color * 2)) & 3
+
+
+def render(cgb):
+    image = []
+    window_line = 0
+    for ly in range(SCREEN_HEIGHT):
+        # OAM scan picks the first ten objects on the line, in OAM order
+        selected = [i for i, (x, y, _) in enumerate(OBJECTS) if y <= ly < y + 8][:10]
+        window_visible = ly >= WY and WX - 7 < SCREEN_WIDTH
+        row = []
+        for lx in range(SCREEN_WIDTH):
+            if window_visible and lx >= WX - 7:
+                tile, attributes = WINDOW_TILE, WINDOW_ATTRIBUTES
+                tx, ty = (lx - (WX - 7)) % 8, window_line % 8
+            else:
+                bx, by = (lx + SCX) % 256, (ly + SCY) % 256
+                tile, attributes = bg_map(bx // 8, by // 8), bg_attributes(bx // 8, by // 8)
+                tx, ty = bx % 8, by % 8
+            if not cgb:
+                attributes = 0
+            if attributes & 0x20:
+                tx = 7 - tx
+            if attributes & 0x40:
+                ty = 7 - ty
+            bg_color = tile_color(tile, tx, ty)
+
+            # In DMG mode the object with the lowest X wins, in CGB mode the first in OAM; transparent pixels never do
+            candidates = [i for i in selected if OBJECTS[i][0] <= lx < OBJECTS[i][0] + 8]
+            if not cgb:
+                candidates.sort(key=lambda i: (OBJECTS[i][0], i))
+            obj = None
+            for i in candidates:
+                x, y, obj_attributes = OBJECTS[i]
+                ox, oy = lx - x, ly - y
+                if obj_attributes & 0x20:
+                    ox = 7 - ox
+                if obj_attributes & 0x40:
+                    oy = 7 - oy
+                color = tile_color(3, ox, oy)
+                if color != 0:
+                    obj = (color, obj_attributes)
+                    break
+
+            if cgb:
+                show_obj = obj is not None and (bg_color == 0 or not (attributes & 0x80 or obj[1] & 0x80))
+                rgb = obj_rgb(obj[1] & 7, obj[0]) if show_obj else bg_rgb(attributes & 7, bg_color)
+            else:
+                show_obj = obj is not None and (bg_color == 0 or not obj[1] & 0x80)
+                gray = GRAYS[shade(OBP1 if obj[1] & 0x10 else OBP0, obj[0])] if show_obj else GRAYS[shade(BGP, bg_color)]
+                rgb = (gray, gray, gray)
+            row.append(rgb)
+        if window_visible:
+            window_line += 1
+        image.append(row)
+    return image
+
+
+def write_png(path, image):
+    raw = b''.join(b'\0' + bytes(v for pixel in row for v in (*pixel, 255)) for row in image)
+
+    def chunk(kind, data):
+        return struct.pack('>I', len(data)) + kind + data + struct.pack('>I', zlib.crc32(kind + data) & 0xFFFFFFFF)
+
+    header = struct.pack('>IIBBBBB', SCREEN_WIDTH, SCREEN_HEIGHT, 8, 6, 0, 0, 0)
+    with open(path, 'wb') as file:
+        file.write(b'\x89PNG\r\n\x1a\n' + chunk(b'IHDR', header) + chunk(b'IDAT', zlib.compress(raw, 9)) + chunk(b'IEND', b''))
+
+
+if __name__ == '__main__':
+    output = sys.argv[1] if len(sys.argv) > 1 else os.path.dirname(os.path.abspath(__file__))
+    write_png(os.path.join(output, 'scene-dmg.png'), render(False))
+    write_png(os.path.join(output, 'scene-cgb.png'), render(True))
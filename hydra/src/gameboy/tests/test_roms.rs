@@ -0,0 +1,270 @@
+//! Runs blargg's and mooneye's test ROMs on every model and compares the results against the tables below.
+//! The tables weren't produced by running the ROMs, so every result that applies to a model starts out `Unverified`: it's
+//! reported at the end of the run, as a row ready to paste back into its table, but can't fail it. Once a row holds real
+//! `Pass` and `Fail` results, any change fails the run, whether it's a regression or a test that started passing.
+//! The ROMs aren't included, so those runs are ignored by default; `cargo test -- --ignored` runs them.
+//! The hand-assembled ROMs at the bottom check that both ways of reporting a result are read correctly, without any ROMs at all.
+
+use crate::gameboy::{GameBoy, cpu::Register8, tests::{DOTS_PER_FRAME, DOTS_PER_SECOND, Expect::{self, *}, MODELS, assemble, boot, read_test_rom, run_until_ld_b_b}};
+
+/// One row per ROM, with the expected result on DMG, MGB, SGB, CGB and AGB.
+type Expectations = &'static [(&'static str, [Expect; 5])];
+
+const EVERY_MODEL: [Expect; 5] = [Unverified; 5];
+const MONOCHROME: [Expect; 5] = [Unverified, Unverified, Unverified, Skip, Skip];
+
+const BLARGG: Expectations = &[
+    ("blargg/cpu_instrs/individual/01-special.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/02-interrupts.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/03-op sp,hl.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/04-op r,imm.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/05-op rp.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/06-ld r,r.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/07-jr,jp,call,ret,rst.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/08-misc instrs.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/09-op r,r.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/10-bit ops.gb", EVERY_MODEL),
+    ("blargg/cpu_instrs/individual/11-op a,(hl).gb", EVERY_MODEL),
+    ("blargg/instr_timing/instr_timing.gb", EVERY_MODEL),
+    ("blargg/mem_timing/individual/01-read_timing.gb", EVERY_MODEL),
+    ("blargg/mem_timing/individual/02-write_timing.gb", EVERY_MODEL),
+    ("blargg/mem_timing/individual/03-modify_timing.gb", EVERY_MODEL),
+];
+
+const MOONEYE: Expectations = &[
+    ("mooneye/acceptance/add_sp_e_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/call_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/call_cc_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/di_timing-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/div_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ei_sequence.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ei_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/halt_ime0_ei.gb", EVERY_MODEL),
+    ("mooneye/acceptance/halt_ime0_nointr_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/halt_ime1_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/if_ie_registers.gb", EVERY_MODEL),
+    ("mooneye/acceptance/intr_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/jp_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ld_hl_sp_e_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/oam_dma_restart.gb", EVERY_MODEL),
+    ("mooneye/acceptance/oam_dma_start.gb", EVERY_MODEL),
+    ("mooneye/acceptance/oam_dma_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/pop_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/push_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/rapid_di_ei.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ret_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ret_cc_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/reti_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/reti_intr_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/rst_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/boot_regs-dmgABC.gb", [Unverified, Skip, Skip, Skip, Skip]),
+    ("mooneye/acceptance/boot_regs-mgb.gb", [Skip, Unverified, Skip, Skip, Skip]),
+    ("mooneye/acceptance/boot_regs-sgb.gb", [Skip, Skip, Unverified, Skip, Skip]),
+    ("mooneye/misc/boot_regs-cgb.gb", [Skip, Skip, Skip, Unverified, Skip]),
+    ("mooneye/misc/boot_regs-A.gb", [Skip, Skip, Skip, Skip, Unverified]),
+    ("mooneye/acceptance/bits/mem_oam.gb", EVERY_MODEL),
+    ("mooneye/acceptance/bits/reg_f.gb", EVERY_MODEL),
+    ("mooneye/acceptance/bits/unused_hwio-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/instr/daa.gb", EVERY_MODEL),
+    ("mooneye/acceptance/interrupts/ie_push.gb", EVERY_MODEL),
+    ("mooneye/acceptance/oam_dma/basic.gb", EVERY_MODEL),
+    ("mooneye/acceptance/oam_dma/reg_read.gb", EVERY_MODEL),
+    ("mooneye/acceptance/oam_dma/sources-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/ppu/hblank_ly_scx_timing-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/ppu/intr_1_2_timing-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/ppu/intr_2_0_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ppu/intr_2_mode0_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ppu/intr_2_mode0_timing_sprites.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ppu/intr_2_mode3_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ppu/intr_2_oam_ok_timing.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ppu/lcdon_timing-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/ppu/lcdon_write_timing-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/ppu/stat_irq_blocking.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ppu/stat_lyc_onoff.gb", EVERY_MODEL),
+    ("mooneye/acceptance/ppu/vblank_stat_intr-GS.gb", MONOCHROME),
+    ("mooneye/acceptance/serial/boot_sclk_align-dmgABCmgb.gb", [Unverified, Unverified, Skip, Skip, Skip]),
+    ("mooneye/acceptance/timer/div_write.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/rapid_toggle.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim00.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim00_div_trigger.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim01.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim01_div_trigger.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim10.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim10_div_trigger.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim11.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tim11_div_trigger.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tima_reload.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tima_write_reloading.gb", EVERY_MODEL),
+    ("mooneye/acceptance/timer/tma_write_reloading.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/bits_bank1.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/bits_bank2.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/bits_mode.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/bits_ramg.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/ram_64kb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/ram_256kb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/rom_512kb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/rom_1Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/rom_2Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/rom_4Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/rom_8Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc1/rom_16Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc2/bits_ramg.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc2/bits_romb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc2/bits_unused.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc2/ram.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc2/rom_512kb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc2/rom_1Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc2/rom_2Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_512kb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_1Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_2Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_4Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_8Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_16Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_32Mb.gb", EVERY_MODEL),
+    ("mooneye/emulator-only/mbc5/rom_64Mb.gb", EVERY_MODEL),
+];
+
+/// How long a ROM gets to report a result before it's counted as a failure, in seconds of emulated time.
+const BLARGG_TIMEOUT: u64 = 60;
+const MOONEYE_TIMEOUT: u64 = 10;
+
+#[derive(Debug)]
+enum Outcome {
+    Passed,
+    Failed(String),
+}
+
+/// Blargg's ROMs print their results over the link cable, ending in either "Passed" or "Failed".
+fn run_blargg(mut gb: GameBoy) -> Outcome {
+    let mut output = Vec::new();
+    for _ in 0..BLARGG_TIMEOUT * DOTS_PER_SECOND / DOTS_PER_FRAME {
+        gb.run_cycles(DOTS_PER_FRAME);
+        output.extend(gb.take_serial_output());
+        let text = String::from_utf8_lossy(&output);
+        if text.contains("Passed") {
+            return Outcome::Passed;
+        } else if text.contains("Failed") {
+            return Outcome::Failed(text.trim().to_string());
+        }
+    }
+    Outcome::Failed(format!("timed out with output {:?}", String::from_utf8_lossy(&output)))
+}
+
+/// Mooneye's ROMs signal completion with `LD B,B`, leaving the Fibonacci numbers in B through L if they passed.
+fn run_mooneye(mut gb: GameBoy) -> Outcome {
+    const PASS_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+    const FAIL_SIGNATURE: [u8; 6] = [0x42; 6];
+
+    if !run_until_ld_b_b(&mut gb, MOONEYE_TIMEOUT * DOTS_PER_SECOND) {
+        return Outcome::Failed("timed out".to_string());
+    }
+    let cpu = gb.cpu.as_ref().unwrap();
+    let registers = [Register8::B, Register8::C, Register8::D, Register8::E, Register8::H, Register8::L].map(|register| cpu.get_register8(register));
+    match registers {
+        PASS_SIGNATURE => Outcome::Passed,
+        FAIL_SIGNATURE => Outcome::Failed("reported failure".to_string()),
+        _ => Outcome::Failed(format!("finished with unexpected registers {:02X?}", registers)),
+    }
+}
+
+/// Runs every ROM in a table on every model it applies to, and panics with a summary of every result that didn't match,
+/// followed by each mismatched row as it should now read.
+fn run_table(table: Expectations, run: fn(GameBoy) -> Outcome) {
+    let mut mismatches = Vec::new();
+    let mut updated_rows = Vec::new();
+    let mut unverified_rows = Vec::new();
+    for (path, expectations) in table {
+        let rom = read_test_rom(path);
+        let mut results = *expectations;
+        for ((model, expected), result) in MODELS.iter().zip(expectations).zip(&mut results) {
+            if *expected == Skip {
+                continue;
+            }
+            let outcome = run(boot(rom.clone(), *model));
+            *result = if matches!(outcome, Outcome::Passed) { Pass } else { Fail };
+            match (expected, outcome) {
+                (Pass, Outcome::Failed(reason)) => mismatches.push(format!("{} on {}: regressed ({})", path, model.as_str(), reason)),
+                (Fail, Outcome::Passed) => mismatches.push(format!("{} on {}: now passes, update its expectation", path, model.as_str())),
+                _ => {}
+            }
+        }
+        let row = format!("    ({:?}, {:?}),", path, results);
+        if expectations.contains(&Unverified) {
+            unverified_rows.push(row);
+        } else if results != *expectations {
+            updated_rows.push(row);
+        }
+    }
+    if !unverified_rows.is_empty() {
+        println!("{} row(s) have unverified results. Once checked, they should read:\n{}", unverified_rows.len(), unverified_rows.join("\n"));
+    }
+    assert!(mismatches.is_empty(), "{} test ROM result(s) didn't match expectations:\n{}\n\nUpdated rows:\n{}", mismatches.len(), mismatches.join("\n"), updated_rows.join("\n"));
+}
+
+#[test]
+#[ignore = "needs blargg's test ROMs in $HYDRA_TEST_ROMS or hydra/test_roms"]
+fn blargg() {
+    run_table(BLARGG, run_blargg);
+}
+
+#[test]
+#[ignore = "needs mooneye's test ROMs in $HYDRA_TEST_ROMS or hydra/test_roms"]
+fn mooneye() {
+    run_table(MOONEYE, run_mooneye);
+}
+
+/// Prints a message over the link cable the way blargg's ROMs do, waiting for each byte to finish sending, then halts.
+fn serial_printer(message: &str) -> Vec<u8> {
+    const MESSAGE_ADDRESS: u16 = 0x0166;
+
+    let mut code = vec![
+        0x21, MESSAGE_ADDRESS as u8, (MESSAGE_ADDRESS >> 8) as u8, // LD HL,message
+        0x2A,       // next: LD A,(HL+)
+        0xB7,       // OR A
+        0x28, 0x0D, // JR Z,done
+        0xE0, 0x01, // LDH (SB),A
+        0x3E, 0x81, // LD A,$81
+        0xE0, 0x02, // LDH (SC),A
+        0xF0, 0x02, // wait: LDH A,(SC)
+        0x87,       // ADD A,A
+        0x38, 0xFB, // JR C,wait
+        0x18, 0xEF, // JR next
+        0x18, 0xFE, // done: JR done
+    ];
+    code.extend(message.bytes());
+    code.push(0x00);
+    code
+}
+
+/// Leaves the given values in B through L, then signals completion with `LD B,B` the way mooneye's ROMs do.
+fn register_reporter(registers: [u8; 6]) -> Vec<u8> {
+    let [b, c, d, e, h, l] = registers;
+    vec![
+        0x06, b, 0x0E, c, 0x16, d, 0x1E, e, 0x26, h, 0x2E, l,
+        0x40,       // LD B,B
+        0x18, 0xFE, // JR -2
+    ]
+}
+
+#[test]
+fn hand_assembled_blargg() {
+    for model in MODELS {
+        let passed = run_blargg(boot(assemble(&serial_printer("cpu_instrs\n\nPassed\n")), model));
+        assert!(matches!(passed, Outcome::Passed), "{} read {:?}", model.as_str(), passed);
+        let failed = run_blargg(boot(assemble(&serial_printer("01-special\n\nFailed #2\n")), model));
+        assert!(matches!(failed, Outcome::Failed(ref text) if text.contains("Failed #2")), "{} read {:?}", model.as_str(), failed);
+    }
+}
+
+#[test]
+fn hand_assembled_mooneye() {
+    for model in MODELS {
+        let passed = run_mooneye(boot(assemble(&register_reporter([3, 5, 8, 13, 21, 34])), model));
+        assert!(matches!(passed, Outcome::Passed), "{} read {:?}", model.as_str(), passed);
+        let failed = run_mooneye(boot(assemble(&register_reporter([0x42; 6])), model));
+        assert!(matches!(failed, Outcome::Failed(ref reason) if reason == "reported failure"), "{} read {:?}", model.as_str(), failed);
+        let garbled = run_mooneye(boot(assemble(&register_reporter([1, 2, 3, 4, 5, 6])), model));
+        assert!(matches!(garbled, Outcome::Failed(ref reason) if reason.contains("unexpected registers")), "{} read {:?}", model.as_str(), garbled);
+    }
+}
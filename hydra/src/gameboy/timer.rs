@@ -1,10 +1,12 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::{Cell, RefCell}, rc::Rc};
 
-use crate::{common::{errors::HydraIOError, timing::ModuloCounter}, deserialize, gameboy::{GBRevision, GbMode, Model, apu::{Apu, state::ApuState}, interrupt::{Interrupt, InterruptFlags}, memory::{MemoryMap, MemoryMapped}, ppu::state::PpuState}, serialize};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{common::{errors::HydraIOError, timing::ModuloCounter}, deserialize, gameboy::{GBRevision, GbMode, Model, apu::{Apu, state::ApuState}, interrupt::{Interrupt, InterruptFlags}, memory::{MemoryMap, MemoryMapped}, ppu::state::PpuState}, save_state_enum, save_state_fields, serialize};
 
 pub struct MasterTimer {
     model: Rc<Model>,
-    mode: Rc<GbMode>,
+    mode: Rc<Cell<GbMode>>,
 
     master_dot_counter: ModuloCounter<u32>,
     machine_cycle_timer: ModuloCounter<u8>,
@@ -17,19 +19,24 @@ pub struct MasterTimer {
 
     system_speed: SystemSpeed,
     speed_switch_queued: bool,
+
+    /// Seeds the outcome of the CGB's indeterminate TIMA glitch. Kept as plain state (rather than a live RNG) so that it's saved with the rest of the timer.
+    glitch_seed: u64,
 }
 
 impl MasterTimer {
     const DOTS_PER_FRAME: u32 = 70224;
 
-    pub fn new(model: Rc<Model>, mode: Rc<GbMode>) -> Self {
+    /// Builds the timer as the boot ROM leaves it, or freshly reset if a boot ROM is going to run.
+    pub fn new(model: Rc<Model>, mode: Rc<Cell<GbMode>>, boot_rom: bool, rng: &mut StdRng) -> Self {
         MasterTimer { 
             master_dot_counter: ModuloCounter::new(0, Self::DOTS_PER_FRAME),
             machine_cycle_timer: ModuloCounter::new(0, 4),
             div_full: match *model { 
+                _ if boot_rom => 0x00,
                 Model::GameBoy(GBRevision::DMG0) => 0x18,
                 Model::GameBoy(_) => 0xAB,
-                Model::SuperGameBoy(_) | Model::GameBoyColor(_) | Model::GameBoyAdvance(_) => rand::random(), // TODO: Number is supposed to be based on boot rom cycles,
+                Model::SuperGameBoy(_) | Model::GameBoyColor(_) | Model::GameBoyAdvance(_) => rng.random(), // TODO: Number is supposed to be based on boot rom cycles,
             } << 6,
             model,
             mode,
@@ -42,6 +49,8 @@ impl MasterTimer {
 
             system_speed: SystemSpeed::Standard,
             speed_switch_queued: false,
+
+            glitch_seed: rng.random(),
         }
     }
     
@@ -164,6 +173,12 @@ impl MasterTimer {
         )
     }
     
+    fn glitch_coin_flip(&mut self) -> bool {
+        let mut rng = StdRng::seed_from_u64(self.glitch_seed);
+        self.glitch_seed = rng.random();
+        rng.random_bool(0.5)
+    }
+
     pub fn write_tac(&mut self, val: u8) {
         deserialize!(val;
             [2] as bool =>> tima_enabled;
@@ -182,7 +197,7 @@ impl MasterTimer {
             // i.e. the selected went from set => unset, while TIMA was enabled
             // or, if TIMA was freshly enabled, the result is indeterminant
             false => old_div_bit_high && new_div_bit_low
-                  && (self.tima_enabled || (tima_enabled && rand::random_bool(0.5)))
+                  && (self.tima_enabled || (tima_enabled && self.glitch_coin_flip()))
         } {
             self.tick_tima();
         }
@@ -213,7 +228,7 @@ impl MasterTimer {
             0xFF05 => Ok(self.read_tima()),
             0xFF06 => Ok(self.read_tma()),
             0xFF07 => Ok(self.read_tac()),
-            0xFF4D if matches!(*self.mode, GbMode::CGB) => Ok(self.read_key1()),
+            0xFF4D if matches!(self.mode.get(), GbMode::CGB) => Ok(self.read_key1()),
             _ => Err(HydraIOError::OpenBusAccess),
         }
     }
@@ -224,18 +239,34 @@ impl MasterTimer {
             0xFF05 => Ok(self.write_tima(val)),
             0xFF06 => Ok(self.write_tma(val)),
             0xFF07 => Ok(self.write_tac(val)),
-            0xFF4D if matches!(*self.mode, GbMode::CGB) => Ok(self.write_key1(val)),
+            0xFF4D if matches!(self.mode.get(), GbMode::CGB) => Ok(self.write_key1(val)),
             _ => Err(HydraIOError::OpenBusAccess),
         }
     }
 }
 
+save_state_fields!(MasterTimer {
+    master_dot_counter,
+    machine_cycle_timer,
+    div_full,
+    tima,
+    tma,
+    tima_speed,
+    tima_enabled,
+    timer_interrupt_status,
+    system_speed,
+    speed_switch_queued,
+    glitch_seed,
+});
+
 enum InterruptStatus {
     Idle,
     Queued,
     Requesting
 }
 
+save_state_enum!(InterruptStatus { Idle, Queued, Requesting });
+
 #[repr(u16)]
 #[derive(Copy, Clone)]
 enum SystemSpeed {
@@ -252,6 +283,8 @@ impl SystemSpeed {
     }
 }
 
+save_state_enum!(SystemSpeed { Standard, CgbDouble });
+
 #[repr(u16)]
 #[derive(Copy, Clone)]
 enum TimaSpeed {
@@ -274,4 +307,6 @@ impl TimaSpeed {
             Self::Slow => 0b11,
         }
     }
-}
\ No newline at end of file
+}
+
+save_state_enum!(TimaSpeed { Fastest, Fast, Slow, Slowest });
\ No newline at end of file
@@ -0,0 +1,117 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+
+use crate::{common::errors::HydraIOError, gameboy::{GameBoy, cpu::{Cpu, Register8, Register16}, memory::MemoryMap}};
+
+/// Logs the CPU's state before every instruction, one line each, in the format used by Game Boy Doctor.
+/// Output is buffered, and only written out in large chunks.
+pub struct TraceLogger {
+    writer: BufWriter<Box<dyn Write>>,
+    pc_range: Option<(u16, u16)>,
+    bank: Option<usize>,
+    show_labels: bool,
+}
+
+impl TraceLogger {
+    const BUFFER_SIZE: usize = 1 << 20;
+
+    pub fn new(writer: Box<dyn Write>) -> Self {
+        TraceLogger { writer: BufWriter::with_capacity(Self::BUFFER_SIZE, writer), pc_range: None, bank: None, show_labels: false }
+    }
+
+    pub fn create(path: &Path) -> Result<Self, HydraIOError> {
+        Ok(Self::new(Box::new(File::create(path)?)))
+    }
+
+    /// Only logs instructions whose address lies within `start..=end`. `None` logs instructions anywhere.
+    pub fn set_pc_range(&mut self, range: Option<(u16, u16)>) {
+        self.pc_range = range;
+    }
+
+    /// Only logs instructions run from the given bank (of ROM, VRAM or WRAM). `None` logs instructions from any bank.
+    pub fn set_bank(&mut self, bank: Option<usize>) {
+        self.bank = bank;
+    }
+
+    /// Appends the label at each logged instruction, if there is one. Off by default, since it breaks Game Boy Doctor's line format.
+    pub fn set_show_labels(&mut self, show_labels: bool) {
+        self.show_labels = show_labels;
+    }
+
+    pub(super) fn log(&mut self, cpu: &Cpu, memory: &MemoryMap) -> io::Result<()> {
+        let pc = cpu.get_register16(Register16::PC);
+        if self.pc_range.is_some_and(|(start, end)| !(start..=end).contains(&pc)) || self.bank.is_some_and(|bank| memory.get_bank(pc) != bank) {
+            return Ok(());
+        }
+        write!(
+            self.writer,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            cpu.get_register8(Register8::A),
+            cpu.get_register8(Register8::F),
+            cpu.get_register8(Register8::B),
+            cpu.get_register8(Register8::C),
+            cpu.get_register8(Register8::D),
+            cpu.get_register8(Register8::E),
+            cpu.get_register8(Register8::H),
+            cpu.get_register8(Register8::L),
+            cpu.get_register16(Register16::SP),
+            pc,
+            memory.debug_read_u8(pc),
+            memory.debug_read_u8(pc.wrapping_add(1)),
+            memory.debug_read_u8(pc.wrapping_add(2)),
+            memory.debug_read_u8(pc.wrapping_add(3)),
+        )?;
+        if self.show_labels && let Some(label) = memory.symbols.get_label(memory.get_bank(pc), pc) {
+            write!(self.writer, " ; {}", label)?;
+        }
+        writeln!(self.writer)
+    }
+
+    /// Writes out anything left in the buffer.
+    pub fn finish(mut self) -> Result<(), HydraIOError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl GameBoy {
+    /// Starts logging every instruction, replacing (and finishing) any trace already running.
+    pub fn start_trace(&mut self, logger: TraceLogger) -> Result<(), HydraIOError> {
+        self.stop_trace()?;
+        self.trace = Some(logger);
+        Ok(())
+    }
+
+    pub fn stop_trace(&mut self) -> Result<(), HydraIOError> {
+        match self.trace.take() {
+            Some(logger) => logger.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_trace(&mut self) -> Option<&mut TraceLogger> {
+        self.trace.as_mut()
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    /// Returns the path of the trace log kept alongside the ROM, if the ROM was loaded from a file.
+    pub fn get_trace_path(&self) -> Option<PathBuf> {
+        self.rom_path.as_ref().map(|path| path.with_extension("log"))
+    }
+
+    /// Starts tracing to the log alongside the ROM, or stops tracing if a trace is already running.
+    pub(super) fn toggle_trace(&mut self) {
+        let result = match (self.is_tracing(), self.get_trace_path()) {
+            (true, _) => self.stop_trace().map(|()| "Stopped tracing.".to_string()),
+            (false, Some(path)) => TraceLogger::create(&path)
+                .and_then(|logger| self.start_trace(logger))
+                .map(|()| format!("Tracing to {}.", path.display())),
+            (false, None) => Ok("No ROM file to trace alongside.".to_string()),
+        };
+        match result {
+            Ok(message) => println!("{}", message),
+            Err(e) => println!("Unable to toggle tracing: {}", e),
+        }
+    }
+}
@@ -1,8 +1,10 @@
-use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+use std::{fs::File, io::BufReader, path::Path, sync::{Arc, RwLock}};
 
 use png::Transformations;
 use wgpu::*;
-use winit::{dpi::PhysicalSize, window::Window};
+use winit::{dpi::PhysicalSize, event_loop::EventLoopProxy, window::Window};
+
+use crate::{common::output::VideoOutput, gameboy::{Viewer, ViewerImage, ViewerOutput}, window::UserEvent};
 
 pub struct Graphics {
     window: Arc<Window>,
@@ -335,3 +337,41 @@ impl Graphics {
         self.queue.clone()
     }
 }
+
+/// Presents emulator frames on the window's screen texture.
+pub struct Viewport {
+    graphics: Arc<RwLock<Graphics>>,
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+impl Viewport {
+    pub fn new(graphics: Arc<RwLock<Graphics>>, proxy: EventLoopProxy<UserEvent>) -> Self {
+        Viewport { graphics, proxy }
+    }
+}
+
+impl VideoOutput for Viewport {
+    fn present(&mut self, frame: &[u8]) {
+        // Send redraw request through event loop proxy
+        self.graphics.read().unwrap().update_screen_texture(frame);
+        self.proxy.send_event(UserEvent::RedrawRequest).expect("Unable to render graphics: Main event loop closed unexpectedly");
+    }
+}
+
+/// Forwards rendered debug views to the event loop, to be shown in their own windows.
+pub struct ViewerProxy {
+    proxy: EventLoopProxy<UserEvent>,
+}
+
+impl ViewerProxy {
+    pub fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
+        ViewerProxy { proxy }
+    }
+}
+
+impl ViewerOutput for ViewerProxy {
+    fn present(&mut self, viewer: Viewer, image: ViewerImage) {
+        // Views are only a debugging aid, so one lost while the event loop is closing doesn't matter
+        let _ = self.proxy.send_event(UserEvent::ViewerFrame(viewer, image));
+    }
+}
@@ -1,18 +1,61 @@
+#[cfg(feature = "frontend")]
 mod audio;
 mod common;
 mod config;
 mod gameboy;
+#[cfg(feature = "frontend")]
 mod graphics;
+#[cfg(feature = "frontend")]
 mod input;
+#[cfg(feature = "frontend")]
 mod ui;
+#[cfg(feature = "frontend")]
 mod window;
 
+#[cfg(feature = "frontend")]
 use muda::MenuEvent;
+#[cfg(feature = "frontend")]
 use winit::event_loop::{ControlFlow, EventLoop};
 
+use crate::common::errors::HydraIOError;
+#[cfg(feature = "frontend")]
 use crate::window::{HydraApp, UserEvent};
 
+/// Runs a subcommand given on the command line (e.g. `Hydra info game.gb`), returning an exit code if there was one to run.
+fn run_subcommand() -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("info") => {
+            let Some(path) = args.get(1) else {
+                eprintln!("Usage: Hydra info <rom>");
+                return Some(2);
+            };
+            let header = std::fs::read(path).map_err(HydraIOError::from).and_then(|rom| gameboy::CartridgeHeader::parse(&rom));
+            match header {
+                Ok(header) => {
+                    println!("{}", header);
+                    let problems = header.get_problems();
+                    for problem in &problems {
+                        println!("Problem: {}", problem);
+                    }
+                    Some(if problems.is_empty() {0} else {1})
+                },
+                Err(e) => {
+                    eprintln!("{}", e);
+                    Some(1)
+                },
+            }
+        },
+        _ => None,
+    }
+}
+
+#[cfg(feature = "frontend")]
 pub fn main() {
+    if let Some(code) = run_subcommand() {
+        std::process::exit(code);
+    }
+
     let event_loop = EventLoop::<UserEvent>::with_user_event().build().unwrap();
 
     // Forward muda::MenuEvent to winit::EventLoop
@@ -22,6 +65,16 @@ pub fn main() {
         menu_proxy.send_event(UserEvent::MenuEvent(event));
     }));
 
+    // Forward lines typed into the terminal to the debug console
+    let console_proxy = proxy.clone();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            if console_proxy.send_event(UserEvent::DebugCommand(line)).is_err() {
+                break; // Event loop has closed
+            }
+        }
+    });
+
     // ControlFlow::Poll continuously runs the event loop, even if the OS hasn't
     // dispatched any events. This is ideal for games and similar applications.
     event_loop.set_control_flow(ControlFlow::Poll);
@@ -29,3 +82,13 @@ pub fn main() {
     let mut app = HydraApp::new(proxy);
     event_loop.run_app(&mut app);
 }
+
+#[cfg(not(feature = "frontend"))]
+pub fn main() {
+    if let Some(code) = run_subcommand() {
+        std::process::exit(code);
+    }
+
+    eprintln!("Hydra was built without the `frontend` feature, so no window can be opened.");
+    std::process::exit(1);
+}
@@ -9,7 +9,7 @@ use winit::window::Window;
 
 use crate::{
     config::Config,
-    gameboy::{AGBRevision, CGBRevision, GBRevision, SGBRevision},
+    gameboy::{AGBRevision, CGBRevision, GBRevision, GameBoy, SGBRevision, TilePalette},
 };
 
 pub struct UserInterface {
@@ -17,6 +17,9 @@ pub struct UserInterface {
     file_submenu: Submenu,
     load_to_console_submenu_abridged: Submenu,
     load_to_console_submenu_full: Submenu,
+    state_slot_checkmenuitems: Vec<CheckMenuItem>,
+    tile_palette_checkmenuitems: Vec<(TilePalette, CheckMenuItem)>,
+    shade_palette_checkmenuitems: Vec<(String, CheckMenuItem)>,
 }
 
 impl UserInterface {
@@ -42,7 +45,7 @@ impl UserInterface {
             true,
             &[
                 &MenuItem::with_id("load_gb", "Game Boy...", true, None),
-                &MenuItem::with_id("load_sgb", "Super Game Boy...", false, None),
+                &MenuItem::with_id("load_sgb", "Super Game Boy...", true, None),
                 &MenuItem::with_id("load_gbc", "Game Boy Color...", true, None),
                 &MenuItem::with_id("load_gba", "Game Boy Advance...", true, None),
                 &toggle_revisions_checkmenuitem,
@@ -57,8 +60,8 @@ impl UserInterface {
                 &MenuItem::with_id("load_gb_dmg0", "Game Boy (DMG0)...", true, None),
                 &MenuItem::with_id("load_gb_dmg", "Game Boy (DMG)...", true, None),
                 &MenuItem::with_id("load_gb_mgb", "Game Boy Pocket...", true, None),
-                &MenuItem::with_id("load_sgb_sgb", "Super Game Boy...", false, None),
-                &MenuItem::with_id("load_sgb_sgb2", "Super Game Boy 2...", false, None),
+                &MenuItem::with_id("load_sgb_sgb", "Super Game Boy...", true, None),
+                &MenuItem::with_id("load_sgb_sgb2", "Super Game Boy 2...", true, None),
                 &MenuItem::with_id("load_gbc_cgb0", "Game Boy Color (CGB0)...", true, None),
                 &MenuItem::with_id("load_gbc_cgb", "Game Boy Color (CGB)...", true, None),
                 &MenuItem::with_id("load_gba_agb0", "Game Boy Advance (AGB0)...", true, None),
@@ -68,15 +71,37 @@ impl UserInterface {
         )
         .unwrap();
 
+        let state_slot_checkmenuitems: Vec<CheckMenuItem> = (0..GameBoy::SAVE_STATE_SLOTS)
+            .map(|slot| CheckMenuItem::with_id(format!("state_slot_{}", slot), format!("Slot {}", slot), true, slot == 0, None))
+            .collect();
+        let state_slot_submenu = Submenu::new("State Slot", true);
+        for checkmenuitem in &state_slot_checkmenuitems {
+            state_slot_submenu.append(checkmenuitem).unwrap();
+        }
+
         let file_submenu = Submenu::with_items(
             "File",
             true,
             &[
                 &MenuItem::with_id("load_rom", "&Load ROM...", true, None),
                 &load_to_console_submenu_abridged,
+                &MenuItem::with_id("cartridge_info", "Cartridge &Info...", true, None),
                 &PredefinedMenuItem::separator(),
-                &MenuItem::new("Save State", true, Some(Accelerator::new(Some(Modifiers::CONTROL), Code::KeyS))),
-                &MenuItem::new("Load State", true, None),
+                &MenuItem::with_id("save_state", "Save State", true, Some(Accelerator::new(Some(Modifiers::CONTROL), Code::KeyS))),
+                &MenuItem::with_id("load_state", "Load State", true, None),
+                &state_slot_submenu,
+                &PredefinedMenuItem::separator(),
+                &Submenu::with_items(
+                    "Movie",
+                    true,
+                    &[
+                        &MenuItem::with_id("record_movie_power_on", "Record from Power-On", true, None),
+                        &MenuItem::with_id("record_movie_here", "Record from Here", true, None),
+                        &MenuItem::with_id("play_movie", "Play Movie...", true, None),
+                        &MenuItem::with_id("stop_movie", "Stop Movie", true, None),
+                    ],
+                )
+                .unwrap(),
                 &PredefinedMenuItem::separator(),
                 &MenuItem::new("Reset", true, None),
                 &MenuItem::with_id("stop_emulation", "Stop", true, None),
@@ -93,10 +118,21 @@ impl UserInterface {
         )
         .unwrap();
 
+        let shade_palette_checkmenuitems: Vec<(String, CheckMenuItem)> = config.gb.palettes.list().into_iter()
+            .map(|(name, label)| {
+                let checkmenuitem = CheckMenuItem::with_id(format!("shade_palette_{}", name), label, true, name == config.gb.palettes.selected, None);
+                (name, checkmenuitem)
+            })
+            .collect();
+        let shade_palette_submenu = Submenu::new("DMG Palette", true);
+        for (_, checkmenuitem) in &shade_palette_checkmenuitems {
+            shade_palette_submenu.append(checkmenuitem).unwrap();
+        }
+
         let gameboy_submenu = Submenu::with_items(
             "Game Boy",
             true,
-            &[&Submenu::with_items(
+            &[&shade_palette_submenu, &Submenu::with_items(
                 "Default Models",
                 true,
                 &[
@@ -123,7 +159,31 @@ impl UserInterface {
         )
         .unwrap();
 
-        menu.append_items(&[&about_submenu, &file_submenu, &gameboy_submenu]).unwrap();
+        let tile_palette_checkmenuitems: Vec<(TilePalette, CheckMenuItem)> = [(TilePalette::Grayscale, "tile_palette_gray".to_string(), "Grayscale".to_string())].into_iter()
+            .chain((0..8).map(|index| (TilePalette::Background(index), format!("tile_palette_bg_{}", index), format!("BG {}", index))))
+            .chain((0..8).map(|index| (TilePalette::Object(index), format!("tile_palette_obj_{}", index), format!("OBJ {}", index))))
+            .map(|(palette, id, text)| (palette, CheckMenuItem::with_id(id, text, true, palette == TilePalette::Background(0), None)))
+            .collect();
+        let tile_palette_submenu = Submenu::new("Tile Palette", true);
+        for (_, checkmenuitem) in &tile_palette_checkmenuitems {
+            tile_palette_submenu.append(checkmenuitem).unwrap();
+        }
+
+        let debug_submenu = Submenu::with_items(
+            "Debug",
+            true,
+            &[
+                &MenuItem::with_id("view_tiles", "VRAM Tiles", true, None),
+                &MenuItem::with_id("view_tilemaps", "Tile Maps", true, None),
+                &MenuItem::with_id("view_objects", "OAM", true, None),
+                &MenuItem::with_id("view_palettes", "Palettes", true, None),
+                &PredefinedMenuItem::separator(),
+                &tile_palette_submenu,
+            ],
+        )
+        .unwrap();
+
+        menu.append_items(&[&about_submenu, &file_submenu, &gameboy_submenu, &debug_submenu]).unwrap();
 
         apply_to_window(&menu, window);
 
@@ -132,6 +192,37 @@ impl UserInterface {
             file_submenu,
             load_to_console_submenu_abridged,
             load_to_console_submenu_full,
+            state_slot_checkmenuitems,
+            tile_palette_checkmenuitems,
+            shade_palette_checkmenuitems,
+        }
+    }
+
+    pub fn select_state_slot(&self, slot: u8) {
+        for (index, checkmenuitem) in self.state_slot_checkmenuitems.iter().enumerate() {
+            checkmenuitem.set_checked(index == slot as usize);
+        }
+    }
+
+    /// Returns the tile viewer palette selected by a menu item, if that's what the item is for.
+    pub fn get_tile_palette(&self, id: &str) -> Option<TilePalette> {
+        self.tile_palette_checkmenuitems.iter().find(|(_, checkmenuitem)| checkmenuitem.id().0 == id).map(|(palette, _)| *palette)
+    }
+
+    pub fn select_tile_palette(&self, palette: TilePalette) {
+        for (item_palette, checkmenuitem) in &self.tile_palette_checkmenuitems {
+            checkmenuitem.set_checked(*item_palette == palette);
+        }
+    }
+
+    /// Returns the name of the DMG palette selected by a menu item, if that's what the item is for.
+    pub fn get_shade_palette(&self, id: &str) -> Option<String> {
+        self.shade_palette_checkmenuitems.iter().find(|(_, checkmenuitem)| checkmenuitem.id().0 == id).map(|(name, _)| name.clone())
+    }
+
+    pub fn select_shade_palette(&self, name: &str) {
+        for (item_name, checkmenuitem) in &self.shade_palette_checkmenuitems {
+            checkmenuitem.set_checked(item_name == name);
         }
     }
 
@@ -1,11 +1,11 @@
 use std::path::PathBuf;
-use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
 
 use muda::MenuEvent;
 use muda::accelerator::{Accelerator, Code, Modifiers};
 use winit::application::ApplicationHandler;
-use winit::event::{ElementState, WindowEvent};
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy};
 use winit::keyboard::{KeyCode, PhysicalKey};
 #[cfg(target_os = "macos")]
@@ -13,7 +13,7 @@ use winit::platform::macos::WindowAttributesExtMacOS;
 use winit::window::{Window, WindowId};
 
 use crate::audio::Audio;
-use crate::common::emulator::{self, EmuMessage};
+use crate::common::emulator::{self, EmuHandle, EmuMessage};
 use crate::common::errors::HydraIOError;
 use crate::config::Config;
 use crate::gameboy;
@@ -22,6 +22,7 @@ use crate::graphics::Graphics;
 use crate::ui::UserInterface;
 
 const GB_FILE_FILTER: (&str, &[&str]) = ("Game Boy (Color)", &["gb", "gbc"]);
+const GB_MOVIE_FILTER: (&str, &[&str]) = ("Game Boy Movie", &["gbm"]);
 
 pub struct HydraApp {
     config: Config,
@@ -32,7 +33,11 @@ pub struct HydraApp {
     ui: Option<UserInterface>,
     proxy: EventLoopProxy<UserEvent>,
 
-    emulator: Option<Sender<EmuMessage>>,
+    emulator: Option<EmuHandle>,
+    rom_path: Option<PathBuf>,
+    state_slot: u8,
+    viewer_windows: Vec<ViewerWindow>,
+    tile_palette: gameboy::TilePalette,
 
     _temp_counter: u64,
     _temp_time: std::time::Instant,
@@ -50,6 +55,10 @@ impl HydraApp {
             proxy,
 
             emulator: None, // Initialized when opening a ROM
+            rom_path: None, // Initialized when opening a ROM
+            state_slot: 0,
+            viewer_windows: Vec::new(),
+            tile_palette: gameboy::TilePalette::Background(0),
 
             _temp_counter: 0,
             _temp_time: std::time::Instant::now(),
@@ -90,33 +99,106 @@ impl HydraApp {
 
     fn try_init_emulator<F>(&mut self, filters: &[(&str, &[&str])], func: F)
     where
-        F: Fn(&PathBuf, &HydraApp) -> Result<Sender<EmuMessage>, HydraIOError>,
+        F: Fn(&PathBuf, &HydraApp) -> Result<EmuHandle, HydraIOError>,
     {
         println!("Loading ROM.");
         let file_dialog = filters.iter().fold(rfd::FileDialog::new(), |a, elem| a.add_filter(elem.0, elem.1));
-        match file_dialog.pick_file() {
-            Some(path) => match func(&path, &self) {
-                // If a file was selected, try to initialize Emulator
-                Ok(emu) => {
-                    // If Emulator construction succeeds, close current emulator (if any) and save communication channel to app state
-                    if let Some(emu_old) = self.emulator.take() {
-                        emu_old.send(EmuMessage::Stop);
+        let Some(path) = file_dialog.pick_file() else {
+            return; // No file selected -- do nothing
+        };
+
+        // Stop the current emulator first, so its save file is fully written before the new one reads it, and only one of them ever writes it
+        let previous_path = self.emulator.take().and_then(|emu_old| {
+            emu_old.stop();
+            self.rom_path.take()
+        });
+
+        match func(&path, &self) {
+            Ok(emu) => {
+                println!("Successfully loaded {}. Launching emulator.", path.file_name().unwrap().display());
+                self.launch_emulator(emu, path);
+            }
+            Err(e) => {
+                // If Emulator construction fails, show an error message
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .set_title("Error Initializing Emulator")
+                    .set_description(e.to_string())
+                    .show();
+                // Then bring back the game that was running, from the save file it just wrote
+                if let Some(previous_path) = previous_path {
+                    match func(&previous_path, &self) {
+                        Ok(emu) => self.launch_emulator(emu, previous_path),
+                        Err(e) => println!("Unable to relaunch {}: {}", previous_path.display(), e),
                     }
-                    println!("Successfully loaded {}. Launching emulator.", path.file_name().unwrap().display());
-                    self.emulator = Some(emu);
-                }
-                Err(e) => {
-                    // If Emulator construction fails, show an error message
-                    rfd::MessageDialog::new()
-                        .set_level(rfd::MessageLevel::Error)
-                        .set_buttons(rfd::MessageButtons::Ok)
-                        .set_title("Error Initializing Emulator")
-                        .set_description(e.to_string())
-                        .show();
                 }
+            }
+        };
+    }
+
+    /// Makes a newly constructed emulator the current one, carrying any open debug views over to it.
+    fn launch_emulator(&mut self, emu: EmuHandle, path: PathBuf) {
+        emu.send(EmuMessage::SetTilePalette(self.tile_palette));
+        for viewer_window in &self.viewer_windows {
+            emu.send(EmuMessage::OpenViewer(viewer_window.viewer));
+        }
+        self.emulator = Some(emu);
+        self.rom_path = Some(path);
+    }
+
+    /// Shows everything in a cartridge's header, along with any problems found with it.
+    /// Describes the running ROM if there is one, and otherwise asks for a file.
+    fn show_cartridge_info(&self) {
+        let Some(path) = self.rom_path.clone().or_else(|| rfd::FileDialog::new().add_filter(GB_FILE_FILTER.0, GB_FILE_FILTER.1).pick_file()) else {
+            return; // No file selected -- do nothing
+        };
+
+        let (level, description) = match std::fs::read(&path).map_err(HydraIOError::from).and_then(|rom| gameboy::CartridgeHeader::parse(&rom)) {
+            Ok(header) => match header.get_problems().as_slice() {
+                [] => (rfd::MessageLevel::Info, header.to_string()),
+                problems => (rfd::MessageLevel::Warning, format!("{}\n\nProblems:\n{}", header, problems.join("\n"))),
             },
-            None => {} // No file selected -- do nothing
+            Err(e) => (rfd::MessageLevel::Error, e.to_string()),
         };
+        rfd::MessageDialog::new()
+            .set_level(level)
+            .set_buttons(rfd::MessageButtons::Ok)
+            .set_title(format!("Cartridge Info - {}", path.file_name().unwrap_or_default().display()))
+            .set_description(description)
+            .show();
+    }
+
+    /// Opens a window showing one of the emulator's debug views, or focuses it if it's already open.
+    fn open_viewer(&mut self, event_loop: &ActiveEventLoop, viewer: gameboy::Viewer) {
+        if let Some(viewer_window) = self.viewer_windows.iter().find(|viewer_window| viewer_window.viewer == viewer) {
+            viewer_window.window.focus_window();
+            return;
+        }
+        let title = match viewer {
+            gameboy::Viewer::Tiles => "VRAM Tiles",
+            gameboy::Viewer::Tilemaps => "Tile Maps",
+            gameboy::Viewer::Objects => "OAM",
+            gameboy::Viewer::Palettes => "Palettes",
+        };
+        let window_attributes = Window::default_attributes().with_title(format!("Hydra - {}", title));
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        let graphics = futures::executor::block_on(Graphics::new(Arc::clone(&window)));
+        self.viewer_windows.push(ViewerWindow { viewer, window, graphics, size: (0, 0), cursor: None });
+        if let Some(emu) = &self.emulator {
+            emu.send(EmuMessage::OpenViewer(viewer));
+        }
+    }
+
+    fn present_viewer(&mut self, viewer: gameboy::Viewer, image: gameboy::ViewerImage) {
+        // The window may have been closed since the emulator rendered this
+        let Some(viewer_window) = self.viewer_windows.iter_mut().find(|viewer_window| viewer_window.viewer == viewer) else {return};
+        if viewer_window.size != (image.width, image.height) {
+            viewer_window.size = (image.width, image.height);
+            viewer_window.graphics.init_emulator(image.width, image.height);
+        }
+        viewer_window.graphics.update_screen_texture(&image.pixels);
+        viewer_window.window.request_redraw();
     }
 
     fn try_init_generic(&mut self) {
@@ -145,6 +227,36 @@ impl ApplicationHandler<UserEvent> for HydraApp {
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
+        if let Some(index) = self.viewer_windows.iter().position(|viewer_window| viewer_window.window.id() == id) {
+            match &event {
+                WindowEvent::CloseRequested => {
+                    let viewer_window = self.viewer_windows.remove(index);
+                    if let Some(emu) = &self.emulator {
+                        emu.send(EmuMessage::CloseViewer(viewer_window.viewer));
+                    }
+                    return;
+                }
+                WindowEvent::RedrawRequested => return self.viewer_windows[index].graphics.render(),
+                WindowEvent::Resized(_) => return self.viewer_windows[index].graphics.resize(),
+                WindowEvent::CursorMoved { position, .. } => self.viewer_windows[index].cursor = Some(*position),
+                WindowEvent::CursorLeft { .. } => self.viewer_windows[index].cursor = None,
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let viewer_window = &self.viewer_windows[index];
+                    let delta = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y.signum() as i32,
+                        MouseScrollDelta::PixelDelta(position) => position.y.signum() as i32,
+                    };
+                    if let Some(emu) = &self.emulator
+                        && let Some((x, y)) = viewer_window.get_cursor_pixel()
+                    {
+                        emu.send(EmuMessage::ScrollViewer { viewer: viewer_window.viewer, x, y, delta });
+                    }
+                    return;
+                }
+                _ => {} // Anything else (e.g. keyboard input) is handled as if it were sent to the main window
+            }
+        }
+
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
@@ -195,21 +307,83 @@ impl ApplicationHandler<UserEvent> for HydraApp {
                     "load_gba" => self.try_init_gameboy(gameboy::Model::GameBoyAdvance(self.config.gb.default_models.agb)),
                     "load_gba_agb0" => self.try_init_gameboy(gameboy::Model::GameBoyAdvance(gameboy::AGBRevision::AGB0)),
                     "load_gba_agb" => self.try_init_gameboy(gameboy::Model::GameBoyAdvance(gameboy::AGBRevision::AGB)),
+                    "cartridge_info" => self.show_cartridge_info(),
                     "toggle_revisions" => self.ui.as_ref().unwrap().toggle_revisions(&mut self.config),
 
+                    "save_state" => {
+                        if let Some(emu) = &self.emulator {
+                            emu.send(EmuMessage::SaveState(self.state_slot));
+                        }
+                    }
+                    "load_state" => {
+                        if let Some(emu) = &self.emulator {
+                            emu.send(EmuMessage::LoadState(self.state_slot));
+                        }
+                    }
+                    "record_movie_power_on" | "record_movie_here" => {
+                        if let Some(emu) = &self.emulator {
+                            emu.send(EmuMessage::RecordMovie { from_power_on: e.id.0 == "record_movie_power_on" });
+                        }
+                    }
+                    "play_movie" => {
+                        if let Some(emu) = &self.emulator
+                            && let Some(path) = rfd::FileDialog::new().add_filter(GB_MOVIE_FILTER.0, GB_MOVIE_FILTER.1).pick_file()
+                        {
+                            emu.send(EmuMessage::PlayMovie(path));
+                        }
+                    }
+                    "stop_movie" => {
+                        if let Some(emu) = &self.emulator {
+                            emu.send(EmuMessage::StopMovie);
+                        }
+                    }
+                    "view_tiles" => self.open_viewer(event_loop, gameboy::Viewer::Tiles),
+                    "view_tilemaps" => self.open_viewer(event_loop, gameboy::Viewer::Tilemaps),
+                    "view_objects" => self.open_viewer(event_loop, gameboy::Viewer::Objects),
+                    "view_palettes" => self.open_viewer(event_loop, gameboy::Viewer::Palettes),
+                    id if let Some(palette) = self.ui.as_ref().unwrap().get_tile_palette(id) => {
+                        self.tile_palette = palette;
+                        self.ui.as_ref().unwrap().select_tile_palette(palette);
+                        if let Some(emu) = &self.emulator {
+                            emu.send(EmuMessage::SetTilePalette(palette));
+                        }
+                    }
+                    id if let Some(name) = self.ui.as_ref().unwrap().get_shade_palette(id) => {
+                        self.ui.as_ref().unwrap().select_shade_palette(&name);
+                        self.config.gb.palettes.selected = name;
+                        if let Some(emu) = &self.emulator {
+                            emu.send(EmuMessage::SetShadePalette(self.config.gb.palettes.get_selected()));
+                        }
+                    }
+                    id if let Some(Ok(slot)) = id.strip_prefix("state_slot_").map(str::parse) => {
+                        self.state_slot = slot;
+                        self.ui.as_ref().unwrap().select_state_slot(slot);
+                    }
+
                     "stop_emulation" => {
-                        self.emulator.as_ref().unwrap().send(EmuMessage::Stop).unwrap();
+                        if let Some(emu) = self.emulator.take() {
+                            emu.stop();
+                        }
+                        self.rom_path = None;
                         self.graphics.as_mut().unwrap().write().unwrap().clear_screen_texture();
                     }
                     _ => {}
                 }
             }
             UserEvent::RedrawRequest => self.window.as_ref().unwrap().request_redraw(),
+            UserEvent::ViewerFrame(viewer, image) => self.present_viewer(viewer, image),
+            UserEvent::DebugCommand(line) => match &self.emulator {
+                Some(emu) => emu.send(EmuMessage::DebugCommand(line)),
+                None => println!("No emulator is running."),
+            },
         }
     }
 
     fn exiting(&mut self, event_loop: &ActiveEventLoop) {
         println!("Thank you for supporting Hydra <3");
+        if let Some(emu) = self.emulator.take() {
+            emu.stop();
+        }
         self.config.write_to_toml();
     }
 }
@@ -217,5 +391,30 @@ impl ApplicationHandler<UserEvent> for HydraApp {
 #[derive(Debug)]
 pub enum UserEvent {
     MenuEvent(MenuEvent),
-    RedrawRequest
+    RedrawRequest,
+    DebugCommand(String),
+    ViewerFrame(gameboy::Viewer, gameboy::ViewerImage),
+}
+
+/// A window showing one of the emulator's debug views.
+struct ViewerWindow {
+    viewer: gameboy::Viewer,
+    window: Arc<Window>,
+    graphics: Graphics,
+    /// The size of the last view shown, which the screen texture is kept at.
+    size: (u32, u32),
+    cursor: Option<PhysicalPosition<f64>>,
+}
+
+impl ViewerWindow {
+    /// Returns the pixel of the view under the cursor, accounting for the view being scaled to fit the window.
+    fn get_cursor_pixel(&self) -> Option<(u32, u32)> {
+        let cursor = self.cursor?;
+        let window_size = self.window.inner_size();
+        let (width, height) = (self.size.0 as f64, self.size.1 as f64);
+        let scale = (window_size.width as f64 / width).min(window_size.height as f64 / height);
+        let x = (cursor.x - (window_size.width as f64 - width * scale) / 2.0) / scale;
+        let y = (cursor.y - (window_size.height as f64 - height * scale) / 2.0) / scale;
+        ((0.0..width).contains(&x) && (0.0..height).contains(&y)).then_some((x as u32, y as u32))
+    }
 }